@@ -0,0 +1,69 @@
+// cargo bench --no-default-features --bench compile
+//
+// one expression evaluated at many points: through `calc`, which tokenizes and
+// parses the text every time, through the parsed tree, and compiled
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use lab2::engine::compile::compile;
+use lab2::engine::env::Env;
+use lab2::engine::eval::calculate_at;
+use lab2::engine::number::Number;
+use lab2::engine::value::Value;
+use lab2::engine::{calc, parse, Outcome};
+
+const SAMPLES: usize = 20_000;
+const EXPRS: [&str; 3] = [
+    "3*x^2 - 2*x + 1",
+    "sin(x)*exp(-x/4) + sqrt(abs(x))",
+    "f(x) + f(x/2)",
+];
+
+fn points() -> impl Iterator<Item = f64> {
+    (0..SAMPLES).map(|i| -10.0 + 20.0 * i as f64 / SAMPLES as f64)
+}
+
+fn time(name: &str, run: impl Fn() -> f64) -> Duration {
+    // one round to warm up
+    black_box(run());
+    let start = Instant::now();
+    black_box(run());
+    let elapsed = start.elapsed();
+    println!("  {name:<10}{:>10.1} ns per evaluation", elapsed.as_nanos() as f64 / SAMPLES as f64);
+    elapsed
+}
+
+fn main() {
+    let mut env = Env::new();
+    calc("f(t) = t^3 - 2*t", &mut env).unwrap();
+
+    for expr in EXPRS {
+        println!("{expr}");
+        let text = time("calc", || {
+            let mut env = env.clone();
+            points()
+                .map(|x| {
+                    env.set("x", Value::Number(Number::Float(x)));
+                    match calc(expr, &mut env) {
+                        Ok(Outcome::Value(value)) => value.number().to_f64(),
+                        _ => f64::NAN,
+                    }
+                })
+                .sum()
+        });
+
+        let tree = parse(expr, &env).unwrap();
+        let tree_time = time("tree", || {
+            points().map(|x| calculate_at(&tree, &env, "x", x).unwrap_or(f64::NAN)).sum()
+        });
+
+        let program = compile(&tree, &env, &["x"]).unwrap();
+        let compiled = time("compiled", || points().map(|x| program.eval(&[x])).sum());
+
+        println!(
+            "  compiled is {:.0}x faster than calc, {:.0}x faster than the tree",
+            text.as_secs_f64() / compiled.as_secs_f64(),
+            tree_time.as_secs_f64() / compiled.as_secs_f64(),
+        );
+    }
+}
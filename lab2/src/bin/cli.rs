@@ -0,0 +1,273 @@
+use std::env;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+
+use lab2::engine::env::Env;
+use lab2::engine::error::CalcError;
+use lab2::engine::format::{Format, Notation};
+use lab2::engine::matrix::format_grid;
+use lab2::engine::number::{NumberMode, WordSize};
+use lab2::engine::value::Value;
+use lab2::engine::{calc, format_solution, Outcome, CONFIG_FILES};
+
+const PROMPT: &str = "> ";
+
+const USAGE: &str = "\
+usage: lab2-cli [-m MODE] [-f FORMAT] [-v] [-e EXPR]...
+
+  -e EXPR   evaluate EXPR, print the result and exit; may be repeated
+  -m MODE   float (default), exact, decimal:DIGITS or programmer:BITS[u]
+  -f FORMAT how to print numbers: auto (default), fixed:DECIMALS, sig:DIGITS,
+            sci:DIGITS or eng:DIGITS, followed by ',group' for thousands
+            separators and ',comma' for a decimal comma, also in input
+  -v        explain every expression: tokens, shunting-yard steps, RPN
+            and the reductions with their values
+  -h        show this help
+
+Without -e an interactive session is started.";
+
+const REPL_HELP: &str = "\
+  :mode MODE  switch to float, exact, decimal:DIGITS or programmer:BITS[u]
+  :format FORMAT
+              print numbers as auto, fixed:DECIMALS, sig:DIGITS, sci:DIGITS
+              or eng:DIGITS, with ',group' and ',comma' as with -f
+  :vars       list variables and functions
+  :help       show this help
+  :quit       leave, as does Ctrl-D";
+
+// $XDG_CONFIG_HOME/lab2, the same place the GTK app reads its config from
+fn config_dir() -> Option<PathBuf> {
+    let base = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    Some(base.join("lab2"))
+}
+
+// `decimal:30`, `programmer:16` or `programmer:8u` for unsigned words
+fn parse_mode(text: &str) -> Result<NumberMode, String> {
+    let (name, arg) = match text.split_once(':') {
+        Some((name, arg)) => (name, Some(arg)),
+        None => (text, None),
+    };
+    match (name, arg) {
+        ("float", None) => Ok(NumberMode::Float),
+        ("exact", None) => Ok(NumberMode::Exact),
+        ("decimal", None) => Ok(NumberMode::Decimal(30)),
+        ("decimal", Some(digits)) => match digits.parse::<u32>() {
+            Ok(digits) if (1..=1000).contains(&digits) => Ok(NumberMode::Decimal(digits)),
+            _ => Err(format!("invalid number of digits '{digits}', expected 1-1000")),
+        },
+        ("programmer", None) => Ok(NumberMode::Programmer(WordSize { bits: 64, signed: true })),
+        ("programmer", Some(word)) => {
+            let (bits, signed) = match word.strip_suffix('u') {
+                Some(bits) => (bits, false),
+                None => (word, true),
+            };
+            match bits.parse::<u32>() {
+                Ok(bits) if [8, 16, 32, 64].contains(&bits) => {
+                    Ok(NumberMode::Programmer(WordSize { bits, signed }))
+                }
+                _ => Err(format!("invalid word size '{word}', expected 8, 16, 32 or 64")),
+            }
+        }
+        _ => Err(format!("unknown mode '{text}'")),
+    }
+}
+
+// `fixed:2`, `sci:4,group` or `auto,group,comma`
+fn parse_format(text: &str) -> Result<Format, String> {
+    let mut parts = text.split(',');
+    let notation = parts.next().unwrap_or("");
+    let (name, arg) = match notation.split_once(':') {
+        Some((name, arg)) => (name, Some(arg)),
+        None => (notation, None),
+    };
+    let digits = |min: u32| match arg.map(str::parse::<u32>) {
+        None => Ok(6),
+        Some(Ok(digits)) if (min..=17).contains(&digits) => Ok(digits),
+        Some(_) => Err(format!("invalid number of digits '{}', expected {min}-17", arg.unwrap_or(""))),
+    };
+    let notation = match (name, arg) {
+        ("auto", None) => Notation::Auto,
+        ("fixed", _) => Notation::Fixed(digits(0)?),
+        ("sig", _) => Notation::Significant(digits(1)?),
+        ("sci", _) => Notation::Scientific(digits(1)?),
+        ("eng", _) => Notation::Engineering(digits(1)?),
+        _ => return Err(format!("unknown format '{notation}'")),
+    };
+
+    let mut format = Format { notation, ..Format::default() };
+    for flag in parts {
+        match flag {
+            "group" => format.grouping = true,
+            "comma" => format.decimal_comma = true,
+            _ => return Err(format!("unknown format option '{flag}'")),
+        }
+    }
+    Ok(format)
+}
+
+fn show_outcome(outcome: &Outcome, format: &Format) -> String {
+    match outcome {
+        Outcome::Value(Value::Matrix(matrix)) => format_grid(&format.cells(matrix)),
+        Outcome::Value(value) => format.value(value),
+        Outcome::Defined(text) | Outcome::Symbolic(text) => text.clone(),
+        Outcome::Solution(roots) => format_solution(roots, format),
+    }
+}
+
+fn show_trace(env: &Env) {
+    if let Some(trace) = env.last_trace().filter(|_| env.trace()) {
+        print!("{trace}");
+    }
+}
+
+// points at the offending part of the line just typed after the prompt
+fn show_error(err: &CalcError) {
+    if let Some(span) = err.span {
+        let width = (span.end - span.start).max(1);
+        println!("{}{}", " ".repeat(PROMPT.len() + span.start), "^".repeat(width));
+    }
+    println!("{err}");
+}
+
+fn show_variables(env: &Env) {
+    for (name, value) in env.variables() {
+        println!("{name} = {}", env.format().value(value));
+    }
+    for (_, func) in env.functions() {
+        println!("{}", func.text);
+    }
+}
+
+fn run_command(command: &str, env: &mut Env) -> bool {
+    let (name, arg) = command.split_once(' ').unwrap_or((command, ""));
+    match name {
+        "mode" => match parse_mode(arg.trim()) {
+            Ok(mode) => env.set_mode(mode),
+            Err(err) => println!("{err}"),
+        },
+        "format" => match parse_format(arg.trim()) {
+            Ok(format) => env.set_format(format),
+            Err(err) => println!("{err}"),
+        },
+        "vars" => show_variables(env),
+        "help" => println!("{REPL_HELP}"),
+        "quit" | "q" => return false,
+        _ => println!("unknown command ':{name}', try :help"),
+    }
+    true
+}
+
+fn repl(env: &mut Env) -> Result<(), ReadlineError> {
+    let mut editor = DefaultEditor::new()?;
+    let history = config_dir().map(|dir| dir.join("repl_history"));
+    if let Some(history) = &history {
+        // a missing file just means a first run
+        let _ = editor.load_history(history);
+    }
+
+    loop {
+        let line = match editor.readline(PROMPT) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) | Err(ReadlineError::Eof) => break,
+            Err(err) => return Err(err),
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        editor.add_history_entry(line)?;
+
+        if let Some(command) = line.strip_prefix(':') {
+            if !run_command(command, env) {
+                break;
+            }
+            continue;
+        }
+        let res = calc(line, env);
+        show_trace(env);
+        match res {
+            Ok(outcome) => println!("{}", show_outcome(&outcome, &env.format())),
+            Err(err) => show_error(&err),
+        }
+    }
+
+    if let Some(history) = &history {
+        if let Some(dir) = history.parent() {
+            let _ = std::fs::create_dir_all(dir);
+        }
+        editor.save_history(history)?;
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let mut env = Env::new();
+    let mut exprs: Vec<String> = Vec::new();
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let res = match arg.as_str() {
+            "-e" => args.next().map(|expr| exprs.push(expr)).ok_or("-e needs an expression".to_string()),
+            "-m" => match args.next() {
+                Some(mode) => parse_mode(&mode).map(|mode| env.set_mode(mode)),
+                None => Err("-m needs a mode".to_string()),
+            },
+            "-f" => match args.next() {
+                Some(format) => parse_format(&format).map(|format| env.set_format(format)),
+                None => Err("-f needs a format".to_string()),
+            },
+            "-v" => {
+                env.set_trace(true);
+                Ok(())
+            }
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return ExitCode::SUCCESS;
+            }
+            _ => Err(format!("unknown argument '{arg}'")),
+        };
+        if let Err(err) = res {
+            eprintln!("{err}\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    }
+
+    for (file, define) in CONFIG_FILES {
+        if let Some(path) = config_dir().map(|dir| dir.join(file)) {
+            if let Ok(text) = std::fs::read_to_string(&path) {
+                if let Err(err) = define(&mut env, &text) {
+                    eprintln!("{}: {err}", path.display());
+                }
+            }
+        }
+    }
+
+    if exprs.is_empty() {
+        return match repl(&mut env) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(err) => {
+                eprintln!("{err}");
+                ExitCode::FAILURE
+            }
+        };
+    }
+
+    for expr in &exprs {
+        let res = calc(expr, &mut env);
+        show_trace(&env);
+        match res {
+            Ok(outcome) => println!("{}", show_outcome(&outcome, &env.format())),
+            Err(err) => {
+                eprintln!("{expr}: {err}");
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    ExitCode::SUCCESS
+}
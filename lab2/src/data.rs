@@ -0,0 +1,75 @@
+use std::rc::Rc;
+use std::cell::RefCell;
+use gtk::{prelude::*, Box, Button, Entry, Label, Orientation, ScrolledWindow, Text, TextView};
+
+use lab2::engine::env::Env;
+use lab2::engine::format::Format;
+use lab2::engine::stats::statistic;
+use lab2::engine::store_data;
+use lab2::engine::value::Value;
+
+// `n = 4, sum = 10, mean = 5/2, ...` in the result format, leaving out what
+// cannot be computed
+fn summary(value: &Value, format: &Format) -> String {
+    let nums = match value {
+        Value::Vector(nums) => nums,
+        _ => return String::new(),
+    };
+    let mut parts = vec![format!("n = {}", nums.len())];
+    for name in ["sum", "mean", "median", "stdev"] {
+        if let Ok(res) = statistic(name, nums) {
+            parts.push(format!("{name} = {}", format.number(&res)));
+        }
+    }
+    parts.join(format.separator())
+}
+
+// data entry: a pasted column of numbers becomes a vector variable for
+// `mean(data)` and the like; `on_store` runs after the variable changes
+pub fn build_data(env: Rc<RefCell<Env>>, on_store: impl Fn() + 'static) -> Box {
+    let text_view = TextView::builder().monospace(true).build();
+    let name_input = Entry::builder().text("data").placeholder_text("variable").build();
+    let store_btn = Button::builder().label("store").build();
+    let summary_text = Label::builder().xalign(0.0).selectable(true).build();
+    let err_text = Text::builder().text("").build();
+
+    {
+        let text_view = text_view.clone();
+        let name_input = name_input.clone();
+        let summary_text = summary_text.clone();
+        let err_text = err_text.clone();
+        store_btn.connect_clicked(move |_| {
+            let buffer = text_view.buffer();
+            let text = buffer.text(&buffer.start_iter(), &buffer.end_iter(), false);
+            let res = store_data(&mut env.borrow_mut(), name_input.text().trim(), &text);
+            match res {
+                Ok(value) => {
+                    err_text.set_text("");
+                    summary_text.set_text(&summary(&value, &env.borrow().format()));
+                    on_store();
+                }
+                Err(err) => err_text.set_text(&err),
+            }
+        });
+    }
+
+    let data_list = ScrolledWindow::builder()
+        .min_content_height(200)
+        .vexpand(true)
+        .child(&text_view)
+        .build();
+
+    let controls = Box::new(Orientation::Horizontal, 5);
+    controls.append(&Label::new(Some("store as")));
+    controls.append(&name_input);
+    controls.append(&store_btn);
+
+    let vbox = Box::new(Orientation::Vertical, 5);
+    vbox.append(&Label::builder().label("Paste a column of numbers, one per line:").xalign(0.0).build());
+    vbox.append(&data_list);
+    vbox.append(&controls);
+    vbox.append(&summary_text);
+    vbox.append(&err_text);
+
+    vbox
+}
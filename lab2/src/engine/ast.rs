@@ -0,0 +1,127 @@
+use std::fmt;
+
+use crate::engine::error::Span;
+use crate::engine::number::Literal;
+use crate::engine::token::{BinOp, PostOp, UnOp, CONDITIONAL_PRIORITY, POSTFIX_PRIORITY, UNARY_PRIORITY};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExprKind {
+    Number(Literal),
+    Var(String),
+    Unary(UnOp, Box<Expr>),
+    Postfix(PostOp, Box<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+    Call(String, Vec<Expr>),
+    // `expr in unit`, the span is that of `in unit`
+    Convert(Box<Expr>, String, Span),
+    // `lhs = rhs` as an argument of `solve` or `nsolve`
+    Equation(Box<Expr>, Box<Expr>),
+    // `cond ? then : otherwise`, also written `if(cond, then, otherwise)`
+    Conditional(Box<Expr>, Box<Expr>, Box<Expr>),
+    // `[a, b, c]`, whose elements are themselves vectors in a matrix
+    Vector(Vec<Expr>),
+}
+
+// `span` covers the whole sub-expression so errors can highlight it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+impl Expr {
+    pub fn new(kind: ExprKind, span: Span) -> Expr {
+        Expr { kind, span }
+    }
+
+    // how tightly the printed form binds, mirroring the parser's priorities
+    fn priority(&self) -> i8 {
+        match &self.kind {
+            ExprKind::Binary(op, _, _) => op.priority(),
+            ExprKind::Unary(_, _) => UNARY_PRIORITY,
+            ExprKind::Postfix(_, _) => POSTFIX_PRIORITY,
+            ExprKind::Conditional(_, _, _) => CONDITIONAL_PRIORITY,
+            ExprKind::Convert(_, _, _) => 0,
+            ExprKind::Equation(_, _) => -1,
+            _ => i8::MAX,
+        }
+    }
+
+    fn fmt_operand(&self, f: &mut fmt::Formatter, parens: bool) -> fmt::Result {
+        if parens {
+            write!(f, "({self})")
+        } else {
+            write!(f, "{self}")
+        }
+    }
+}
+
+fn fmt_list(f: &mut fmt::Formatter, items: &[Expr]) -> fmt::Result {
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{item}")?;
+    }
+    Ok(())
+}
+
+// prints the expression back with only the parentheses it needs
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.kind {
+            ExprKind::Number(literal) => match &literal.unit {
+                Some(unit) => write!(f, "{} {unit}", literal.text),
+                None => write!(f, "{}", literal.text),
+            },
+            ExprKind::Var(name) => write!(f, "{name}"),
+            ExprKind::Unary(op, arg) => {
+                write!(f, "{}", op.symbol())?;
+                // `-a*b` reads the same either way round, `!a*b` does not
+                let product = matches!(op, UnOp::Plus | UnOp::Minus)
+                    && matches!(arg.kind, ExprKind::Binary(BinOp::Mul | BinOp::Div, _, _));
+                arg.fmt_operand(f, !product && arg.priority() < BinOp::Pow.priority())
+            }
+            ExprKind::Postfix(op, arg) => {
+                arg.fmt_operand(f, arg.priority() < POSTFIX_PRIORITY)?;
+                write!(f, "{}", op.symbol())
+            }
+            ExprKind::Binary(op, lhs, rhs) => {
+                let priority = op.priority();
+                let right = op.is_right_associative();
+                lhs.fmt_operand(f, lhs.priority() < priority || (right && lhs.priority() == priority))?;
+                match op {
+                    BinOp::Mul | BinOp::Div | BinOp::Pow => write!(f, "{}", op.symbol())?,
+                    _ => write!(f, " {} ", op.symbol())?,
+                }
+                rhs.fmt_operand(f, rhs.priority() < priority || (!right && rhs.priority() == priority))
+            }
+            ExprKind::Call(name, args) => {
+                write!(f, "{name}(")?;
+                fmt_list(f, args)?;
+                write!(f, ")")
+            }
+            ExprKind::Vector(items) => {
+                write!(f, "[")?;
+                fmt_list(f, items)?;
+                write!(f, "]")
+            }
+            ExprKind::Convert(value, unit, _) => write!(f, "{value} in {unit}"),
+            ExprKind::Equation(lhs, rhs) => write!(f, "{lhs} = {rhs}"),
+            ExprKind::Conditional(cond, then, otherwise) => {
+                cond.fmt_operand(f, cond.priority() <= CONDITIONAL_PRIORITY)?;
+                write!(f, " ? ")?;
+                then.fmt_operand(f, then.priority() <= CONDITIONAL_PRIORITY)?;
+                write!(f, " : ")?;
+                otherwise.fmt_operand(f, otherwise.priority() < CONDITIONAL_PRIORITY)
+            }
+        }
+    }
+}
+
+// the left-hand side of `=`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Target {
+    Var(String),
+    Func(String, Vec<String>),
+}
@@ -0,0 +1,349 @@
+use crate::engine::ast::{Expr, ExprKind};
+use crate::engine::env::Env;
+use crate::engine::error::CalcError;
+use crate::engine::functions::{find_builtin, find_constant, gamma, is_array_function, is_special_form};
+use crate::engine::number::{Number, NumberMode};
+use crate::engine::symbolic::differentiate;
+use crate::engine::token::{BinOp, PostOp, UnOp};
+use crate::engine::value::Value;
+
+// how deeply user functions are inlined; a deeper nesting is taken to be recursion
+static MAX_INLINE_DEPTH: usize = 32;
+
+#[derive(Debug, Clone, Copy)]
+enum Op {
+    Const(f64),
+    Load(usize),
+    // pops the top of the stack into a slot, used for the arguments of
+    // inlined user functions
+    Store(usize),
+    Neg,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+    IntDiv,
+    Mod,
+    // `a + b%` and `a - b%`, with `b%` already divided by 100
+    AddShare,
+    SubShare,
+    // comparisons and logic give 1 for true and 0 for false
+    Less,
+    LessEq,
+    Greater,
+    GreaterEq,
+    Equal,
+    NotEqual,
+    And,
+    Or,
+    Not,
+    // pops the condition and both values; unlike the evaluator it computes
+    // both branches
+    Select,
+    Call(fn(&[f64]) -> f64, usize),
+}
+
+fn from_bool(value: bool) -> f64 {
+    if value {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+// an expression compiled to postfix code over `f64`. The variables given to
+// `compile` are read from the first slots, every other variable is fixed at
+// its value at compile time; results outside the real domain are NaN
+#[derive(Debug, Clone)]
+pub struct Program {
+    code: Vec<Op>,
+    vars: usize,
+    slots: usize,
+    stack: usize,
+}
+
+impl Program {
+    // `args` holds one value per variable, in the order given to `compile`
+    pub fn eval(&self, args: &[f64]) -> f64 {
+        // slots first, the operand stack above them
+        let mut stack: Vec<f64> = Vec::with_capacity(self.slots + self.stack);
+        stack.extend(args.iter().copied().take(self.vars));
+        stack.resize(self.slots, 0.0);
+
+        for op in &self.code {
+            match *op {
+                Op::Const(value) => stack.push(value),
+                Op::Load(slot) => stack.push(stack[slot]),
+                Op::Store(slot) => stack[slot] = stack.pop().unwrap(),
+                Op::Neg => {
+                    let top = stack.last_mut().unwrap();
+                    *top = -*top;
+                }
+                Op::Not => {
+                    let top = stack.last_mut().unwrap();
+                    *top = from_bool(*top == 0.0);
+                }
+                Op::Select => {
+                    let otherwise = stack.pop().unwrap();
+                    let then = stack.pop().unwrap();
+                    let cond = stack.last_mut().unwrap();
+                    *cond = if cond.is_nan() {
+                        f64::NAN
+                    } else if *cond != 0.0 {
+                        then
+                    } else {
+                        otherwise
+                    };
+                }
+                Op::Call(func, argc) => {
+                    let start = stack.len() - argc;
+                    let res = func(&stack[start..]);
+                    stack.truncate(start);
+                    stack.push(res);
+                }
+                op => {
+                    let rhs = stack.pop().unwrap();
+                    let lhs = stack.last_mut().unwrap();
+                    *lhs = match op {
+                        Op::Add => *lhs + rhs,
+                        Op::Sub => *lhs - rhs,
+                        Op::Mul => *lhs * rhs,
+                        Op::Div => *lhs / rhs,
+                        Op::Pow => lhs.powf(rhs),
+                        Op::IntDiv => (*lhs / rhs).floor(),
+                        Op::AddShare => *lhs + *lhs * rhs,
+                        Op::SubShare => *lhs - *lhs * rhs,
+                        Op::Less => from_bool(*lhs < rhs),
+                        Op::LessEq => from_bool(*lhs <= rhs),
+                        Op::Greater => from_bool(*lhs > rhs),
+                        Op::GreaterEq => from_bool(*lhs >= rhs),
+                        Op::Equal => from_bool(*lhs == rhs),
+                        Op::NotEqual => from_bool(*lhs != rhs),
+                        Op::And => from_bool(*lhs != 0.0 && rhs != 0.0),
+                        Op::Or => from_bool(*lhs != 0.0 || rhs != 0.0),
+                        // the sign of the divisor, as `Number::modulo`
+                        _ => *lhs - rhs * (*lhs / rhs).floor(),
+                    };
+                }
+            }
+        }
+        stack.pop().unwrap_or(f64::NAN)
+    }
+}
+
+struct Compiler<'a> {
+    env: &'a Env,
+    // values of variables that are neither compiled variables nor parameters
+    lookup: &'a dyn Fn(&str) -> Option<Value>,
+    code: Vec<Op>,
+    slots: usize,
+    depth: usize,
+    max_depth: usize,
+    // the user functions being inlined, innermost last
+    inlining: Vec<String>,
+}
+
+fn unsupported(what: &str, expr: &Expr) -> CalcError {
+    CalcError::calculate(format!("{what} cannot be compiled"), expr.span)
+}
+
+impl Compiler<'_> {
+    fn push(&mut self, op: Op) {
+        self.code.push(op);
+        let (pops, pushes) = match op {
+            Op::Const(_) | Op::Load(_) => (0, 1),
+            Op::Store(_) => (1, 0),
+            Op::Neg | Op::Not => (1, 1),
+            Op::Select => (3, 1),
+            Op::Call(_, argc) => (argc, 1),
+            _ => (2, 1),
+        };
+        self.depth = self.depth - pops + pushes;
+        self.max_depth = self.max_depth.max(self.depth);
+    }
+
+    // `frame` maps names to slots: the compiled variables at the top level,
+    // the parameters inside an inlined user function
+    fn compile(&mut self, expr: &Expr, frame: &[(&str, usize)], inlined: usize) -> Result<(), CalcError> {
+        match &expr.kind {
+            ExprKind::Number(literal) if literal.unit.is_none() => {
+                // only programmer mode refuses a literal; an imaginary one has no real value
+                let num = Number::from_literal(literal, NumberMode::Float).map_or(f64::NAN, |num| num.to_f64());
+                self.push(Op::Const(num));
+            }
+            ExprKind::Number(_) => return Err(unsupported("a quantity", expr)),
+            ExprKind::Var(name) => self.compile_var(name, frame, inlined, expr)?,
+            ExprKind::Unary(UnOp::Plus, arg) => self.compile(arg, frame, inlined)?,
+            ExprKind::Unary(UnOp::Minus, arg) => {
+                self.compile(arg, frame, inlined)?;
+                self.push(Op::Neg);
+            }
+            ExprKind::Unary(UnOp::Not, _) => return Err(unsupported("'~'", expr)),
+            ExprKind::Unary(UnOp::LogicalNot, arg) => {
+                self.compile(arg, frame, inlined)?;
+                self.push(Op::Not);
+            }
+            ExprKind::Postfix(PostOp::Factorial, arg) => {
+                self.compile(arg, frame, inlined)?;
+                self.push(Op::Call(|a| gamma(a[0] + 1.0), 1));
+            }
+            ExprKind::Postfix(PostOp::Percent, arg) => {
+                self.compile(arg, frame, inlined)?;
+                self.push(Op::Const(100.0));
+                self.push(Op::Div);
+            }
+            ExprKind::Binary(op, lhs, rhs) => {
+                let percent = matches!(rhs.kind, ExprKind::Postfix(PostOp::Percent, _));
+                let op = match op {
+                    BinOp::Add if percent => Op::AddShare,
+                    BinOp::Sub if percent => Op::SubShare,
+                    BinOp::Add => Op::Add,
+                    BinOp::Sub => Op::Sub,
+                    BinOp::Mul | BinOp::ElemMul => Op::Mul,
+                    BinOp::Div | BinOp::ElemDiv => Op::Div,
+                    BinOp::Pow => Op::Pow,
+                    BinOp::IntDiv => Op::IntDiv,
+                    BinOp::Mod => Op::Mod,
+                    BinOp::Less => Op::Less,
+                    BinOp::LessEq => Op::LessEq,
+                    BinOp::Greater => Op::Greater,
+                    BinOp::GreaterEq => Op::GreaterEq,
+                    BinOp::Equal => Op::Equal,
+                    BinOp::NotEqual => Op::NotEqual,
+                    BinOp::And => Op::And,
+                    BinOp::Or => Op::Or,
+                    op => return Err(unsupported(&format!("'{}'", op.symbol()), expr)),
+                };
+                self.compile(lhs, frame, inlined)?;
+                self.compile(rhs, frame, inlined)?;
+                self.push(op);
+            }
+            ExprKind::Conditional(cond, then, otherwise) => {
+                self.compile(cond, frame, inlined)?;
+                self.compile(then, frame, inlined)?;
+                self.compile(otherwise, frame, inlined)?;
+                self.push(Op::Select);
+            }
+            ExprKind::Call(name, args) if name == "diff" => {
+                let derivative = differentiate(expr, args, self.env)?;
+                self.compile(&derivative, frame, inlined)?;
+            }
+            ExprKind::Call(name, _) if is_special_form(name) || is_array_function(name) => {
+                return Err(unsupported(name, expr))
+            }
+            ExprKind::Call(name, args) => self.compile_call(name, args, frame, inlined, expr)?,
+            ExprKind::Convert(_, _, _) => return Err(unsupported("a unit conversion", expr)),
+            ExprKind::Equation(_, _) => return Err(unsupported("an equation", expr)),
+            ExprKind::Vector(_) => return Err(unsupported("a vector", expr)),
+        }
+        Ok(())
+    }
+
+    fn compile_var(
+        &mut self,
+        name: &str,
+        frame: &[(&str, usize)],
+        inlined: usize,
+        expr: &Expr,
+    ) -> Result<(), CalcError> {
+        if let Some((_, slot)) = frame.iter().find(|(local, _)| *local == name) {
+            self.push(Op::Load(*slot));
+            return Ok(());
+        }
+        // the body of a user function only sees globals besides its parameters
+        let value = if inlined == 0 { (self.lookup)(name) } else { self.env.get(name).cloned() };
+        if let (None, Some(constant)) = (&value, self.env.constant(name)) {
+            if inlined >= MAX_INLINE_DEPTH {
+                return Err(unsupported(&format!("'{name}'"), expr));
+            }
+            return self.compile(constant, &[], inlined + 1);
+        }
+        let value = value.or_else(|| find_constant(name).map(Value::from));
+        match value.map(|value| value.settle()) {
+            Some(Value::Number(num)) if !num.is_complex() => {
+                self.push(Op::Const(num.to_f64()));
+                Ok(())
+            }
+            Some(_) => Err(unsupported(&format!("'{name}'"), expr)),
+            None => Err(CalcError::calculate(format!("undefined variable '{name}'"), expr.span)),
+        }
+    }
+
+    fn compile_call(
+        &mut self,
+        name: &str,
+        args: &[Expr],
+        frame: &[(&str, usize)],
+        inlined: usize,
+        expr: &Expr,
+    ) -> Result<(), CalcError> {
+        if let Some(builtin) = find_builtin(name) {
+            if !builtin.arity.accepts(args.len()) {
+                return Err(CalcError::calculate(
+                    format!("{name} expects {}, got {}", builtin.arity.describe(), args.len()),
+                    expr.span,
+                ));
+            }
+            for arg in args {
+                self.compile(arg, frame, inlined)?;
+            }
+            self.push(Op::Call(builtin.func, args.len()));
+            return Ok(());
+        }
+
+        let func = match self.env.function(name) {
+            Some(func) => func,
+            None => return Err(CalcError::calculate(format!("unknown function '{name}'"), expr.span)),
+        };
+        if func.params.len() != args.len() {
+            return Err(CalcError::calculate(
+                format!("{name} expects {} arguments, got {}", func.params.len(), args.len()),
+                expr.span,
+            ));
+        }
+        // both branches of a conditional are compiled, so recursion would never end
+        if inlined >= MAX_INLINE_DEPTH || self.inlining.iter().any(|outer| outer == name) {
+            return Err(unsupported("a recursive function", expr));
+        }
+
+        // every inlined call gets slots of its own for its arguments
+        let mut params = Vec::with_capacity(args.len());
+        for (param, arg) in func.params.iter().zip(args) {
+            self.compile(arg, frame, inlined)?;
+            params.push((param.as_str(), self.slots));
+            self.push(Op::Store(self.slots));
+            self.slots += 1;
+        }
+        self.inlining.push(name.to_string());
+        let res = self.compile(&func.body, &params, inlined + 1);
+        self.inlining.pop();
+        res
+    }
+}
+
+// compiles `expr` as a function of `vars` with the other variables taken from `env`
+pub fn compile(expr: &Expr, env: &Env, vars: &[&str]) -> Result<Program, CalcError> {
+    compile_with(expr, env, vars, &|name| env.get(name).cloned())
+}
+
+// as `compile`, with `lookup` giving the values of free variables
+pub fn compile_with(
+    expr: &Expr,
+    env: &Env,
+    vars: &[&str],
+    lookup: &dyn Fn(&str) -> Option<Value>,
+) -> Result<Program, CalcError> {
+    let mut compiler = Compiler {
+        env,
+        lookup,
+        code: Vec::new(),
+        slots: vars.len(),
+        depth: 0,
+        max_depth: 0,
+        inlining: Vec::new(),
+    };
+    let frame: Vec<(&str, usize)> = vars.iter().copied().zip(0..).collect();
+    compiler.compile(expr, &frame, 0)?;
+    Ok(Program { code: compiler.code, vars: vars.len(), slots: compiler.slots, stack: compiler.max_depth })
+}
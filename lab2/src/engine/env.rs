@@ -0,0 +1,129 @@
+use std::collections::BTreeMap;
+
+use crate::engine::ast::Expr;
+use crate::engine::format::Format;
+use crate::engine::number::NumberMode;
+use crate::engine::trace::Trace;
+use crate::engine::units::UnitTable;
+use crate::engine::value::Value;
+
+#[derive(Debug, Clone)]
+pub struct UserFunction {
+    pub params: Vec<String>,
+    pub body: Expr,
+    // the definition as the user typed it, for history and the side panel
+    pub text: String,
+}
+
+// evaluation state that outlives a single `calc` call
+#[derive(Debug, Default, Clone)]
+pub struct Env {
+    vars: BTreeMap<String, Value>,
+    funcs: BTreeMap<String, UserFunction>,
+    // constants from the config, evaluated in the mode in effect where used
+    constants: BTreeMap<String, Expr>,
+    mode: NumberMode,
+    // how results are shown, and whether input uses a decimal comma
+    format: Format,
+    units: UnitTable,
+    // every value `calc` has produced, `$1` is the first and `ans` the last
+    results: Vec<Value>,
+    // record how every expression is parsed and reduced
+    trace: bool,
+    last_trace: Option<Trace>,
+}
+
+impl Env {
+    pub fn new() -> Env {
+        Env::default()
+    }
+
+    // a variable, or a previous result as `ans` or `$n`
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        if let Some(value) = self.vars.get(name) {
+            return Some(value);
+        }
+        match name.strip_prefix('$') {
+            Some(index) => self.results.get(index.parse::<usize>().ok()?.checked_sub(1)?),
+            None if name == "ans" => self.results.last(),
+            None => None,
+        }
+    }
+
+    pub fn set(&mut self, name: &str, value: Value) {
+        self.vars.insert(name.to_string(), value);
+    }
+
+    pub fn variables(&self) -> impl Iterator<Item = (&String, &Value)> {
+        self.vars.iter()
+    }
+
+    pub fn results(&self) -> &[Value] {
+        &self.results
+    }
+
+    pub fn push_result(&mut self, value: Value) {
+        self.results.push(value);
+    }
+
+    pub fn function(&self, name: &str) -> Option<&UserFunction> {
+        self.funcs.get(name)
+    }
+
+    pub fn define(&mut self, name: &str, func: UserFunction) {
+        self.funcs.insert(name.to_string(), func);
+    }
+
+    pub fn functions(&self) -> impl Iterator<Item = (&String, &UserFunction)> {
+        self.funcs.iter()
+    }
+
+    pub fn constant(&self, name: &str) -> Option<&Expr> {
+        self.constants.get(name)
+    }
+
+    pub fn define_constant(&mut self, name: &str, expr: Expr) {
+        self.constants.insert(name.to_string(), expr);
+    }
+
+    pub fn mode(&self) -> NumberMode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: NumberMode) {
+        self.mode = mode;
+    }
+
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    pub fn set_format(&mut self, format: Format) {
+        self.format = format;
+    }
+
+    pub fn trace(&self) -> bool {
+        self.trace
+    }
+
+    pub fn set_trace(&mut self, trace: bool) {
+        self.trace = trace;
+    }
+
+    // the trace of the last `calc`, also when it failed
+    pub fn last_trace(&self) -> Option<&Trace> {
+        self.last_trace.as_ref()
+    }
+
+    pub fn set_last_trace(&mut self, trace: Trace) {
+        self.last_trace = Some(trace);
+    }
+
+    pub fn units(&self) -> &UnitTable {
+        &self.units
+    }
+
+    pub fn units_mut(&mut self) -> &mut UnitTable {
+        &mut self.units
+    }
+}
@@ -0,0 +1,73 @@
+use std::fmt;
+
+// character positions in the source expression, `end` is exclusive
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Span {
+        Span { start, end }
+    }
+
+    pub fn join(self, other: Span) -> Span {
+        Span::new(self.start.min(other.start), self.end.max(other.end))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Parse,
+    Rpn,
+    Calculate,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CalcError {
+    pub stage: Stage,
+    pub message: String,
+    pub span: Option<Span>,
+}
+
+impl CalcError {
+    pub fn new(stage: Stage, message: impl Into<String>, span: Option<Span>) -> CalcError {
+        CalcError { stage, message: message.into(), span }
+    }
+
+    pub fn parse(message: impl Into<String>, span: Span) -> CalcError {
+        CalcError::new(Stage::Parse, message, Some(span))
+    }
+
+    pub fn rpn(message: impl Into<String>, span: Span) -> CalcError {
+        CalcError::new(Stage::Rpn, message, Some(span))
+    }
+
+    pub fn calculate(message: impl Into<String>, span: Span) -> CalcError {
+        CalcError::new(Stage::Calculate, message, Some(span))
+    }
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Stage::Parse => write!(f, "Parse"),
+            Stage::Rpn => write!(f, "RPN"),
+            Stage::Calculate => write!(f, "Calculate"),
+        }
+    }
+}
+
+impl fmt::Display for CalcError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} Error: {}", self.stage, self.message)?;
+        match self.span {
+            Some(span) if span.end > span.start + 1 => {
+                write!(f, " (columns {}-{})", span.start + 1, span.end)
+            }
+            Some(span) => write!(f, " (column {})", span.start + 1),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,581 @@
+use std::cell::Cell;
+
+use num_complex::Complex64;
+use num_rational::BigRational;
+
+use crate::engine::ast::{Expr, ExprKind};
+use crate::engine::compile::compile_with;
+use crate::engine::env::Env;
+use crate::engine::error::{CalcError, Span};
+use crate::engine::functions::{
+    find_builtin, find_constant, is_array_function, is_list_function, is_matrix_function, is_special_form, Arity,
+};
+use crate::engine::is_result_name;
+use crate::engine::matrix::{cross, dot, Matrix};
+use crate::engine::number::{exact_builtin, Literal, Number};
+use crate::engine::solve::{bisect, newton, newton_system};
+use crate::engine::stats::statistic;
+use crate::engine::symbolic::{derivative, differentiate};
+use crate::engine::token::{BinOp, PostOp, UnOp};
+use crate::engine::value::Value;
+
+// limits for user-defined functions, so a runaway definition fails instead of
+// freezing the UI: nesting depth and the total number of calls per evaluation
+pub static MAX_CALL_DEPTH: usize = 256;
+pub static MAX_CALLS: usize = 100_000;
+
+fn calculate_expr(op: BinOp, oprnd1: &Value, oprnd2: &Value) -> Result<Value, String> {
+    match op {
+        BinOp::Add => oprnd1.add(oprnd2),
+        BinOp::Sub => oprnd1.sub(oprnd2),
+        BinOp::Mul => oprnd1.mul(oprnd2),
+        BinOp::Div => oprnd1.div(oprnd2),
+        BinOp::ElemMul => oprnd1.elementwise_mul(oprnd2),
+        BinOp::ElemDiv => oprnd1.elementwise_div(oprnd2),
+        BinOp::Pow => oprnd1.pow(oprnd2),
+        BinOp::IntDiv => oprnd1.plain(oprnd2, op.symbol(), Number::int_div),
+        BinOp::Mod => oprnd1.plain(oprnd2, op.symbol(), Number::modulo),
+        BinOp::BitAnd => oprnd1.plain(oprnd2, op.symbol(), Number::bit_and),
+        BinOp::BitOr => oprnd1.plain(oprnd2, op.symbol(), Number::bit_or),
+        BinOp::BitXor => oprnd1.plain(oprnd2, op.symbol(), Number::bit_xor),
+        BinOp::Shl => oprnd1.plain(oprnd2, op.symbol(), Number::shift_left),
+        BinOp::Shr => oprnd1.plain(oprnd2, op.symbol(), Number::shift_right),
+        BinOp::Equal => oprnd1.equals(oprnd2).map(Value::Bool),
+        BinOp::NotEqual => oprnd1.equals(oprnd2).map(|equal| Value::Bool(!equal)),
+        BinOp::Less | BinOp::LessEq | BinOp::Greater | BinOp::GreaterEq => {
+            let ordering = oprnd1.compare(oprnd2)?;
+            // NaN is neither smaller nor larger than anything
+            Ok(Value::Bool(ordering.is_some_and(|ordering| match op {
+                BinOp::Less => ordering.is_lt(),
+                BinOp::LessEq => ordering.is_le(),
+                BinOp::Greater => ordering.is_gt(),
+                _ => ordering.is_ge(),
+            })))
+        }
+        BinOp::And => Ok(Value::Bool(oprnd1.truth()? && oprnd2.truth()?)),
+        BinOp::Or => Ok(Value::Bool(oprnd1.truth()? || oprnd2.truth()?)),
+    }
+}
+
+fn calculate_unary_expr(op: UnOp, oprnd: Value) -> Result<Value, String> {
+    match op {
+        UnOp::Plus => Ok(oprnd),
+        UnOp::Minus => Ok(oprnd.neg()),
+        UnOp::Not => oprnd.plain(&oprnd, op.symbol(), |num, _| num.bit_not()),
+        UnOp::LogicalNot => Ok(Value::Bool(!oprnd.truth()?)),
+    }
+}
+
+// the numbers among `values` and inside their vectors and matrices
+fn flatten(name: &str, values: &[Value]) -> Result<Vec<Number>, String> {
+    let mut nums: Vec<Number> = Vec::new();
+    for value in values {
+        match value.settle() {
+            Value::Number(num) => nums.push(num),
+            Value::Vector(items) => nums.extend(items),
+            Value::Matrix(matrix) => nums.extend(matrix.into_data()),
+            value => return Err(format!("{name} expects plain numbers, got {}", value.describe_unit())),
+        }
+    }
+    Ok(nums)
+}
+
+fn call_matrix_function(name: &str, values: &[Value], expr: &Expr) -> Result<Value, CalcError> {
+    let argc = if matches!(name, "dot" | "cross") { 2 } else { 1 };
+    if values.len() != argc {
+        return Err(CalcError::calculate(
+            format!("{name} expects {}, got {}", Arity::Exact(argc).describe(), values.len()),
+            expr.span,
+        ));
+    }
+    let res = match (name, values) {
+        ("det", [Value::Matrix(matrix)]) => matrix.det().map(Value::Number),
+        ("inv", [Value::Matrix(matrix)]) => matrix.inv().map(Value::Matrix),
+        ("transpose", [Value::Matrix(matrix)]) => Ok(Value::Matrix(matrix.transpose())),
+        // a vector already serves as a row or a column
+        ("transpose", [vector @ Value::Vector(_)]) => Ok(vector.clone()),
+        ("dot", [Value::Vector(a), Value::Vector(b)]) => dot(a, b).map(Value::Number),
+        ("cross", [Value::Vector(a), Value::Vector(b)]) => cross(a, b).map(Value::Vector),
+        ("dot" | "cross", _) => Err(format!("{name} expects two vectors")),
+        (_, [value]) => Err(format!("{name} expects a matrix, got {}", value.describe_unit())),
+        _ => unreachable!(),
+    };
+    res.map_err(|err| CalcError::calculate(err, expr.span))
+}
+
+// kept out of `calculate`, whose frame it would grow
+#[inline(never)]
+fn finite(res: Result<Value, CalcError>, expr: &Expr) -> Result<Value, CalcError> {
+    match res {
+        Ok(value) if value.overflows() => Err(CalcError::calculate("overflow", expr.span)),
+        res => res,
+    }
+}
+
+// variable lookup for one evaluation: function parameters shadow globals
+struct Scope<'a> {
+    env: &'a Env,
+    locals: Vec<(&'a str, Value)>,
+    depth: usize,
+    calls: &'a Cell<usize>,
+}
+
+impl<'a> Scope<'a> {
+    fn lookup(&self, name: &str) -> Option<&Value> {
+        match self.locals.iter().find(|(local, _)| *local == name) {
+            Some((_, value)) => Some(value),
+            None => self.env.get(name),
+        }
+    }
+
+    // each kind of node is evaluated in its own method: this keeps the frame of
+    // `calculate` small, which matters for deeply recursive user functions.
+    // A result too large for a float is an error, as `1/0` is, not `inf`
+    fn calculate(&self, expr: &Expr) -> Result<Value, CalcError> {
+        let res = match &expr.kind {
+            ExprKind::Number(literal) => self.calculate_literal(literal, expr),
+            ExprKind::Var(name) => self.calculate_var(name, expr),
+            ExprKind::Unary(op, arg) => calculate_unary_expr(*op, self.calculate(arg)?)
+                .map_err(|err| CalcError::calculate(err, expr.span)),
+            ExprKind::Postfix(op, arg) => self.calculate_postfix(*op, arg, expr),
+            ExprKind::Binary(op @ (BinOp::Add | BinOp::Sub), lhs, rhs)
+                if matches!(rhs.kind, ExprKind::Postfix(PostOp::Percent, _)) =>
+            {
+                self.calculate_share(*op, lhs, rhs, expr)
+            }
+            ExprKind::Binary(op @ (BinOp::And | BinOp::Or), lhs, rhs) => self.calculate_logic(*op, lhs, rhs),
+            ExprKind::Binary(op, lhs, rhs) => self.calculate_binary(*op, lhs, rhs, expr),
+            ExprKind::Conditional(cond, then, otherwise) => self.calculate_conditional(cond, then, otherwise),
+            ExprKind::Vector(items) => self.calculate_vector(items, expr),
+            ExprKind::Convert(value, unit, span) => self.calculate_convert(value, unit, *span, expr),
+            ExprKind::Call(name, args) if is_special_form(name) => self.calculate_special(name, args, expr),
+            ExprKind::Call(name, args) => self.calculate_call(name, args, expr),
+            ExprKind::Equation(_, _) => Err(CalcError::calculate(
+                "an equation can only be an argument of solve or nsolve",
+                expr.span,
+            )),
+        };
+        finite(res, expr)
+    }
+
+    fn calculate_literal(&self, literal: &Literal, expr: &Expr) -> Result<Value, CalcError> {
+        let num = Number::from_literal(literal, self.env.mode()).map_err(|err| CalcError::calculate(err, expr.span))?;
+        match &literal.unit {
+            Some(unit) => {
+                let unit = self.env.units().parse(unit)
+                    .map_err(|err| CalcError::calculate(err, expr.span))?;
+                Ok(Value::quantity(num, unit))
+            }
+            None => Ok(Value::Number(num)),
+        }
+    }
+
+    fn calculate_var(&self, name: &str, expr: &Expr) -> Result<Value, CalcError> {
+        match self.lookup(name) {
+            Some(value) => Ok(value.clone()),
+            None if self.env.constant(name).is_some() => calculate(self.env.constant(name).unwrap(), self.env),
+            None if find_constant(name).is_some() => {
+                let num = find_constant(name).unwrap().for_mode(self.env.mode());
+                num.map(Value::Number).map_err(|err| CalcError::calculate(err, expr.span))
+            }
+            None if find_builtin(name).is_some()
+                || is_special_form(name)
+                || is_array_function(name)
+                || self.env.function(name).is_some() =>
+            {
+                Err(CalcError::calculate(
+                    format!("'{name}' is a function, call it as {name}(...)"),
+                    expr.span,
+                ))
+            }
+            None if is_result_name(name) => {
+                Err(CalcError::calculate(format!("there is no result {name} yet"), expr.span))
+            }
+            None => Err(CalcError::calculate(format!("undefined variable '{name}'"), expr.span)),
+        }
+    }
+
+    fn calculate_postfix(&self, op: PostOp, arg: &Expr, expr: &Expr) -> Result<Value, CalcError> {
+        let value = self.calculate(arg)?;
+        let res = match op {
+            PostOp::Factorial => value.plain(&value, op.symbol(), |num, _| num.factorial()),
+            PostOp::Percent => value.div(&Value::Number(Number::Rational(BigRational::from_integer(100.into())))),
+        };
+        res.map_err(|err| CalcError::calculate(err, expr.span))
+    }
+
+    // `200 + 10%` adds 10% of 200
+    fn calculate_share(&self, op: BinOp, lhs: &Expr, rhs: &Expr, expr: &Expr) -> Result<Value, CalcError> {
+        let base = self.calculate(lhs)?;
+        let share = base.mul(&self.calculate(rhs)?).map_err(|err| CalcError::calculate(err, expr.span))?;
+        let res = if op == BinOp::Add { base.add(&share) } else { base.sub(&share) };
+        res.map_err(|err| CalcError::calculate(err, expr.span))
+    }
+
+    // a vector of numbers, or a matrix when every element is a vector
+    fn calculate_vector(&self, items: &[Expr], expr: &Expr) -> Result<Value, CalcError> {
+        let mut values: Vec<Value> = Vec::with_capacity(items.len());
+        for item in items {
+            values.push(self.calculate(item)?.settle());
+        }
+        let error = |message: String| CalcError::calculate(message, expr.span);
+        if values.iter().all(|value| matches!(value, Value::Number(_))) {
+            return Ok(Value::Vector(values.iter().map(|value| value.number().clone()).collect()));
+        }
+        let mut rows: Vec<Vec<Number>> = Vec::with_capacity(values.len());
+        for value in values {
+            match value {
+                Value::Vector(row) => rows.push(row),
+                Value::Quantity(_, unit) => return Err(error(format!("a vector cannot hold quantities such as {unit}"))),
+                _ => return Err(error(String::from("a matrix is written as a vector of rows, [[1, 2], [3, 4]]"))),
+            }
+        }
+        Matrix::from_rows(rows).map(Value::Matrix).map_err(error)
+    }
+
+    // the right side of `&&` and `||` is only evaluated when it decides the result
+    fn calculate_logic(&self, op: BinOp, lhs: &Expr, rhs: &Expr) -> Result<Value, CalcError> {
+        let truth = |arg: &Expr| -> Result<bool, CalcError> {
+            self.calculate(arg)?.truth().map_err(|err| CalcError::calculate(err, arg.span))
+        };
+        let res = if op == BinOp::And { truth(lhs)? && truth(rhs)? } else { truth(lhs)? || truth(rhs)? };
+        Ok(Value::Bool(res))
+    }
+
+    // only the chosen branch is evaluated, so recursive functions can stop
+    fn calculate_conditional(&self, cond: &Expr, then: &Expr, otherwise: &Expr) -> Result<Value, CalcError> {
+        let truth = self.calculate(cond)?.truth().map_err(|err| CalcError::calculate(err, cond.span))?;
+        self.calculate(if truth { then } else { otherwise })
+    }
+
+    fn calculate_binary(&self, op: BinOp, lhs: &Expr, rhs: &Expr, expr: &Expr) -> Result<Value, CalcError> {
+        let oprnd1 = self.calculate(lhs)?;
+        let oprnd2 = self.calculate(rhs)?;
+        // a zero divisor is the operand to blame, not the whole division
+        let span = match op {
+            BinOp::Div | BinOp::IntDiv | BinOp::Mod if oprnd2.number().is_zero() => rhs.span,
+            _ => expr.span,
+        };
+        calculate_expr(op, &oprnd1, &oprnd2).map_err(|err| CalcError::calculate(err, span))
+    }
+
+    fn calculate_convert(&self, value: &Expr, unit: &str, span: Span, expr: &Expr) -> Result<Value, CalcError> {
+        let value = self.calculate(value)?;
+        let unit = self.env.units().parse(unit).map_err(|err| CalcError::calculate(err, span))?;
+        value.convert(&unit).map_err(|err| CalcError::calculate(err, expr.span))
+    }
+
+    fn calculate_special(&self, name: &str, args: &[Expr], expr: &Expr) -> Result<Value, CalcError> {
+        match name {
+            "diff" => {
+                let derivative = differentiate(expr, args, self.env)?;
+                self.calculate(&derivative)
+            }
+            "solve" => Ok(Value::Number(Number::Float(self.solve(args, expr)?))),
+            _ => match self.nsolve(args, expr)?.as_slice() {
+                [(_, root)] => Ok(Value::Number(Number::Float(*root))),
+                _ => Err(CalcError::calculate(
+                    "a system of equations has several solutions, nsolve(...) has to stand on its own",
+                    expr.span,
+                )),
+            },
+        }
+    }
+
+    // `expr` as a real number with `bindings` shadowing every other variable
+    fn calculate_at(&self, expr: &Expr, bindings: &[(&str, f64)]) -> Result<f64, CalcError> {
+        let locals = bindings
+            .iter()
+            .map(|(name, value)| (*name, Value::Number(Number::Float(*value))))
+            .chain(self.locals.iter().cloned())
+            .collect();
+        let scope = Scope { env: self.env, locals, depth: self.depth, calls: self.calls };
+        match scope.calculate(expr)?.settle() {
+            Value::Number(Number::Complex(_)) => {
+                Err(CalcError::calculate("the equation has a complex value here", expr.span))
+            }
+            value => Ok(value.number().to_f64()),
+        }
+    }
+
+    // `expr` as a function of `vars`: compiled when it can be, otherwise
+    // evaluated through `calculate_at`
+    fn sampler<'b>(&'b self, expr: &'b Expr, vars: &'b [&'b str]) -> impl Fn(&[f64]) -> Result<f64, CalcError> + 'b {
+        let program = compile_with(expr, self.env, vars, &|name| self.lookup(name).cloned()).ok();
+        move |xs: &[f64]| match &program {
+            Some(program) => Ok(program.eval(xs)),
+            None => {
+                let bindings: Vec<(&str, f64)> = vars.iter().copied().zip(xs.iter().copied()).collect();
+                self.calculate_at(expr, &bindings)
+            }
+        }
+    }
+
+    fn real_arg(&self, arg: &Expr) -> Result<f64, CalcError> {
+        match self.calculate(arg)? {
+            Value::Number(num) if !num.is_complex() => Ok(num.to_f64()),
+            _ => Err(CalcError::calculate("expected a real number", arg.span)),
+        }
+    }
+
+    // `solve(f = g, x, x0)` by Newton's method, `solve(f = g, x, a, b)` by bisection
+    fn solve(&self, args: &[Expr], expr: &Expr) -> Result<f64, CalcError> {
+        let (equation, var, points) = match args {
+            [equation, Expr { kind: ExprKind::Var(var), .. }, points @ ..] if matches!(points.len(), 1 | 2) => {
+                (equation, var.as_str(), points)
+            }
+            [_, other, _, ..] if !matches!(other.kind, ExprKind::Var(_)) => {
+                return Err(CalcError::calculate("solve expects a variable name", other.span))
+            }
+            _ => {
+                return Err(CalcError::calculate(
+                    "solve expects an equation, a variable and a starting value or an interval",
+                    expr.span,
+                ))
+            }
+        };
+        let residual = residual(equation);
+        let vars = [var];
+        let sample = self.sampler(&residual, &vars);
+        let f = |x: f64| sample(&[x]);
+
+        let mut values = Vec::with_capacity(points.len());
+        for point in points {
+            values.push(self.real_arg(point)?);
+        }
+        match values[..] {
+            [a, b] => bisect(&f, a, b, var, expr.span),
+            _ => {
+                // a symbolic derivative when there is one, a numeric one otherwise
+                let slope = derivative(&residual, var, self.env, expr.span).ok();
+                let sample_slope = slope.as_ref().map(|slope| self.sampler(slope, &vars));
+                let df = sample_slope.as_ref().map(|sample| move |x: f64| sample(&[x]));
+                let df = df.as_ref().map(|df| df as &dyn Fn(f64) -> Result<f64, CalcError>);
+                newton(&f, df, values[0], var, expr.span)
+            }
+        }
+    }
+
+    // `nsolve(f1 = g1, ..., fn = gn, x1, ..., xn, start1, ..., startn)`
+    fn nsolve(&self, args: &[Expr], expr: &Expr) -> Result<Vec<(String, f64)>, CalcError> {
+        let n = args.len() / 3;
+        if n == 0 || !args.len().is_multiple_of(3) {
+            return Err(CalcError::calculate(
+                "nsolve expects n equations, n variables and n starting values",
+                expr.span,
+            ));
+        }
+        let residuals: Vec<Expr> = args[..n].iter().map(residual).collect();
+        let mut vars: Vec<&str> = Vec::with_capacity(n);
+        for arg in &args[n..2 * n] {
+            match &arg.kind {
+                ExprKind::Var(var) if !vars.contains(&var.as_str()) => vars.push(var),
+                ExprKind::Var(var) => {
+                    return Err(CalcError::calculate(format!("'{var}' is listed twice"), arg.span))
+                }
+                _ => return Err(CalcError::calculate("nsolve expects a variable name", arg.span)),
+            }
+        }
+        let mut start = Vec::with_capacity(n);
+        for arg in &args[2 * n..] {
+            start.push(self.real_arg(arg)?);
+        }
+
+        let samplers: Vec<_> = residuals.iter().map(|residual| self.sampler(residual, &vars)).collect();
+        let f = |xs: &[f64]| samplers.iter().map(|sample| sample(xs)).collect();
+        let roots = newton_system(&f, start, expr.span)?;
+        Ok(vars.iter().map(|var| var.to_string()).zip(roots).collect())
+    }
+
+    fn calculate_call(&self, name: &str, args: &[Expr], expr: &Expr) -> Result<Value, CalcError> {
+        let mut values: Vec<Value> = Vec::with_capacity(args.len());
+        for arg in args {
+            values.push(self.calculate(arg)?);
+        }
+        if find_builtin(name).is_some() {
+            self.call_builtin(name, &values, expr)
+        } else if is_matrix_function(name) {
+            call_matrix_function(name, &values, expr)
+        } else if is_list_function(name) {
+            let values = flatten(name, &values).map_err(|err| CalcError::calculate(err, expr.span))?;
+            statistic(name, &values).map(Value::Number).map_err(|err| CalcError::calculate(err, expr.span))
+        } else {
+            self.call_user_function(name, &values, expr)
+        }
+    }
+
+    fn call_builtin(&self, name: &str, values: &[Value], expr: &Expr) -> Result<Value, CalcError> {
+        let builtin = find_builtin(name).unwrap();
+        if !builtin.arity.accepts(values.len()) {
+            return Err(CalcError::calculate(
+                format!("{name} expects {}, got {}", builtin.arity.describe(), values.len()),
+                expr.span,
+            ));
+        }
+
+        // `min` and `max` take the elements of vectors as further arguments,
+        // functions of one number apply to each element
+        if builtin.arity == Arity::AtLeast(1) && values.iter().any(Value::is_array) {
+            let nums = flatten(name, values).map_err(|err| CalcError::calculate(err, expr.span))?;
+            return Ok(Value::Number(self.call_numeric(name, &nums, expr)?));
+        }
+        if let [array] = values {
+            if let Some(res) = array.try_map(|num| self.call_numeric(name, std::slice::from_ref(num), expr)) {
+                return res;
+            }
+        }
+        if let Some(array) = values.iter().find(|value| value.is_array()) {
+            return Err(CalcError::calculate(
+                format!("{name} is not defined for {}", array.describe_unit()),
+                expr.span,
+            ));
+        }
+
+        let values: Vec<Value> = values.iter().map(Value::settle).collect();
+        let unit = match values.iter().find_map(Value::unit) {
+            Some(unit) => unit.clone(),
+            None => {
+                let nums: Vec<Number> = values.iter().map(|value| value.number().clone()).collect();
+                return Ok(Value::Number(self.call_numeric(name, &nums, expr)?));
+            }
+        };
+
+        // functions that make sense for quantities: the unit-carrying arguments
+        // are brought to one unit, the rest has to be plain numbers
+        let error = |message: String| CalcError::calculate(format!("{name}: {message}"), expr.span);
+        let (scaled, rest) = match name {
+            "abs" | "floor" | "ceil" | "trunc" | "round" => values.split_at(1),
+            "min" | "max" | "hypot" => values.split_at(values.len()),
+            "sqrt" | "cbrt" => {
+                let root = match unit.root(if name == "sqrt" { 2 } else { 3 }) {
+                    Some(root) => root,
+                    None => return Err(error(format!("cannot take the root of {unit}"))),
+                };
+                let res = self.call_numeric(name, &[values[0].number().clone()], expr)?;
+                return Ok(Value::quantity(res, root));
+            }
+            _ => return Err(error(String::from("expects dimensionless arguments"))),
+        };
+
+        let mut nums: Vec<Number> = Vec::with_capacity(values.len());
+        for value in scaled {
+            nums.push(value.convert(&unit).map_err(error)?.number().clone());
+        }
+        for value in rest {
+            match value {
+                Value::Number(num) => nums.push(num.clone()),
+                _ => return Err(error(String::from("expects a plain number"))),
+            }
+        }
+        Ok(Value::Quantity(self.call_numeric(name, &nums, expr)?, Box::new(unit)))
+    }
+
+    fn call_numeric(&self, name: &str, values: &[Number], expr: &Expr) -> Result<Number, CalcError> {
+        let res = self.call_float(name, values, expr)?;
+        res.for_mode(self.env.mode()).map_err(|err| CalcError::calculate(format!("{name}: {err}"), expr.span))
+    }
+
+    fn call_float(&self, name: &str, values: &[Number], expr: &Expr) -> Result<Number, CalcError> {
+        let builtin = find_builtin(name).unwrap();
+        if let Some(res) = exact_builtin(name, values) {
+            return Ok(res);
+        }
+
+        let is_complex = values.iter().any(Number::is_complex);
+        if !is_complex {
+            let floats: Vec<f64> = values.iter().map(Number::to_f64).collect();
+            let res = (builtin.func)(&floats);
+            // NaN out of finite arguments means the input was outside the real domain
+            if !res.is_nan() || floats.iter().any(|value| value.is_nan()) {
+                return Ok(Number::Float(res));
+            }
+        }
+
+        match builtin.complex {
+            Some(func) => {
+                let complex: Vec<Complex64> = values.iter().map(Number::to_complex).collect();
+                let res = func(&complex);
+                if res.is_nan() {
+                    return Err(CalcError::calculate(format!("{name}: argument out of domain"), expr.span));
+                }
+                Ok(Number::from_complex(res))
+            }
+            None if is_complex => Err(CalcError::calculate(
+                format!("{name} is not defined for complex numbers"),
+                expr.span,
+            )),
+            None => Err(CalcError::calculate(format!("{name}: argument out of domain"), expr.span)),
+        }
+    }
+
+    fn call_user_function(&self, name: &str, values: &[Value], expr: &Expr) -> Result<Value, CalcError> {
+        let func = match self.env.function(name) {
+            Some(func) => func,
+            None => {
+                return Err(CalcError::calculate(format!("unknown function '{name}'"), expr.span))
+            }
+        };
+        if func.params.len() != values.len() {
+            return Err(CalcError::calculate(
+                format!("{name} expects {} arguments, got {}", func.params.len(), values.len()),
+                expr.span,
+            ));
+        }
+        if self.depth >= MAX_CALL_DEPTH {
+            return Err(CalcError::calculate("maximum recursion depth exceeded", expr.span));
+        }
+        self.calls.set(self.calls.get() + 1);
+        if self.calls.get() > MAX_CALLS {
+            return Err(CalcError::calculate("too many function calls", expr.span));
+        }
+
+        let scope = Scope {
+            env: self.env,
+            locals: func.params.iter().map(String::as_str).zip(values.iter().cloned()).collect(),
+            depth: self.depth + 1,
+            calls: self.calls,
+        };
+        let res = scope.calculate(&func.body);
+        if self.depth > 0 {
+            return res;
+        }
+        // spans inside the body point into the definition, not into this input
+        res.map_err(|err| CalcError::calculate(format!("in {name}: {}", err.message), expr.span))
+    }
+}
+
+// `lhs = rhs` becomes `lhs - rhs`, anything else is read as `expr = 0`
+fn residual(equation: &Expr) -> Expr {
+    match &equation.kind {
+        ExprKind::Equation(lhs, rhs) => Expr::new(
+            ExprKind::Binary(BinOp::Sub, lhs.clone(), rhs.clone()),
+            equation.span,
+        ),
+        _ => equation.clone(),
+    }
+}
+
+// the values of all unknowns of a top-level `nsolve(...)`
+pub fn solve_system(expr: &Expr, env: &Env) -> Result<Vec<(String, Value)>, CalcError> {
+    let args = match &expr.kind {
+        ExprKind::Call(name, args) if name == "nsolve" => args,
+        _ => return Err(CalcError::calculate("expected nsolve(...)", expr.span)),
+    };
+    let calls = Cell::new(0);
+    let scope = Scope { env, locals: Vec::new(), depth: 0, calls: &calls };
+    let roots = scope.nsolve(args, expr)?;
+    Ok(roots.into_iter().map(|(var, root)| (var, Value::Number(Number::Float(root)))).collect())
+}
+
+// `expr` as a real number with `var` bound to `x`
+pub fn calculate_at(expr: &Expr, env: &Env, var: &str, x: f64) -> Result<f64, CalcError> {
+    let calls = Cell::new(0);
+    let scope = Scope { env, locals: Vec::new(), depth: 0, calls: &calls };
+    scope.calculate_at(expr, &[(var, x)])
+}
+
+pub fn calculate(expr: &Expr, env: &Env) -> Result<Value, CalcError> {
+    let calls = Cell::new(0);
+    let scope = Scope { env, locals: Vec::new(), depth: 0, calls: &calls };
+    scope.calculate(expr)
+}
@@ -0,0 +1,208 @@
+use std::ops::Range;
+
+use crate::engine::matrix::Matrix;
+use crate::engine::number::{format_complex, format_decimal, Number};
+use crate::engine::value::Value;
+
+// exponents that `Auto` and `Significant` write out in full, `1e16` and
+// `1e-6` get an exponent
+const POSITIONAL: Range<i32> = -5..16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Notation {
+    // rounded to 15 significant digits, so `0.1 + 0.2` shows as `0.3`
+    #[default]
+    Auto,
+    // this many digits after the point
+    Fixed(u32),
+    // this many significant digits
+    Significant(u32),
+    // `1.50e3`, with this many significant digits
+    Scientific(u32),
+    // as scientific, with the exponent a multiple of 3: `15.0e3`
+    Engineering(u32),
+}
+
+// how results are written out. The notation applies to floating point and
+// decimal numbers; exact fractions and programmer words keep their form
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Format {
+    pub notation: Notation,
+    // `1,234,567.5`, or `1 234 567,5` with a decimal comma
+    pub grouping: bool,
+    // `1,5` in results and in input, as in the Ukrainian locale; lists are
+    // then separated by `;`: `max(1,5; 2)`, `[1,5; 2,5]`
+    pub decimal_comma: bool,
+}
+
+// the power of ten in `1.5e-3`
+fn exponent(sci: &str) -> i32 {
+    sci.split_once('e').and_then(|(_, exp)| exp.parse().ok()).unwrap_or(0)
+}
+
+fn auto(x: f64) -> String {
+    let rounded: f64 = format!("{x:.14e}").parse().unwrap_or(x);
+    if rounded == 0.0 || POSITIONAL.contains(&exponent(&format!("{rounded:e}"))) {
+        rounded.to_string()
+    } else {
+        format!("{rounded:e}")
+    }
+}
+
+fn significant(x: f64, digits: usize) -> String {
+    let sci = format!("{x:.*e}", digits - 1);
+    let exp = exponent(&sci);
+    if x == 0.0 || POSITIONAL.contains(&exp) {
+        let decimals = (digits as i32 - 1 - exp).max(0) as usize;
+        format!("{:.decimals$}", sci.parse::<f64>().unwrap_or(x))
+    } else {
+        sci
+    }
+}
+
+// the point of `1.2345e4` moved to make the exponent a multiple of 3: `12.345e3`
+fn engineering(x: f64, digits: usize) -> String {
+    let sci = format!("{x:.*e}", digits - 1);
+    let (mantissa, exp) = match sci.split_once('e') {
+        Some((mantissa, exp)) => (mantissa, exp.parse::<i32>().unwrap_or(0)),
+        None => return sci,
+    };
+    let (sign, mantissa) = match mantissa.strip_prefix('-') {
+        Some(mantissa) => ("-", mantissa),
+        None => ("", mantissa),
+    };
+    let shift = exp.rem_euclid(3);
+    let mut digits: String = mantissa.chars().filter(char::is_ascii_digit).collect();
+    let int_len = 1 + shift as usize;
+    while digits.len() < int_len {
+        digits.push('0');
+    }
+    let (int_part, frac_part) = digits.split_at(int_len);
+    let point = if frac_part.is_empty() { "" } else { "." };
+    format!("{sign}{int_part}{point}{frac_part}e{}", exp - shift)
+}
+
+// `format_decimal` without the trailing zeros trimmed
+fn pad_decimals(text: String, decimals: usize) -> String {
+    if decimals == 0 {
+        return text;
+    }
+    let present = text.split_once('.').map_or(0, |(_, frac)| frac.len());
+    let point = if present == 0 { "." } else { "" };
+    format!("{text}{point}{}", "0".repeat(decimals - present))
+}
+
+// `-0.00` after rounding is `0.00`
+fn unsigned_zero(text: String) -> String {
+    let mantissa = text.split('e').next().unwrap_or("");
+    match text.strip_prefix('-') {
+        Some(rest) if mantissa.chars().all(|ch| matches!(ch, '-' | '0' | '.')) => rest.to_string(),
+        _ => text,
+    }
+}
+
+impl Format {
+    fn plain_float(&self, x: f64) -> String {
+        if !x.is_finite() {
+            return x.to_string();
+        }
+        let text = match self.notation {
+            Notation::Auto => auto(x),
+            Notation::Fixed(decimals) => format!("{x:.*}", decimals as usize),
+            Notation::Significant(digits) => significant(x, digits.max(1) as usize),
+            Notation::Scientific(digits) => format!("{x:.*e}", digits.max(1) as usize - 1),
+            Notation::Engineering(digits) => engineering(x, digits.max(1) as usize),
+        };
+        unsigned_zero(text)
+    }
+
+    // groups the whole part of `-1234.5e3` and puts in the decimal comma
+    fn punctuate(&self, text: &str) -> String {
+        let (sign, text) = match text.strip_prefix('-') {
+            Some(text) => ("-", text),
+            None => ("", text),
+        };
+        let int_len = text.find(|ch: char| !ch.is_ascii_digit()).unwrap_or(text.len());
+        let (int_part, rest) = text.split_at(int_len);
+
+        let mut grouped = String::with_capacity(int_part.len() * 4 / 3);
+        let separator = if self.decimal_comma { ' ' } else { ',' };
+        for (i, ch) in int_part.chars().enumerate() {
+            if self.grouping && i > 0 && (int_len - i) % 3 == 0 {
+                grouped.push(separator);
+            }
+            grouped.push(ch);
+        }
+        let rest = if self.decimal_comma { rest.replacen('.', ",", 1) } else { rest.to_string() };
+        format!("{sign}{grouped}{rest}")
+    }
+
+    fn float(&self, x: f64) -> String {
+        self.punctuate(&self.plain_float(x))
+    }
+
+    pub fn number(&self, num: &Number) -> String {
+        match (num, self.notation) {
+            (Number::Float(x), _) => self.float(*x),
+            (Number::Decimal(_, _), Notation::Auto) => self.punctuate(&num.to_string()),
+            (Number::Decimal(value, _), Notation::Fixed(decimals)) => {
+                self.punctuate(&pad_decimals(format_decimal(value, decimals), decimals as usize))
+            }
+            (Number::Decimal(_, _), _) => self.float(num.to_f64()),
+            (Number::Rational(_), _) => {
+                num.to_string().split('/').map(|part| self.punctuate(part)).collect::<Vec<_>>().join("/")
+            }
+            (Number::Word(_, _), _) => num.to_string(),
+            (Number::Complex(z), _) => format_complex(z, |part| self.float(part)),
+        }
+    }
+
+    // between list items: `, `, or `; ` with a decimal comma
+    pub fn separator(&self) -> &'static str {
+        if self.decimal_comma { "; " } else { ", " }
+    }
+
+    fn list(&self, nums: &[Number]) -> String {
+        nums.iter().map(|num| self.number(num)).collect::<Vec<_>>().join(self.separator())
+    }
+
+    // as `Value`'s `Display`, with the numbers written in this format
+    pub fn value(&self, value: &Value) -> String {
+        match value {
+            Value::Number(num) => self.number(num),
+            Value::Quantity(num, unit) => format!("{} {unit}", self.number(num)),
+            Value::Bool(value) => value.to_string(),
+            Value::Vector(nums) => format!("[{}]", self.list(nums)),
+            Value::Matrix(matrix) => {
+                let rows: Vec<String> =
+                    (0..matrix.rows()).map(|row| format!("[{}]", self.list(matrix.row(row)))).collect();
+                format!("[{}]", rows.join(self.separator()))
+            }
+        }
+    }
+
+    // the entries as text, one `Vec` per row, for showing as a grid
+    pub fn cells(&self, matrix: &Matrix) -> Vec<Vec<String>> {
+        (0..matrix.rows()).map(|row| matrix.row(row).iter().map(|num| self.number(num)).collect()).collect()
+    }
+
+    // input with a decimal comma turned into what the lexer reads: a comma
+    // between two digits becomes a point and `;` separates arguments. Every
+    // character stays in its place, so error spans still fit the input
+    pub fn localize(&self, expr: &str) -> String {
+        if !self.decimal_comma {
+            return expr.to_string();
+        }
+        let chars: Vec<char> = expr.chars().collect();
+        let is_digit = |i: Option<usize>| i.and_then(|i| chars.get(i)).is_some_and(char::is_ascii_digit);
+        chars
+            .iter()
+            .enumerate()
+            .map(|(i, &ch)| match ch {
+                ',' if is_digit(i.checked_sub(1)) && is_digit(Some(i + 1)) => '.',
+                ';' => ',',
+                ch => ch,
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,189 @@
+use std::f64::consts::{E, PI, TAU};
+
+use num_complex::Complex64;
+
+use crate::engine::number::Number;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arity {
+    Exact(usize),
+    Range(usize, usize),
+    AtLeast(usize),
+}
+
+pub struct Builtin {
+    pub name: &'static str,
+    pub arity: Arity,
+    pub func: fn(&[f64]) -> f64,
+    // used for complex arguments and when the real result would be NaN
+    pub complex: Option<fn(&[Complex64]) -> Complex64>,
+}
+
+impl Arity {
+    pub fn accepts(&self, argc: usize) -> bool {
+        match *self {
+            Arity::Exact(n) => argc == n,
+            Arity::Range(min, max) => argc >= min && argc <= max,
+            Arity::AtLeast(min) => argc >= min,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        let plural = |n: usize| if n == 1 { "argument" } else { "arguments" };
+        match *self {
+            Arity::Exact(n) => format!("{n} {}", plural(n)),
+            Arity::Range(min, max) => format!("{min} to {max} arguments"),
+            Arity::AtLeast(min) => format!("at least {min} {}", plural(min)),
+        }
+    }
+}
+
+const fn real(name: &'static str, arity: Arity, func: fn(&[f64]) -> f64) -> Builtin {
+    Builtin { name, arity, func, complex: None }
+}
+
+const fn complex(
+    name: &'static str,
+    arity: Arity,
+    func: fn(&[f64]) -> f64,
+    complex: fn(&[Complex64]) -> Complex64,
+) -> Builtin {
+    Builtin { name, arity, func, complex: Some(complex) }
+}
+
+fn round(args: &[f64]) -> f64 {
+    match args {
+        [x] => x.round(),
+        [x, digits] => {
+            let scale = 10f64.powi(*digits as i32);
+            (x * scale).round() / scale
+        }
+        _ => f64::NAN,
+    }
+}
+
+fn log(args: &[f64]) -> f64 {
+    match args {
+        [x] => x.log10(),
+        [base, x] => x.ln() / base.ln(),
+        _ => f64::NAN,
+    }
+}
+
+// the Lanczos approximation, with the reflection formula below 1/2; whole
+// numbers are multiplied out so `gamma(6)` is exactly 120
+pub fn gamma(x: f64) -> f64 {
+    const G: f64 = 7.0;
+    const COEFFS: [f64; 9] = [
+        0.999_999_999_999_809_9,
+        676.520_368_121_885_1,
+        -1_259.139_216_722_402_8,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507_343_278_686_905,
+        -0.138_571_095_265_720_12,
+        9.984_369_578_019_572e-6,
+        1.505_632_735_149_311_6e-7,
+    ];
+
+    if x.fract() == 0.0 {
+        return match x {
+            x if x <= 0.0 => f64::NAN,
+            x if x > 171.0 => f64::INFINITY,
+            x => (2..x as u32).map(f64::from).product(),
+        };
+    }
+    if x < 0.5 {
+        return PI / ((PI * x).sin() * gamma(1.0 - x));
+    }
+    let x = x - 1.0;
+    let t = x + G + 0.5;
+    let sum = COEFFS[0] + COEFFS.iter().enumerate().skip(1).map(|(i, c)| c / (x + i as f64)).sum::<f64>();
+    (2.0 * PI).sqrt() * t.powf(x + 0.5) * (-t).exp() * sum
+}
+
+fn complex_log(args: &[Complex64]) -> Complex64 {
+    match args {
+        [x] => x.ln() / 10f64.ln(),
+        [base, x] => x.ln() / base.ln(),
+        _ => Complex64::new(f64::NAN, f64::NAN),
+    }
+}
+
+pub static BUILTINS: &[Builtin] = &[
+    complex("sin", Arity::Exact(1), |a| a[0].sin(), |z| z[0].sin()),
+    complex("cos", Arity::Exact(1), |a| a[0].cos(), |z| z[0].cos()),
+    complex("tan", Arity::Exact(1), |a| a[0].tan(), |z| z[0].tan()),
+    complex("asin", Arity::Exact(1), |a| a[0].asin(), |z| z[0].asin()),
+    complex("acos", Arity::Exact(1), |a| a[0].acos(), |z| z[0].acos()),
+    complex("atan", Arity::Exact(1), |a| a[0].atan(), |z| z[0].atan()),
+    real("atan2", Arity::Exact(2), |a| a[0].atan2(a[1])),
+    complex("sinh", Arity::Exact(1), |a| a[0].sinh(), |z| z[0].sinh()),
+    complex("cosh", Arity::Exact(1), |a| a[0].cosh(), |z| z[0].cosh()),
+    complex("tanh", Arity::Exact(1), |a| a[0].tanh(), |z| z[0].tanh()),
+    complex("exp", Arity::Exact(1), |a| a[0].exp(), |z| z[0].exp()),
+    complex("ln", Arity::Exact(1), |a| a[0].ln(), |z| z[0].ln()),
+    complex("log", Arity::Range(1, 2), log, complex_log),
+    complex("log2", Arity::Exact(1), |a| a[0].log2(), |z| z[0].ln() / 2f64.ln()),
+    complex("log10", Arity::Exact(1), |a| a[0].log10(), |z| z[0].ln() / 10f64.ln()),
+    complex("sqrt", Arity::Exact(1), |a| a[0].sqrt(), |z| z[0].sqrt()),
+    complex("cbrt", Arity::Exact(1), |a| a[0].cbrt(), |z| z[0].cbrt()),
+    complex("abs", Arity::Exact(1), |a| a[0].abs(), |z| Complex64::new(z[0].norm(), 0.0)),
+    complex("arg", Arity::Exact(1), |a| if a[0] < 0.0 { PI } else { 0.0 }, |z| Complex64::new(z[0].arg(), 0.0)),
+    complex("conj", Arity::Exact(1), |a| a[0], |z| z[0].conj()),
+    complex("re", Arity::Exact(1), |a| a[0], |z| Complex64::new(z[0].re, 0.0)),
+    complex("im", Arity::Exact(1), |_| 0.0, |z| Complex64::new(z[0].im, 0.0)),
+    real("gamma", Arity::Exact(1), |a| gamma(a[0])),
+    real("sign", Arity::Exact(1), |a| if a[0] == 0.0 { 0.0 } else { a[0].signum() }),
+    real("hypot", Arity::Exact(2), |a| a[0].hypot(a[1])),
+    real("min", Arity::AtLeast(1), |a| a.iter().copied().fold(f64::INFINITY, f64::min)),
+    real("max", Arity::AtLeast(1), |a| a.iter().copied().fold(f64::NEG_INFINITY, f64::max)),
+    real("round", Arity::Range(1, 2), round),
+    real("floor", Arity::Exact(1), |a| a[0].floor()),
+    real("ceil", Arity::Exact(1), |a| a[0].ceil()),
+    real("trunc", Arity::Exact(1), |a| a[0].trunc()),
+];
+
+pub fn find_builtin(name: &str) -> Option<&'static Builtin> {
+    BUILTINS.iter().find(|builtin| builtin.name == name)
+}
+
+// functions that receive their arguments as expressions rather than values
+pub static SPECIAL_FORMS: &[&str] = &["diff", "solve", "nsolve"];
+
+pub fn is_special_form(name: &str) -> bool {
+    SPECIAL_FORMS.contains(&name)
+}
+
+// functions of vectors and matrices, evaluated in `eval`
+pub static MATRIX_FUNCTIONS: &[&str] = &["det", "inv", "transpose", "dot", "cross"];
+
+pub fn is_matrix_function(name: &str) -> bool {
+    MATRIX_FUNCTIONS.contains(&name)
+}
+
+// statistics over any number of values and vectors, `mean(1, 2, 3)` or
+// `mean(data)`; `percentile` takes the percentage last
+pub static LIST_FUNCTIONS: &[&str] = &["sum", "mean", "median", "var", "stdev", "percentile"];
+
+pub fn is_list_function(name: &str) -> bool {
+    LIST_FUNCTIONS.contains(&name)
+}
+
+// either of the above, which only the evaluator knows
+pub fn is_array_function(name: &str) -> bool {
+    is_matrix_function(name) || is_list_function(name)
+}
+
+// names that evaluate to a fixed value unless a variable shadows them
+pub fn find_constant(name: &str) -> Option<Number> {
+    match name {
+        "i" => Some(Number::Complex(Complex64::new(0.0, 1.0))),
+        "pi" => Some(Number::Float(PI)),
+        "e" => Some(Number::Float(E)),
+        "tau" => Some(Number::Float(TAU)),
+        // the golden ratio
+        "phi" => Some(Number::Float((1.0 + 5f64.sqrt()) / 2.0)),
+        _ => None,
+    }
+}
@@ -0,0 +1,286 @@
+use crate::engine::error::{CalcError, Span};
+use crate::engine::number::Literal;
+use crate::engine::token::{BinOp, PostOp, Token, TokenKind, UnOp};
+use crate::engine::units::UnitTable;
+
+// words that act as operators after an operand
+static KEYWORDS: [&str; 4] = ["in", "to", "xor", "mod"];
+
+fn is_ident_char(chars: &[char], pos: usize) -> bool {
+    pos < chars.len() && (chars[pos].is_alphanumeric() || chars[pos] == '_')
+}
+
+fn read_word(chars: &[char], mut pos: usize) -> usize {
+    while is_ident_char(chars, pos) {
+        pos += 1;
+    }
+    pos
+}
+
+fn is_digit(chars: &[char], pos: usize) -> bool {
+    pos < chars.len() && chars[pos].is_ascii_digit()
+}
+
+// digits with single `_` separators between them, as in `1_000_000`
+fn read_digits(chars: &[char], mut pos: usize) -> usize {
+    let start = pos;
+    loop {
+        let separator = pos > start && chars.get(pos) == Some(&'_') && is_digit(chars, pos - 1);
+        if is_digit(chars, pos) || (separator && is_digit(chars, pos + 1)) {
+            pos += 1;
+        } else {
+            return pos;
+        }
+    }
+}
+
+// a unit written without spaces, e.g. `km`, `m/s^2` or `kg*m^-1`; returns
+// where it ends. It stops before a name that `is_unit` refuses, and a name
+// followed by `(` is a function call, not a unit
+fn read_unit(chars: &[char], start: usize, is_unit: &dyn Fn(&str) -> bool) -> Option<usize> {
+    let read_name = |pos: usize| {
+        let starts_name = pos < chars.len() && (chars[pos].is_alphabetic() || chars[pos] == '_');
+        let end = read_word(chars, pos);
+        (starts_name && is_unit(&String::from_iter(&chars[pos..end]))).then_some(end)
+    };
+    let mut pos = read_name(start)?;
+    loop {
+        if pos < chars.len() && chars[pos] == '^' {
+            let mut end = pos + 1;
+            if end < chars.len() && chars[end] == '-' {
+                end += 1;
+            }
+            let digits = end;
+            while end < chars.len() && chars[end].is_ascii_digit() {
+                end += 1;
+            }
+            if end > digits {
+                pos = end;
+            }
+        }
+        match chars.get(pos) {
+            Some('*' | '/') => match read_name(pos + 1) {
+                Some(end) => pos = end,
+                None => break,
+            },
+            _ => break,
+        }
+    }
+    if pos < chars.len() && chars[pos] == '(' {
+        return None;
+    }
+    Some(pos)
+}
+
+// `0x`, `0o` or `0b` followed by a digit of that base
+fn radix_prefix(chars: &[char], pos: usize) -> Option<u32> {
+    let radix = match chars.get(pos..pos + 2)? {
+        ['0', 'x'] => 16,
+        ['0', 'o'] => 8,
+        ['0', 'b'] => 2,
+        _ => return None,
+    };
+    chars.get(pos + 2)?.is_digit(radix).then_some(radix)
+}
+
+// signs are left out: after `10%` they are read as operators
+fn starts_operand(chars: &[char], pos: usize) -> bool {
+    pos < chars.len() && (chars[pos].is_alphanumeric() || matches!(chars[pos], '_' | '.' | '(' | '[' | '$' | '~'))
+}
+
+// whether the last token ends an operand, so what follows is an operator
+fn after_operand(tokens: &[Token]) -> bool {
+    matches!(
+        tokens.last(),
+        Some(Token {
+            kind: TokenKind::Number(_)
+                | TokenKind::Ident(_)
+                | TokenKind::RParen
+                | TokenKind::RBracket
+                | TokenKind::PostOp(_),
+            ..
+        })
+    )
+}
+
+fn skip_spaces(chars: &[char], mut pos: usize) -> usize {
+    while pos < chars.len() && chars[pos].is_whitespace() {
+        pos += 1;
+    }
+    pos
+}
+
+// a word after a number is its unit only when `units` knows it, so `2 x`
+// stays a number and a name
+pub fn parse_expression_to_tokens(expr: &str, units: &UnitTable) -> Result<Vec<Token>, CalcError> {
+    let chars: Vec<char> = expr.chars().collect();
+    let mut tokens: Vec<Token> = Vec::new();
+    let mut pos = 0;
+
+    while pos < chars.len() {
+        let ch = chars[pos];
+
+        if ch.is_whitespace() {
+            pos += 1;
+        } else if let Some(radix) = radix_prefix(&chars, pos) {
+            // `0xFF`; the digits are checked by `Literal::parse`
+            let start = pos;
+            pos = read_word(&chars, pos + 2);
+            let span = Span::new(start, pos);
+            let text: String = chars[start..pos].iter().collect();
+            match Literal::parse(&text) {
+                Ok(literal) => tokens.push(Token::new(TokenKind::Number(literal), span)),
+                Err(_) => {
+                    return Err(CalcError::parse(format!("invalid base {radix} number '{text}'"), span))
+                }
+            }
+        } else if ch.is_ascii_digit() || (ch == '.' && is_digit(&chars, pos + 1)) {
+            let start = pos;
+            pos = read_digits(&chars, pos);
+            if pos < chars.len() && chars[pos] == '.' {
+                pos = read_digits(&chars, pos + 1);
+            }
+            // `e3`, `E-5`; otherwise the `e` is left for a unit or a name
+            if pos < chars.len() && (chars[pos] == 'e' || chars[pos] == 'E') {
+                let sign = usize::from(matches!(chars.get(pos + 1), Some('+' | '-')));
+                if is_digit(&chars, pos + 1 + sign) {
+                    pos = read_digits(&chars, pos + 1 + sign);
+                }
+            }
+            // imaginary suffix, unless it starts a longer identifier
+            if pos < chars.len() && chars[pos] == 'i' && !is_ident_char(&chars, pos + 1) {
+                pos += 1;
+            }
+
+            let span = Span::new(start, pos);
+            let text: String = chars[start..pos].iter().collect();
+            let mut literal = match Literal::parse(&text) {
+                Ok(literal) => literal,
+                Err(err) => return Err(CalcError::parse(format!("{err} '{text}'"), span)),
+            };
+
+            // `3 km`, but not `3 in km` or `3 xor 1`
+            let unit_start = skip_spaces(&chars, pos);
+            let word: String = chars[unit_start..read_word(&chars, unit_start)].iter().collect();
+            if !literal.imaginary && !KEYWORDS.contains(&word.as_str()) {
+                if let Some(end) = read_unit(&chars, unit_start, &|name| units.is_unit(name)) {
+                    literal.unit = Some(chars[unit_start..end].iter().collect());
+                    pos = end;
+                }
+            }
+            tokens.push(Token::new(TokenKind::Number(literal), Span::new(start, pos)));
+        } else if let Some(op) = chars.get(pos..pos + 2).and_then(|pair| BinOp::from_pair(&String::from_iter(pair))) {
+            tokens.push(Token::new(TokenKind::BinOp(op), Span::new(pos, pos + 2)));
+            pos += 2;
+        } else if ch == '!' {
+            // `3!` is a factorial, `!x` a negation
+            let kind = if after_operand(&tokens) {
+                TokenKind::PostOp(PostOp::Factorial)
+            } else {
+                TokenKind::UnOp(UnOp::LogicalNot)
+            };
+            tokens.push(Token::new(kind, Span::new(pos, pos + 1)));
+            pos += 1;
+        } else if ch == '%' && !starts_operand(&chars, skip_spaces(&chars, pos + 1)) {
+            // a `%` that nothing follows is a percentage, `200 + 10% - 5`;
+            // before an operand it is the remainder, `7 % 3`
+            tokens.push(Token::new(TokenKind::PostOp(PostOp::Percent), Span::new(pos, pos + 1)));
+            pos += 1;
+        } else if let Some(op) = BinOp::from_char(ch) {
+            let span = Span::new(pos, pos + 1);
+            // a sign is unary when there is no left operand to apply it to
+            let is_unary = match tokens.last() {
+                None => true,
+                Some(prev) => matches!(
+                    prev.kind,
+                    TokenKind::BinOp(_)
+                        | TokenKind::UnOp(_)
+                        | TokenKind::LParen
+                        | TokenKind::LBracket
+                        | TokenKind::Comma
+                        | TokenKind::Assign
+                        | TokenKind::Question
+                        | TokenKind::Colon
+                ),
+            };
+
+            match UnOp::from_char(ch) {
+                Some(un) if is_unary => tokens.push(Token::new(TokenKind::UnOp(un), span)),
+                _ => tokens.push(Token::new(TokenKind::BinOp(op), span)),
+            }
+            pos += 1;
+        } else if let Some(op) = UnOp::from_char(ch) {
+            tokens.push(Token::new(TokenKind::UnOp(op), Span::new(pos, pos + 1)));
+            pos += 1;
+        } else if ch == '$' {
+            // `$3`, the third result
+            let start = pos;
+            pos += 1;
+            while pos < chars.len() && chars[pos].is_ascii_digit() {
+                pos += 1;
+            }
+            if pos == start + 1 {
+                return Err(CalcError::parse("expected a result number after '$'", Span::new(start, pos)));
+            }
+            tokens.push(Token::new(TokenKind::Ident(chars[start..pos].iter().collect()), Span::new(start, pos)));
+        } else if ch.is_alphabetic() || ch == '_' {
+            let start = pos;
+            pos = read_word(&chars, pos);
+            let name: String = chars[start..pos].iter().collect();
+
+            // `in`/`to` right after an operand converts it to another unit
+            let after_operand = after_operand(&tokens);
+            if let Some(op) = BinOp::from_word(&name).filter(|_| after_operand) {
+                tokens.push(Token::new(TokenKind::BinOp(op), Span::new(start, pos)));
+            } else if (name == "in" || name == "to") && after_operand {
+                let unit_start = skip_spaces(&chars, pos);
+                // unknown units are reported when the conversion is evaluated
+                let end = match read_unit(&chars, unit_start, &|_| true) {
+                    Some(end) => end,
+                    None => {
+                        return Err(CalcError::parse(
+                            format!("expected a unit after '{name}'"),
+                            Span::new(start, pos),
+                        ))
+                    }
+                };
+                let unit: String = chars[unit_start..end].iter().collect();
+                tokens.push(Token::new(TokenKind::Convert(unit), Span::new(start, end)));
+                pos = end;
+            } else {
+                tokens.push(Token::new(TokenKind::Ident(name), Span::new(start, pos)));
+            }
+        } else if ch == '=' {
+            tokens.push(Token::new(TokenKind::Assign, Span::new(pos, pos + 1)));
+            pos += 1;
+        } else if ch == '?' {
+            tokens.push(Token::new(TokenKind::Question, Span::new(pos, pos + 1)));
+            pos += 1;
+        } else if ch == ':' {
+            tokens.push(Token::new(TokenKind::Colon, Span::new(pos, pos + 1)));
+            pos += 1;
+        } else if ch == ',' {
+            tokens.push(Token::new(TokenKind::Comma, Span::new(pos, pos + 1)));
+            pos += 1;
+        } else if ch == '(' {
+            tokens.push(Token::new(TokenKind::LParen, Span::new(pos, pos + 1)));
+            pos += 1;
+        } else if ch == ')' {
+            tokens.push(Token::new(TokenKind::RParen, Span::new(pos, pos + 1)));
+            pos += 1;
+        } else if ch == '[' {
+            tokens.push(Token::new(TokenKind::LBracket, Span::new(pos, pos + 1)));
+            pos += 1;
+        } else if ch == ']' {
+            tokens.push(Token::new(TokenKind::RBracket, Span::new(pos, pos + 1)));
+            pos += 1;
+        } else {
+            return Err(CalcError::parse(
+                format!("invalid character '{ch}'"),
+                Span::new(pos, pos + 1),
+            ));
+        }
+    }
+
+    Ok(tokens)
+}
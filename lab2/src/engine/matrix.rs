@@ -0,0 +1,219 @@
+use crate::engine::number::{check_exact_power, exact_bits, integer, Number};
+
+// a dense matrix of numbers, stored row by row. Arithmetic goes through
+// `Number`, so exact modes give exact determinants and inverses
+#[derive(Debug, Clone, PartialEq)]
+pub struct Matrix {
+    rows: usize,
+    cols: usize,
+    data: Vec<Number>,
+}
+
+// the entry to pivot on: the largest one, which keeps float rounding small
+fn pivot_row(data: &[Number], n: usize, col: usize, start: usize) -> usize {
+    (start..n)
+        .max_by(|&a, &b| {
+            let (a, b) = (data[a * n + col].to_complex().norm(), data[b * n + col].to_complex().norm());
+            a.total_cmp(&b)
+        })
+        .unwrap_or(start)
+}
+
+impl Matrix {
+    // rows of equal length, at least one of them
+    pub fn from_rows(rows: Vec<Vec<Number>>) -> Result<Matrix, String> {
+        let cols = rows.first().map_or(0, Vec::len);
+        if rows.iter().any(|row| row.len() != cols) {
+            return Err(String::from("the rows of a matrix must have the same length"));
+        }
+        Ok(Matrix { rows: rows.len(), cols, data: rows.into_iter().flatten().collect() })
+    }
+
+    pub fn row_vector(data: Vec<Number>) -> Matrix {
+        Matrix { rows: 1, cols: data.len(), data }
+    }
+
+    pub fn column_vector(data: Vec<Number>) -> Matrix {
+        Matrix { rows: data.len(), cols: 1, data }
+    }
+
+    fn identity(n: usize) -> Matrix {
+        let data = (0..n * n).map(|i| integer(i64::from(i % (n + 1) == 0))).collect();
+        Matrix { rows: n, cols: n, data }
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    pub fn data(&self) -> &[Number] {
+        &self.data
+    }
+
+    pub fn into_data(self) -> Vec<Number> {
+        self.data
+    }
+
+    pub fn row(&self, row: usize) -> &[Number] {
+        &self.data[row * self.cols..(row + 1) * self.cols]
+    }
+
+    // `2×3`
+    pub fn shape(&self) -> String {
+        format!("{}×{}", self.rows, self.cols)
+    }
+
+    pub fn try_map<E>(&self, func: impl FnMut(&Number) -> Result<Number, E>) -> Result<Matrix, E> {
+        let data = self.data.iter().map(func).collect::<Result<_, _>>()?;
+        Ok(Matrix { rows: self.rows, cols: self.cols, data })
+    }
+
+    pub fn transpose(&self) -> Matrix {
+        let data = (0..self.rows * self.cols)
+            .map(|i| self.data[(i % self.rows) * self.cols + i / self.rows].clone())
+            .collect();
+        Matrix { rows: self.cols, cols: self.rows, data }
+    }
+
+    pub fn mul(&self, other: &Matrix) -> Result<Matrix, String> {
+        if self.cols != other.rows {
+            return Err(format!("cannot multiply a {} matrix by a {} matrix", self.shape(), other.shape()));
+        }
+        let mut data = Vec::with_capacity(self.rows * other.cols);
+        for row in 0..self.rows {
+            for col in 0..other.cols {
+                let sum = (0..self.cols)
+                    .map(|k| self.data[row * self.cols + k].mul(&other.data[k * other.cols + col]))
+                    .fold(integer(0), |sum, term| sum.add(&term));
+                data.push(sum);
+            }
+        }
+        Ok(Matrix { rows: self.rows, cols: other.cols, data })
+    }
+
+    fn check_square(&self, what: &str) -> Result<usize, String> {
+        if self.rows != self.cols {
+            return Err(format!("{what} needs a square matrix, not {}", self.shape()));
+        }
+        Ok(self.rows)
+    }
+
+    // by Gaussian elimination
+    pub fn det(&self) -> Result<Number, String> {
+        let n = self.check_square("det")?;
+        let mut data = self.data.clone();
+        let mut det = integer(1);
+        for col in 0..n {
+            let pivot = pivot_row(&data, n, col, col);
+            if data[pivot * n + col].is_zero() {
+                return Ok(integer(0));
+            }
+            if pivot != col {
+                for k in 0..n {
+                    data.swap(pivot * n + k, col * n + k);
+                }
+                det = det.neg();
+            }
+            let head = data[col * n + col].clone();
+            det = det.mul(&head);
+            for row in col + 1..n {
+                let factor = data[row * n + col].div(&head)?;
+                for k in col..n {
+                    data[row * n + k] = data[row * n + k].sub(&factor.mul(&data[col * n + k]));
+                }
+            }
+        }
+        Ok(det)
+    }
+
+    // by Gauss-Jordan elimination next to the identity
+    pub fn inv(&self) -> Result<Matrix, String> {
+        let n = self.check_square("inv")?;
+        let mut data = self.data.clone();
+        let mut res = Matrix::identity(n).data;
+        for col in 0..n {
+            let pivot = pivot_row(&data, n, col, col);
+            if data[pivot * n + col].is_zero() {
+                return Err(String::from("the matrix is singular"));
+            }
+            for k in 0..n {
+                data.swap(pivot * n + k, col * n + k);
+                res.swap(pivot * n + k, col * n + k);
+            }
+            let head = data[col * n + col].clone();
+            for k in 0..n {
+                data[col * n + k] = data[col * n + k].div(&head)?;
+                res[col * n + k] = res[col * n + k].div(&head)?;
+            }
+            for row in (0..n).filter(|&row| row != col) {
+                let factor = data[row * n + col].clone();
+                for k in 0..n {
+                    data[row * n + k] = data[row * n + k].sub(&factor.mul(&data[col * n + k]));
+                    res[row * n + k] = res[row * n + k].sub(&factor.mul(&res[col * n + k]));
+                }
+            }
+        }
+        Ok(Matrix { rows: n, cols: n, data: res })
+    }
+
+    // repeated squaring; negative powers go through the inverse
+    pub fn pow(&self, exp: i64) -> Result<Matrix, String> {
+        let n = self.check_square("a power")?;
+        let mut base = if exp < 0 { self.inv()? } else { self.clone() };
+        // exact entries grow as in a power of a number, and by up to `n` times
+        // with every product
+        let bits = base.data.iter().filter_map(Number::to_rational).map(exact_bits).max();
+        if let Some(bits) = bits {
+            let sum_bits = n.next_power_of_two().trailing_zeros() as u64;
+            check_exact_power(bits + sum_bits, exp)?;
+        }
+        let mut exp = exp.unsigned_abs();
+        let mut res = Matrix::identity(n);
+        while exp > 0 {
+            if exp & 1 == 1 {
+                res = res.mul(&base)?;
+            }
+            base = base.mul(&base)?;
+            exp >>= 1;
+        }
+        Ok(res)
+    }
+}
+
+// the product of two vectors of equal length
+pub fn dot(a: &[Number], b: &[Number]) -> Result<Number, String> {
+    if a.len() != b.len() {
+        return Err(format!("dot needs vectors of equal length, not {} and {}", a.len(), b.len()));
+    }
+    Ok(a.iter().zip(b).fold(integer(0), |sum, (a, b)| sum.add(&a.mul(b))))
+}
+
+pub fn cross(a: &[Number], b: &[Number]) -> Result<Vec<Number>, String> {
+    match (a, b) {
+        ([a1, a2, a3], [b1, b2, b3]) => Ok(vec![
+            a2.mul(b3).sub(&a3.mul(b2)),
+            a3.mul(b1).sub(&a1.mul(b3)),
+            a1.mul(b2).sub(&a2.mul(b1)),
+        ]),
+        _ => Err(String::from("cross needs two vectors of length 3")),
+    }
+}
+
+// rows of cells with right-aligned columns, as the CLI prints a matrix
+pub fn format_grid(cells: &[Vec<String>]) -> String {
+    let cols = cells.first().map_or(0, Vec::len);
+    let widths: Vec<usize> =
+        (0..cols).map(|col| cells.iter().map(|row| row[col].chars().count()).max().unwrap_or(0)).collect();
+    cells
+        .iter()
+        .map(|row| {
+            let row: Vec<String> = row.iter().zip(&widths).map(|(cell, width)| format!("{cell:>width$}")).collect();
+            format!("[ {} ]", row.join("  "))
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
@@ -0,0 +1,271 @@
+pub mod ast;
+pub mod compile;
+pub mod env;
+pub mod error;
+pub mod eval;
+pub mod format;
+pub mod functions;
+pub mod lexer;
+pub mod matrix;
+pub mod number;
+pub mod parser;
+pub mod solve;
+pub mod stats;
+pub mod symbolic;
+pub mod token;
+pub mod trace;
+pub mod units;
+pub mod value;
+
+use crate::engine::ast::{Expr, ExprKind, Target};
+use crate::engine::env::{Env, UserFunction};
+use crate::engine::error::{CalcError, Span};
+use crate::engine::functions::{find_builtin, is_array_function, is_special_form};
+use crate::engine::eval::{calculate, solve_system};
+use crate::engine::format::Format;
+use crate::engine::lexer::parse_expression_to_tokens;
+use crate::engine::number::{float_to_rational, Number, NumberMode};
+use crate::engine::parser::{build_tree, parse_tokens_to_rpn, split_assignment, trace_tokens_to_rpn};
+use crate::engine::symbolic::{expand_derivatives, has_symbolic_diff};
+use crate::engine::token::join_tokens;
+use crate::engine::trace::{reduce, Trace};
+use crate::engine::units::Unit;
+use crate::engine::value::Value;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    Value(Value),
+    // a user function definition, carries its normalized text
+    Defined(String),
+    // an expression that cannot be evaluated yet, such as `diff(x^2, x)`
+    // while `x` has no value
+    Symbolic(String),
+    // the unknowns of `nsolve(...)` and their values
+    Solution(Vec<(String, Value)>),
+}
+
+// `x = 1, y = 2`
+pub fn format_solution(roots: &[(String, Value)], format: &Format) -> String {
+    roots.iter().map(|(var, value)| format!("{var} = {}", format.value(value))).collect::<Vec<_>>().join(", ")
+}
+
+// `ans` and `$n`
+pub fn is_result_name(name: &str) -> bool {
+    name == "ans" || name.starts_with('$')
+}
+
+fn source_text(expr: &str, span: Span) -> String {
+    expr.chars().skip(span.start).take(span.end - span.start).collect()
+}
+
+// `calculate`, or `reduce` when the steps are wanted
+fn evaluate(tree: &Expr, env: &Env, trace: Option<&mut Trace>) -> Result<Value, CalcError> {
+    match trace {
+        Some(trace) => reduce(tree, env, &mut trace.reductions),
+        None => calculate(tree, env),
+    }
+}
+
+// with tracing on, the steps are kept in `env` for `Env::last_trace`
+pub fn calc(expr: &str, env: &mut Env) -> Result<Outcome, CalcError> {
+    let expr = env.format().localize(expr);
+    if !env.trace() {
+        return calc_traced(&expr, env, None);
+    }
+    let mut trace = Trace::default();
+    let res = calc_traced(&expr, env, Some(&mut trace));
+    env.set_last_trace(trace);
+    res
+}
+
+fn calc_traced(expr: &str, env: &mut Env, mut trace: Option<&mut Trace>) -> Result<Outcome, CalcError> {
+    let tokens = parse_expression_to_tokens(expr, env.units())?;
+    if let Some(trace) = trace.as_deref_mut() {
+        trace.tokens = join_tokens(&tokens);
+    }
+
+    let (target, body) = split_assignment(&tokens)?;
+    let rpn = match trace.as_deref_mut() {
+        Some(trace) => {
+            let rpn = trace_tokens_to_rpn(body, &mut trace.steps)?;
+            trace.rpn = join_tokens(&rpn);
+            rpn
+        }
+        None => parse_tokens_to_rpn(body)?,
+    };
+
+    let tree = build_tree(&rpn)?;
+    match target {
+        Some(Target::Func(name, params)) => {
+            if find_builtin(&name).is_some() || is_special_form(&name) || is_array_function(&name) {
+                return Err(CalcError::rpn(
+                    format!("cannot redefine built-in function '{name}'"),
+                    tokens[0].span,
+                ));
+            }
+            // derivatives are taken once, the function keeps the result
+            let expanded = expand_derivatives(&tree, env)?;
+            let body_text = if expanded == tree {
+                source_text(expr, body[0].span.join(body[body.len() - 1].span))
+            } else {
+                expanded.to_string()
+            };
+            let text = format!("{name}({}) = {body_text}", params.join(", "));
+            env.define(&name, UserFunction { params, body: expanded, text: text.clone() });
+            Ok(Outcome::Defined(text))
+        }
+        Some(Target::Var(name)) if is_result_name(&name) => Err(CalcError::rpn(
+            format!("cannot assign to '{name}', it refers to a previous result"),
+            tokens[0].span,
+        )),
+        Some(Target::Var(name)) => {
+            let res = evaluate(&tree, env, trace)?;
+            env.set(&name, res.clone());
+            env.push_result(res.clone());
+            Ok(Outcome::Value(res))
+        }
+        None if matches!(&tree.kind, ExprKind::Call(name, _) if name == "nsolve") => {
+            Ok(Outcome::Solution(solve_system(&tree, env)?))
+        }
+        None if has_symbolic_diff(&tree, env) => {
+            Ok(Outcome::Symbolic(expand_derivatives(&tree, env)?.to_string()))
+        }
+        None => {
+            let res = evaluate(&tree, env, trace)?;
+            env.push_result(res.clone());
+            Ok(Outcome::Value(res))
+        }
+    }
+}
+
+// parses an expression once so it can be evaluated many times with
+// `eval::calculate_at`, as the plot does for every sample
+pub fn parse(expr: &str, env: &Env) -> Result<Expr, CalcError> {
+    let tokens = parse_expression_to_tokens(&env.format().localize(expr), env.units())?;
+    if let (Some(_), _) = split_assignment(&tokens)? {
+        return Err(CalcError::rpn("expected an expression, not an assignment", tokens[0].span));
+    }
+    let rpn = parse_tokens_to_rpn(&tokens)?;
+    expand_derivatives(&build_tree(&rpn)?, env)
+}
+
+// a plain identifier that is not an operator word
+fn is_plain_name(name: &str) -> bool {
+    name.starts_with(|ch: char| ch.is_alphabetic() || ch == '_')
+        && name.chars().all(|ch| ch.is_alphanumeric() || ch == '_')
+        && !["in", "to", "xor", "mod"].contains(&name)
+}
+
+// a pasted column of numbers as a vector, one number or expression per line;
+// blank lines are skipped
+pub fn read_data(text: &str, env: &Env) -> Result<Value, String> {
+    let mut nums: Vec<Number> = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let value = parse(line, env).and_then(|tree| calculate(&tree, env));
+        match value.map(|value| value.settle()) {
+            Ok(Value::Number(num)) => nums.push(num),
+            Ok(value) => return Err(format!("line {}: expected a number, got {}", i + 1, value.describe_unit())),
+            Err(err) => return Err(format!("line {}: {err}", i + 1)),
+        }
+    }
+    if nums.is_empty() {
+        return Err(String::from("no numbers to read"));
+    }
+    Ok(Value::Vector(nums))
+}
+
+// reads a column of numbers into the variable `name`, as the data-entry mode does
+pub fn store_data(env: &mut Env, name: &str, text: &str) -> Result<Value, String> {
+    if !is_plain_name(name) || is_result_name(name) {
+        return Err(format!("invalid variable name '{name}'"));
+    }
+    let value = read_data(text, env)?;
+    env.set(name, value.clone());
+    Ok(value)
+}
+
+// `name = expr`, with `name` a plain identifier that is not an operator word
+fn split_definition<'a>(line: &'a str, kind: &str) -> Result<(&'a str, &'a str), String> {
+    let (name, expr) = match line.split_once('=') {
+        Some((name, expr)) => (name.trim(), expr),
+        None => return Err(String::from("expected 'name = value'")),
+    };
+    if !is_plain_name(name) {
+        return Err(format!("invalid {kind} name '{name}'"));
+    }
+    Ok((name, expr))
+}
+
+fn define_unit(env: &mut Env, line: &str) -> Result<(), String> {
+    let (name, expr) = split_definition(line, "unit")?;
+    let tokens = parse_expression_to_tokens(expr, env.units()).map_err(|err| err.to_string())?;
+    let rpn = parse_tokens_to_rpn(&tokens).map_err(|err| err.to_string())?;
+    let tree = build_tree(&rpn).map_err(|err| err.to_string())?;
+    let (num, unit) = match calculate(&tree, env).map_err(|err| err.to_string())?.settle() {
+        Value::Quantity(num, unit) => (num, *unit),
+        value => (value.number().clone(), Unit::one()),
+    };
+
+    let size = num.to_rational().cloned().or_else(|| float_to_rational(num.to_f64()));
+    match size {
+        Some(size) if !num.is_zero() => {
+            env.units_mut().define(name, size * &unit.factor, unit.dim);
+            Ok(())
+        }
+        _ => Err(format!("'{name}' must be a non-zero real number")),
+    }
+}
+
+fn define_constant(env: &mut Env, line: &str) -> Result<(), String> {
+    let (name, expr) = split_definition(line, "constant")?;
+    if find_builtin(name).is_some() || is_special_form(name) || is_array_function(name) || is_result_name(name) {
+        return Err(format!("'{name}' is reserved"));
+    }
+    let tree = parse(expr, env).map_err(|err| err.to_string())?;
+    // checked once here, so a broken constant is reported with its line
+    calculate(&tree, env).map_err(|err| err.to_string())?;
+    env.define_constant(name, tree);
+    Ok(())
+}
+
+// runs `define` for every non-empty line, `#` starts a comment
+fn read_definitions(env: &mut Env, text: &str, define: fn(&mut Env, &str) -> Result<(), String>) -> Result<(), String> {
+    for (i, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        define(env, line).map_err(|err| format!("line {}: {err}", i + 1))?;
+    }
+    Ok(())
+}
+
+// extra units, one `name = value` per line such as `furlong = 201.168 m`;
+// `#` starts a comment. Values are evaluated exactly and may use earlier lines
+pub fn define_units(env: &mut Env, text: &str) -> Result<(), String> {
+    let mode = env.mode();
+    env.set_mode(NumberMode::Exact);
+    let res = read_definitions(env, text, define_unit);
+    env.set_mode(mode);
+    res
+}
+
+// named constants in the same format, such as `c = 299792458 m/s`. They may
+// use earlier lines and are evaluated in the current mode wherever they are used
+pub fn define_constants(env: &mut Env, text: &str) -> Result<(), String> {
+    read_definitions(env, text, define_constant)
+}
+
+pub type ConfigReader = fn(&mut Env, &str) -> Result<(), String>;
+
+// the files of the config directory in the order they are read, constants
+// may use the units
+pub static CONFIG_FILES: [(&str, ConfigReader); 2] =
+    [("units.conf", define_units), ("constants.conf", define_constants)];
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,671 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+use num_bigint::BigInt;
+use num_complex::Complex64;
+use num_rational::BigRational;
+use num_traits::{FromPrimitive, One, Signed, ToPrimitive, Zero};
+
+use crate::engine::functions::gamma;
+
+// exact integer powers and shifts beyond this are refused instead of eating
+// all memory
+static MAX_EXACT_EXPONENT: i64 = 100_000;
+// and so are powers whose numerator or denominator would need more bits
+static MAX_EXACT_BITS: u64 = 1 << 20;
+// 10000! already has 35660 digits
+static MAX_EXACT_FACTORIAL: u64 = 10_000;
+
+// the larger of the numerator and the denominator, in bits
+pub fn exact_bits(num: &BigRational) -> u64 {
+    num.numer().bits().max(num.denom().bits())
+}
+
+// refuses raising an exact number of `bits` bits to `exp` when the result
+// would be too large
+pub fn check_exact_power(bits: u64, exp: i64) -> Result<(), &'static str> {
+    if exp.unsigned_abs() > MAX_EXACT_EXPONENT as u64 {
+        return Err("exponent is too large for exact arithmetic");
+    }
+    if bits.saturating_mul(exp.unsigned_abs()) > MAX_EXACT_BITS {
+        return Err("result is too large");
+    }
+    Ok(())
+}
+
+// the integer type of programmer mode; results wrap around like machine words
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WordSize {
+    pub bits: u32,
+    pub signed: bool,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum NumberMode {
+    #[default]
+    Float,
+    Exact,
+    // arbitrary precision, rounded to this many digits after the point.
+    // What only has a floating point form, `pi`, `sin(1)` or `2^0.5`, keeps
+    // the 16 or 17 significant digits of its f64 and is not padded further
+    Decimal(u32),
+    Programmer(WordSize),
+}
+
+// keeps `1e999999999` from building a number with a billion digits
+static MAX_EXPONENT: u32 = 10_000;
+
+// a numeric literal as typed, kept exact so every mode can use it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Literal {
+    pub text: String,
+    pub float: f64,
+    pub exact: BigRational,
+    // `2i`, the imaginary part of a complex number
+    pub imaginary: bool,
+    // `3 km`, resolved against the unit table when evaluated
+    pub unit: Option<String>,
+}
+
+// complex results always have a non-zero imaginary part, see `from_complex`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Number {
+    Float(f64),
+    Rational(BigRational),
+    Decimal(BigRational, u32),
+    // always an integer that fits the word
+    Word(BigRational, WordSize),
+    Complex(Complex64),
+}
+
+impl Literal {
+    // `42`, `0.5`, `.5`, `3.`, `6.02e23`, `1_000`, an imaginary `2.5i`, or an
+    // integer in another base: `0xFF`, `0o17`, `0b1010`. The error says why
+    // the text is no number
+    pub fn parse(text: &str) -> Result<Literal, &'static str> {
+        let number = text.replace('_', "");
+        let radix = match number.get(..2) {
+            Some("0x") => Some(16),
+            Some("0o") => Some(8),
+            Some("0b") => Some(2),
+            _ => None,
+        };
+        if let Some(radix) = radix {
+            let value = BigInt::parse_bytes(&number.as_bytes()[2..], radix).ok_or("invalid number")?;
+            return Ok(Literal {
+                text: text.to_string(),
+                float: value.to_f64().unwrap_or(f64::INFINITY),
+                exact: BigRational::from_integer(value),
+                imaginary: false,
+                unit: None,
+            });
+        }
+
+        let (number, imaginary) = match number.strip_suffix('i') {
+            Some(number) => (number, true),
+            None => (number.as_str(), false),
+        };
+        let (mantissa, exponent) = match number.split_once(['e', 'E']) {
+            Some((mantissa, exponent)) => (mantissa, exponent),
+            None => (number, "0"),
+        };
+        let (int_part, frac_part) = match mantissa.split_once('.') {
+            Some((int_part, frac_part)) => (int_part, frac_part),
+            None => (mantissa, ""),
+        };
+        let all_digits = |part: &str| part.chars().all(|ch| ch.is_ascii_digit());
+        if (int_part.is_empty() && frac_part.is_empty()) || !all_digits(int_part) || !all_digits(frac_part) {
+            return Err("invalid number");
+        }
+        let magnitude = exponent.strip_prefix(['+', '-']).unwrap_or(exponent);
+        if magnitude.is_empty() || !all_digits(magnitude) {
+            return Err("invalid number");
+        }
+        let exponent = match exponent.parse::<i32>() {
+            Ok(exponent) if exponent.unsigned_abs() <= MAX_EXPONENT => exponent,
+            _ if exponent.starts_with('-') => return Err("underflow in number"),
+            _ => return Err("overflow in number"),
+        };
+
+        let digits: BigInt = format!("0{int_part}{frac_part}").parse().map_err(|_| "invalid number")?;
+        let shift = exponent - frac_part.len() as i32;
+        let exact = if shift < 0 {
+            BigRational::new(digits, pow10(shift.unsigned_abs()))
+        } else {
+            BigRational::from_integer(digits * pow10(shift as u32))
+        };
+        Ok(Literal {
+            text: text.to_string(),
+            float: number.parse().map_err(|_| "invalid number")?,
+            exact,
+            imaginary,
+            unit: None,
+        })
+    }
+}
+
+fn pow10(digits: u32) -> BigInt {
+    BigInt::from(10).pow(digits)
+}
+
+// the shortest decimal that reads back as `x`, so `0.1` is 1/10 and not the
+// binary fraction the float holds
+pub fn float_to_rational(x: f64) -> Option<BigRational> {
+    if !x.is_finite() {
+        return None;
+    }
+    let exact = Literal::parse(&format!("{:e}", x.abs())).ok()?.exact;
+    Some(if x < 0.0 { -exact } else { exact })
+}
+
+fn round_to(value: &BigRational, digits: u32) -> BigRational {
+    let scale = BigRational::from_integer(pow10(digits));
+    (value * &scale).round() / scale
+}
+
+impl WordSize {
+    // drops the fraction, then keeps the low `bits` bits
+    pub fn wrap(&self, value: &BigRational) -> BigRational {
+        let modulus = BigInt::one() << self.bits;
+        let mut value = value.trunc().to_integer() % &modulus;
+        if value.is_negative() {
+            value += &modulus;
+        }
+        if self.signed && value >= &modulus >> 1 {
+            value -= &modulus;
+        }
+        BigRational::from_integer(value)
+    }
+
+    // the bit pattern of a wrapped value, negative numbers in two's complement
+    fn unsigned(&self, value: &BigInt) -> BigInt {
+        if value.is_negative() {
+            value + (BigInt::one() << self.bits)
+        } else {
+            value.clone()
+        }
+    }
+}
+
+impl Number {
+    // a fraction in programmer mode is refused rather than cut off
+    pub fn from_literal(literal: &Literal, mode: NumberMode) -> Result<Number, &'static str> {
+        if literal.imaginary {
+            return Number::from_complex(Complex64::new(0.0, literal.float)).for_mode(mode);
+        }
+        Ok(match mode {
+            NumberMode::Float => Number::Float(literal.float),
+            NumberMode::Exact => Number::Rational(literal.exact.clone()),
+            NumberMode::Decimal(digits) => Number::Decimal(round_to(&literal.exact, digits), digits),
+            NumberMode::Programmer(_) if !literal.exact.is_integer() => return Err("programmer mode needs an integer"),
+            // a bit pattern such as `0xFF` may wrap to `-1`, a wider number
+            // is not wrapped, so `1 << 1000` is no shift by 232
+            NumberMode::Programmer(word) if literal.exact.numer().bits() > u64::from(word.bits) => {
+                return Err("number does not fit in the word size")
+            }
+            NumberMode::Programmer(word) => Number::Word(word.wrap(&literal.exact), word),
+        })
+    }
+
+    // a floating point result, of `sin(1)` or `pi`, in programmer mode is a
+    // word when it is a whole number, in decimal mode a decimal of the digits
+    // the float has; other modes take it as it is
+    pub fn for_mode(self, mode: NumberMode) -> Result<Number, &'static str> {
+        match (mode, self) {
+            (NumberMode::Decimal(digits), num) => Ok(num.into_decimal(digits)),
+            (NumberMode::Programmer(word), Number::Float(num)) if num.is_finite() && num.fract() == 0.0 => {
+                let value = BigRational::from_float(num).ok_or("programmer mode needs an integer")?;
+                Ok(Number::Word(word.wrap(&value), word))
+            }
+            (NumberMode::Programmer(_), Number::Float(_)) => Err("programmer mode needs an integer"),
+            (NumberMode::Programmer(_), Number::Complex(_)) => Err("programmer mode has no complex numbers"),
+            (_, num) => Ok(num),
+        }
+    }
+
+    // a float as a decimal with `digits` places, other numbers as they are
+    fn into_decimal(self, digits: u32) -> Number {
+        match &self {
+            Number::Float(num) => match float_to_rational(*num) {
+                Some(value) => Number::Decimal(round_to(&value, digits), digits),
+                None => self,
+            },
+            _ => self,
+        }
+    }
+
+    // real results stay real, so `(1+2i)*(1-2i)` prints as `5`
+    pub fn from_complex(num: Complex64) -> Number {
+        if num.im == 0.0 {
+            Number::Float(num.re)
+        } else {
+            Number::Complex(num)
+        }
+    }
+
+    // NaN for complex numbers, which have no real value
+    pub fn to_f64(&self) -> f64 {
+        match self {
+            Number::Float(num) => *num,
+            Number::Rational(num) | Number::Decimal(num, _) | Number::Word(num, _) => num.to_f64().unwrap_or(f64::NAN),
+            Number::Complex(_) => f64::NAN,
+        }
+    }
+
+    pub fn to_complex(&self) -> Complex64 {
+        match self {
+            Number::Complex(num) => *num,
+            _ => Complex64::new(self.to_f64(), 0.0),
+        }
+    }
+
+    // the exact value, `None` for floats
+    pub fn to_rational(&self) -> Option<&BigRational> {
+        match self {
+            Number::Rational(num) | Number::Decimal(num, _) | Number::Word(num, _) => Some(num),
+            Number::Float(_) | Number::Complex(_) => None,
+        }
+    }
+
+    pub fn is_complex(&self) -> bool {
+        matches!(self, Number::Complex(_))
+    }
+
+    // a floating point result past the largest f64
+    pub fn is_infinite(&self) -> bool {
+        match self {
+            Number::Float(num) => num.is_infinite(),
+            Number::Complex(num) => num.is_infinite(),
+            Number::Rational(_) | Number::Decimal(_, _) | Number::Word(_, _) => false,
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        match self {
+            Number::Float(num) => *num == 0.0,
+            Number::Rational(num) | Number::Decimal(num, _) | Number::Word(num, _) => num.is_zero(),
+            Number::Complex(num) => num.re == 0.0 && num.im == 0.0,
+        }
+    }
+
+    // wraps an exact result the same way as `self`, mixing in `other`'s precision;
+    // machine words win over decimals
+    fn exact_like(&self, other: Option<&Number>, value: BigRational) -> Number {
+        let word = [Some(self), other].into_iter().flatten().find_map(|num| match num {
+            Number::Word(_, word) => Some(*word),
+            _ => None,
+        });
+        if let Some(word) = word {
+            return Number::Word(word.wrap(&value), word);
+        }
+        let digits = [Some(self), other]
+            .into_iter()
+            .flatten()
+            .filter_map(|num| match num {
+                Number::Decimal(_, digits) => Some(*digits),
+                _ => None,
+            })
+            .max();
+        match digits {
+            Some(digits) => Number::Decimal(round_to(&value, digits), digits),
+            None => Number::Rational(value),
+        }
+    }
+
+    // a floating point result of an operation on a decimal is a decimal
+    fn float_like(&self, other: &Number, value: f64) -> Number {
+        let digits = [self, other]
+            .into_iter()
+            .filter_map(|num| match num {
+                Number::Decimal(_, digits) => Some(*digits),
+                _ => None,
+            })
+            .max();
+        match digits {
+            Some(digits) => Number::Float(value).into_decimal(digits),
+            None => Number::Float(value),
+        }
+    }
+
+    pub fn map_exact(&self, func: impl Fn(&BigRational) -> BigRational) -> Option<Number> {
+        self.to_rational().map(|num| self.exact_like(None, func(num)))
+    }
+
+    fn combine(
+        &self,
+        other: &Number,
+        float: impl Fn(f64, f64) -> f64,
+        exact: impl Fn(&BigRational, &BigRational) -> BigRational,
+        complex: impl Fn(Complex64, Complex64) -> Complex64,
+    ) -> Number {
+        if self.is_complex() || other.is_complex() {
+            return Number::from_complex(complex(self.to_complex(), other.to_complex()));
+        }
+        match (self.to_rational(), other.to_rational()) {
+            (Some(a), Some(b)) => self.exact_like(Some(other), exact(a, b)),
+            _ => self.float_like(other, float(self.to_f64(), other.to_f64())),
+        }
+    }
+
+    pub fn add(&self, other: &Number) -> Number {
+        self.combine(other, |a, b| a + b, |a, b| a + b, |a, b| a + b)
+    }
+
+    pub fn sub(&self, other: &Number) -> Number {
+        self.combine(other, |a, b| a - b, |a, b| a - b, |a, b| a - b)
+    }
+
+    pub fn mul(&self, other: &Number) -> Number {
+        self.combine(other, |a, b| a * b, |a, b| a * b, |a, b| a * b)
+    }
+
+    pub fn div(&self, other: &Number) -> Result<Number, &'static str> {
+        if other.is_zero() {
+            return Err("division by 0");
+        }
+        Ok(self.combine(other, |a, b| a / b, |a, b| a / b, |a, b| a / b))
+    }
+
+    fn complex_pow(&self, other: &Number) -> Result<Number, &'static str> {
+        let base = self.to_complex();
+        if self.is_zero() {
+            let exp = other.to_complex().re;
+            return if exp > 0.0 {
+                Ok(Number::Float(0.0))
+            } else if exp == 0.0 {
+                Ok(Number::Float(1.0))
+            } else {
+                Err("division by 0")
+            };
+        }
+        // repeated multiplication keeps `i^2` free of rounding noise
+        let exp = other.to_f64();
+        if exp.fract() == 0.0 && exp.abs() <= 1024.0 {
+            return Ok(Number::from_complex(base.powi(exp as i32)));
+        }
+        Ok(Number::from_complex(base.powc(other.to_complex())))
+    }
+
+    pub fn pow(&self, other: &Number) -> Result<Number, &'static str> {
+        if let (Some(base), Some(exp)) = (self.to_rational(), other.to_rational()) {
+            if exp.is_integer() {
+                let exp = exp.to_integer().to_i64().unwrap_or(i64::MAX);
+                check_exact_power(exact_bits(base), exp)?;
+                if base.is_zero() && exp < 0 {
+                    return Err("division by 0");
+                }
+                return Ok(self.exact_like(Some(other), base.pow(exp as i32)));
+            }
+        }
+        if self.is_complex() || other.is_complex() {
+            return self.complex_pow(other);
+        }
+
+        let (base, exp) = (self.to_f64(), other.to_f64());
+        let res = base.powf(exp);
+        // a negative base with a fractional exponent has a complex root
+        if res.is_nan() && !base.is_nan() && !exp.is_nan() {
+            return self.complex_pow(other);
+        }
+        Ok(self.float_like(other, res))
+    }
+
+    pub fn neg(&self) -> Number {
+        match self {
+            Number::Float(num) => Number::Float(-num),
+            Number::Rational(num) => Number::Rational(-num),
+            Number::Decimal(num, digits) => Number::Decimal(-num, *digits),
+            Number::Word(num, word) => Number::Word(word.wrap(&-num), *word),
+            Number::Complex(num) => Number::Complex(-num),
+        }
+    }
+
+    // floor division, `7 // 2` is 3 and `-7 // 2` is -4
+    pub fn int_div(&self, other: &Number) -> Result<Number, &'static str> {
+        if self.is_complex() || other.is_complex() {
+            return Err("'//' is not defined for complex numbers");
+        }
+        if other.is_zero() {
+            return Err("division by 0");
+        }
+        Ok(self.combine(other, |a, b| (a / b).floor(), |a, b| (a / b).floor(), |a, _| a))
+    }
+
+    // the remainder takes the sign of the divisor, so `-7 % 3` is 2
+    pub fn modulo(&self, other: &Number) -> Result<Number, &'static str> {
+        if self.is_complex() || other.is_complex() {
+            return Err("'%' is not defined for complex numbers");
+        }
+        if other.is_zero() {
+            return Err("division by 0");
+        }
+        Ok(self.combine(
+            other,
+            |a, b| a - b * (a / b).floor(),
+            |a, b| a - b * (a / b).floor(),
+            |a, _| a,
+        ))
+    }
+
+    fn to_integer(&self) -> Option<BigInt> {
+        match self {
+            Number::Float(num) if num.fract() == 0.0 => BigInt::from_f64(*num),
+            _ => self.to_rational().filter(|num| num.is_integer()).map(|num| num.to_integer()),
+        }
+    }
+
+    // bitwise operators work on integers of any mode; float operands give a
+    // float back
+    fn integer_op(
+        &self,
+        other: &Number,
+        op: impl Fn(&BigInt, &BigInt) -> Result<BigInt, &'static str>,
+    ) -> Result<Number, &'static str> {
+        let (a, b) = match (self.to_integer(), other.to_integer()) {
+            (Some(a), Some(b)) => (a, b),
+            _ => return Err("bitwise operators need integers"),
+        };
+        let res = BigRational::from_integer(op(&a, &b)?);
+        if matches!(self, Number::Float(_)) || matches!(other, Number::Float(_)) {
+            return Ok(Number::Float(res.to_f64().unwrap_or(f64::NAN)));
+        }
+        Ok(self.exact_like(Some(other), res))
+    }
+
+    pub fn bit_and(&self, other: &Number) -> Result<Number, &'static str> {
+        self.integer_op(other, |a, b| Ok(a & b))
+    }
+
+    pub fn bit_or(&self, other: &Number) -> Result<Number, &'static str> {
+        self.integer_op(other, |a, b| Ok(a | b))
+    }
+
+    pub fn bit_xor(&self, other: &Number) -> Result<Number, &'static str> {
+        self.integer_op(other, |a, b| Ok(a ^ b))
+    }
+
+    pub fn shift_left(&self, other: &Number) -> Result<Number, &'static str> {
+        self.integer_op(other, |a, b| Ok(a << shift_amount(b)?))
+    }
+
+    pub fn shift_right(&self, other: &Number) -> Result<Number, &'static str> {
+        self.integer_op(other, |a, b| Ok(a >> shift_amount(b)?))
+    }
+
+    pub fn bit_not(&self) -> Result<Number, &'static str> {
+        self.integer_op(self, |a, _| Ok(-a - 1))
+    }
+
+    // exact for whole numbers in the exact modes, through the gamma function
+    // for everything else
+    pub fn factorial(&self) -> Result<Number, &'static str> {
+        if self.is_complex() {
+            return Err("'!' is not defined for complex numbers");
+        }
+        let x = self.to_f64();
+        if x < 0.0 && x.fract() == 0.0 {
+            return Err("factorial of a negative integer");
+        }
+        if let Some(n) = self.to_rational().filter(|num| num.is_integer()) {
+            let n = match n.to_integer().to_u64() {
+                Some(n) if n <= MAX_EXACT_FACTORIAL => n,
+                _ => return Err("factorial is too large for exact arithmetic"),
+            };
+            let res = (2..=n).fold(BigInt::one(), |acc, k| acc * k);
+            return Ok(self.exact_like(None, BigRational::from_integer(res)));
+        }
+        Ok(Number::Float(gamma(x + 1.0)))
+    }
+
+    // complex numbers are not ordered
+    pub fn compare(&self, other: &Number) -> Option<Ordering> {
+        if self.is_complex() || other.is_complex() {
+            return None;
+        }
+        match (self.to_rational(), other.to_rational()) {
+            (Some(a), Some(b)) => Some(a.cmp(b)),
+            _ => self.to_f64().partial_cmp(&other.to_f64()),
+        }
+    }
+
+    // exact square root when it exists: perfect squares of rationals, or the
+    // decimal expansion cut at the number's precision
+    pub fn sqrt(&self) -> Option<Number> {
+        let num = self.to_rational()?;
+        if num.is_negative() {
+            return None;
+        }
+        match self {
+            Number::Decimal(_, digits) => {
+                // one guard digit, then round back
+                let scale = pow10(2 * (digits + 1));
+                let root = (num * BigRational::from_integer(scale)).to_integer().sqrt();
+                let root = BigRational::new(root, pow10(digits + 1));
+                Some(Number::Decimal(round_to(&root, *digits), *digits))
+            }
+            Number::Word(_, word) => {
+                Some(Number::Word(BigRational::from_integer(num.to_integer().sqrt()), *word))
+            }
+            _ => {
+                let numer = num.numer().sqrt();
+                let denom = num.denom().sqrt();
+                if &(&numer * &numer) == num.numer() && &(&denom * &denom) == num.denom() {
+                    Some(Number::Rational(BigRational::new(numer, denom)))
+                } else {
+                    None
+                }
+            }
+        }
+    }
+}
+
+// an exact whole number, which adopts the kind of whatever it is combined with
+pub fn integer(value: i64) -> Number {
+    Number::Rational(BigRational::from_integer(BigInt::from(value)))
+}
+
+fn shift_amount(shift: &BigInt) -> Result<usize, &'static str> {
+    if shift.is_negative() {
+        return Err("negative shift");
+    }
+    match shift.to_usize() {
+        Some(shift) if shift as i64 <= MAX_EXACT_EXPONENT => Ok(shift),
+        _ => Err("shift is too large"),
+    }
+}
+
+// exact versions of the builtins that make sense on rationals, `None` falls
+// back to the floating point implementation
+pub fn exact_builtin(name: &str, args: &[Number]) -> Option<Number> {
+    if args.iter().any(|num| num.to_rational().is_none()) {
+        return None;
+    }
+
+    match (name, args) {
+        ("abs", [x]) => x.map_exact(|num| num.abs()),
+        ("sign", [x]) => x.map_exact(|num| BigRational::from_integer(num.numer().signum())),
+        ("floor", [x]) => x.map_exact(|num| num.floor()),
+        ("ceil", [x]) => x.map_exact(|num| num.ceil()),
+        ("trunc", [x]) => x.map_exact(|num| num.trunc()),
+        ("round", [x]) => x.map_exact(|num| num.round()),
+        ("round", [x, digits]) => {
+            let digits = digits.to_rational()?;
+            if !digits.is_integer() || digits.is_negative() {
+                return None;
+            }
+            let digits = digits.to_integer().to_u32()?;
+            x.map_exact(|num| round_to(num, digits))
+        }
+        ("sqrt", [x]) => x.sqrt(),
+        ("min", _) => args
+            .iter()
+            .min_by(|a, b| a.compare(b).unwrap_or(Ordering::Equal))
+            .cloned(),
+        ("max", _) => args
+            .iter()
+            .max_by(|a, b| a.compare(b).unwrap_or(Ordering::Equal))
+            .cloned(),
+        _ => None,
+    }
+}
+
+pub(crate) fn format_decimal(value: &BigRational, digits: u32) -> String {
+    let scaled = (value.abs() * BigRational::from_integer(pow10(digits))).round().to_integer();
+    let mut text = scaled.to_string();
+    let digits = digits as usize;
+    if text.len() <= digits {
+        text = format!("{}{text}", "0".repeat(digits + 1 - text.len()));
+    }
+    let (int_part, frac_part) = text.split_at(text.len() - digits);
+    let frac_part = frac_part.trim_end_matches('0');
+
+    let sign = if value.is_negative() && (int_part != "0" || !frac_part.is_empty()) { "-" } else { "" };
+    if frac_part.is_empty() {
+        format!("{sign}{int_part}")
+    } else {
+        format!("{sign}{int_part}.{frac_part}")
+    }
+}
+
+// `255 (0xFF, 0o377, 0b11111111)`
+fn format_word(value: &BigRational, word: &WordSize) -> String {
+    let value = value.to_integer();
+    let bits = word.unsigned(&value);
+    format!(
+        "{value} (0x{}, 0o{}, 0b{})",
+        bits.to_str_radix(16).to_uppercase(),
+        bits.to_str_radix(8),
+        bits.to_str_radix(2)
+    )
+}
+
+// `1+2i`, with the parts written by `part`
+pub(crate) fn format_complex(num: &Complex64, part: impl Fn(f64) -> String) -> String {
+    let im = if num.im == 1.0 {
+        String::from("i")
+    } else if num.im == -1.0 {
+        String::from("-i")
+    } else {
+        format!("{}i", part(num.im))
+    };
+    if num.re == 0.0 {
+        im
+    } else if im.starts_with('-') {
+        format!("{}{im}", part(num.re))
+    } else {
+        format!("{}+{im}", part(num.re))
+    }
+}
+
+impl fmt::Display for Number {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Number::Float(num) => write!(f, "{num}"),
+            Number::Rational(num) if num.denom().is_one() => write!(f, "{}", num.numer()),
+            Number::Rational(num) => write!(f, "{}/{}", num.numer(), num.denom()),
+            Number::Decimal(num, digits) => write!(f, "{}", format_decimal(num, *digits)),
+            Number::Word(num, word) => write!(f, "{}", format_word(num, word)),
+            Number::Complex(num) => write!(f, "{}", format_complex(num, |part| part.to_string())),
+        }
+    }
+}
@@ -0,0 +1,461 @@
+use crate::engine::ast::{Expr, ExprKind, Target};
+use crate::engine::error::{CalcError, Span, Stage};
+use crate::engine::token::{Token, TokenKind, CONDITIONAL_PRIORITY};
+use crate::engine::trace::ParseStep;
+
+// the innermost open group on the operator stack is a `[`
+fn is_open_bracket(expr_ops: &[Token]) -> bool {
+    matches!(expr_ops.last(), Some(Token { kind: TokenKind::LBracket, .. }))
+}
+
+// the `(` on top of the operator stack was opened by a function call
+fn is_call_paren(expr_ops: &[Token]) -> bool {
+    match expr_ops {
+        [.., call, paren] => {
+            paren.kind == TokenKind::LParen && matches!(call.kind, TokenKind::Call(_, _))
+        }
+        _ => false,
+    }
+}
+
+// stops at a `(` or a `[`, whichever is innermost
+fn pop_until_lparen(expr_ops: &mut Vec<Token>, rpn_expr: &mut Vec<Token>) -> bool {
+    while let Some(top) = expr_ops.last() {
+        if matches!(top.kind, TokenKind::LParen | TokenKind::LBracket) {
+            return true;
+        }
+        rpn_expr.extend(expr_ops.pop());
+    }
+    false
+}
+
+// moves the operators that bind tighter than one of `priority` to the output
+fn pop_operators(expr_ops: &mut Vec<Token>, rpn_expr: &mut Vec<Token>, priority: i8, right_associative: bool) {
+    while let Some(top) = expr_ops.last() {
+        let top_priority = top.kind.priority();
+        if top_priority > priority || (top_priority == priority && !right_associative) {
+            rpn_expr.extend(expr_ops.pop());
+        } else {
+            break;
+        }
+    }
+}
+
+pub fn parse_tokens_to_rpn(tokens: &[Token]) -> Result<Vec<Token>, CalcError> {
+    shunting_yard(tokens, None)
+}
+
+// the same, recording the operator stack and the output after every token
+pub fn trace_tokens_to_rpn(tokens: &[Token], steps: &mut Vec<ParseStep>) -> Result<Vec<Token>, CalcError> {
+    shunting_yard(tokens, Some(steps))
+}
+
+fn shunting_yard(tokens: &[Token], mut steps: Option<&mut Vec<ParseStep>>) -> Result<Vec<Token>, CalcError> {
+    let mut expr_ops: Vec<Token> = Vec::new();
+    let mut rpn_expr: Vec<Token> = Vec::new();
+    // true while the next token has to start an operand
+    let mut expect_operand = true;
+    let mut prev_token: Option<&Token> = None;
+
+    for (i, token) in tokens.iter().enumerate() {
+        match &token.kind {
+            TokenKind::Number(_) => {
+                if !expect_operand {
+                    return Err(CalcError::rpn("missing operator", token.span));
+                }
+                rpn_expr.push(token.clone());
+                expect_operand = false;
+            }
+            TokenKind::Ident(name) => {
+                if !expect_operand {
+                    // `2 x` or `2pi`, which the lexer did not read as a unit
+                    let message = match prev_token {
+                        Some(Token { kind: TokenKind::Number(_), .. }) => {
+                            format!("'{name}' is not a unit, use '*' to multiply by it")
+                        }
+                        _ => String::from("missing operator"),
+                    };
+                    return Err(CalcError::rpn(message, token.span));
+                }
+                match tokens.get(i + 1) {
+                    Some(next) if next.kind == TokenKind::LParen => {
+                        expr_ops.push(Token::new(TokenKind::Call(name.clone(), 1), token.span));
+                    }
+                    _ => {
+                        rpn_expr.push(token.clone());
+                        expect_operand = false;
+                    }
+                }
+            }
+            TokenKind::UnOp(_) | TokenKind::LParen => {
+                if !expect_operand {
+                    return Err(CalcError::rpn("missing operator", token.span));
+                }
+                expr_ops.push(token.clone());
+            }
+            // the `Vector` below the `[` counts the elements, as `Call` does
+            // the arguments
+            TokenKind::LBracket => {
+                if !expect_operand {
+                    return Err(CalcError::rpn("missing operator", token.span));
+                }
+                expr_ops.push(Token::new(TokenKind::Vector(1), token.span));
+                expr_ops.push(token.clone());
+            }
+            TokenKind::Comma => {
+                if expect_operand {
+                    return Err(CalcError::rpn("missing argument", token.span));
+                }
+                if !pop_until_lparen(&mut expr_ops, &mut rpn_expr)
+                    || !(is_call_paren(&expr_ops) || is_open_bracket(&expr_ops))
+                {
+                    return Err(CalcError::rpn("',' outside of a function call", token.span));
+                }
+                let call = expr_ops.len() - 2;
+                if let TokenKind::Call(_, count) | TokenKind::Vector(count) = &mut expr_ops[call].kind {
+                    *count += 1;
+                }
+                expect_operand = true;
+            }
+            TokenKind::RBracket => {
+                if expect_operand {
+                    let after_lbracket = matches!(prev_token, Some(prev) if prev.kind == TokenKind::LBracket);
+                    let message = if after_lbracket { "empty vector" } else { "missing operand" };
+                    return Err(CalcError::rpn(message, token.span));
+                }
+                if !pop_until_lparen(&mut expr_ops, &mut rpn_expr) || !is_open_bracket(&expr_ops) {
+                    return Err(CalcError::rpn("unbalanced brackets: missing '['", token.span));
+                }
+                expr_ops.pop();
+                let vector = expr_ops.pop().unwrap();
+                rpn_expr.push(Token::new(vector.kind, vector.span.join(token.span)));
+                expect_operand = false;
+            }
+            TokenKind::RParen => {
+                let after_lparen = matches!(prev_token, Some(prev) if prev.kind == TokenKind::LParen);
+                if expect_operand && !(after_lparen && is_call_paren(&expr_ops)) {
+                    let message = if after_lparen {
+                        "empty parentheses"
+                    } else if is_call_paren(&expr_ops) {
+                        "missing argument"
+                    } else {
+                        "missing operand"
+                    };
+                    return Err(CalcError::rpn(message, token.span));
+                }
+                if !pop_until_lparen(&mut expr_ops, &mut rpn_expr) {
+                    return Err(CalcError::rpn("unbalanced parentheses: missing '('", token.span));
+                }
+                if is_open_bracket(&expr_ops) {
+                    return Err(CalcError::rpn("unbalanced brackets: missing ']'", token.span));
+                }
+                expr_ops.pop();
+
+                if let Some(Token { kind: TokenKind::Call(_, _), .. }) = expr_ops.last() {
+                    let call = expr_ops.pop().unwrap();
+                    if let TokenKind::Call(name, argc) = call.kind {
+                        let argc = if after_lparen { 0 } else { argc };
+                        rpn_expr.push(Token::new(
+                            TokenKind::Call(name, argc),
+                            call.span.join(token.span),
+                        ));
+                    }
+                }
+                expect_operand = false;
+            }
+            TokenKind::BinOp(op) => {
+                if expect_operand {
+                    return Err(CalcError::rpn(
+                        format!("missing operand for '{}'", op.symbol()),
+                        token.span,
+                    ));
+                }
+                pop_operators(&mut expr_ops, &mut rpn_expr, op.priority(), op.is_right_associative());
+                expr_ops.push(token.clone());
+                expect_operand = true;
+            }
+            TokenKind::Question => {
+                if expect_operand {
+                    return Err(CalcError::rpn("missing condition for '?'", token.span));
+                }
+                pop_operators(&mut expr_ops, &mut rpn_expr, CONDITIONAL_PRIORITY, true);
+                expr_ops.push(token.clone());
+                expect_operand = true;
+            }
+            // closes the value of `cond ? a`; the `:` takes the place of its
+            // `?` and builds the whole conditional once `b` is read
+            TokenKind::Colon => {
+                if expect_operand {
+                    return Err(CalcError::rpn("missing operand for ':'", token.span));
+                }
+                while let Some(top) = expr_ops.last() {
+                    if matches!(top.kind, TokenKind::Question | TokenKind::LParen | TokenKind::LBracket) {
+                        break;
+                    }
+                    rpn_expr.extend(expr_ops.pop());
+                }
+                match expr_ops.last() {
+                    Some(Token { kind: TokenKind::Question, .. }) => {
+                        expr_ops.pop();
+                        expr_ops.push(token.clone());
+                    }
+                    _ => return Err(CalcError::rpn("':' without '?'", token.span)),
+                }
+                expect_operand = true;
+            }
+            // postfix operators bind tightest, so they go straight to the output
+            TokenKind::PostOp(op) => {
+                if expect_operand {
+                    return Err(CalcError::rpn(
+                        format!("missing operand for '{}'", op.symbol()),
+                        token.span,
+                    ));
+                }
+                rpn_expr.push(token.clone());
+            }
+            TokenKind::Convert(_) => {
+                if expect_operand {
+                    return Err(CalcError::rpn("missing value to convert", token.span));
+                }
+                // binds looser than every operator, up to the enclosing `(`
+                pop_until_lparen(&mut expr_ops, &mut rpn_expr);
+                rpn_expr.push(token.clone());
+            }
+            // an equation, only inside the arguments of a call such as `solve`
+            TokenKind::Assign => {
+                if expect_operand {
+                    return Err(CalcError::rpn("missing operand for '='", token.span));
+                }
+                if !pop_until_lparen(&mut expr_ops, &mut rpn_expr) || !is_call_paren(&expr_ops) {
+                    return Err(CalcError::rpn("unexpected '='", token.span));
+                }
+                expr_ops.push(token.clone());
+                expect_operand = true;
+            }
+            TokenKind::Call(_, _) | TokenKind::Vector(_) => {
+                return Err(CalcError::rpn("unexpected token", token.span));
+            }
+        }
+        prev_token = Some(token);
+        if let Some(steps) = steps.as_deref_mut() {
+            steps.push(ParseStep::new(&token.to_string(), &expr_ops, &rpn_expr));
+        }
+    }
+
+    if let Some(last) = prev_token {
+        if expect_operand {
+            return Err(CalcError::rpn("missing operand", last.span));
+        }
+    } else {
+        return Err(CalcError::new(Stage::Rpn, "empty expression", None));
+    }
+
+    while let Some(op) = expr_ops.pop() {
+        if op.kind == TokenKind::LParen {
+            return Err(CalcError::rpn("unbalanced parentheses: missing ')'", op.span));
+        }
+        if op.kind == TokenKind::LBracket {
+            return Err(CalcError::rpn("unbalanced brackets: missing ']'", op.span));
+        }
+        rpn_expr.push(op);
+    }
+
+    if let Some(steps) = steps {
+        steps.push(ParseStep::new("end", &expr_ops, &rpn_expr));
+    }
+    Ok(rpn_expr)
+}
+
+// how deep a tree may nest. Evaluation, printing and differentiation recurse
+// over the tree, so a deeper one, such as a sum of thousands of terms, would
+// overflow the stack instead of failing
+pub static MAX_DEPTH: usize = 256;
+
+// the operands built so far, each with the depth of its tree
+type Operands = Vec<(Expr, usize)>;
+
+fn pop_operand(stack: &mut Operands, token: &Token) -> Result<(Expr, usize), CalcError> {
+    stack
+        .pop()
+        .ok_or_else(|| CalcError::rpn(format!("missing operand for '{token}'"), token.span))
+}
+
+fn push_operand(stack: &mut Operands, expr: Expr, depth: usize) -> Result<(), CalcError> {
+    if depth > MAX_DEPTH {
+        return Err(CalcError::rpn("expression is too deeply nested", expr.span));
+    }
+    stack.push((expr, depth));
+    Ok(())
+}
+
+// the arguments of a call or the elements of a vector, and the deepest of them
+fn pop_operands(stack: &mut Operands, count: usize) -> (Vec<Expr>, usize) {
+    let items = stack.split_off(stack.len() - count);
+    let depth = items.iter().map(|(_, depth)| *depth).max().unwrap_or(0);
+    (items.into_iter().map(|(item, _)| item).collect(), depth)
+}
+
+fn conditional(cond: Expr, then: Expr, otherwise: Expr, span: Span) -> Expr {
+    Expr::new(ExprKind::Conditional(Box::new(cond), Box::new(then), Box::new(otherwise)), span)
+}
+
+pub fn build_tree(rpn: &[Token]) -> Result<Expr, CalcError> {
+    let mut stack: Operands = Vec::new();
+
+    for token in rpn {
+        match &token.kind {
+            TokenKind::Number(literal) => {
+                push_operand(&mut stack, Expr::new(ExprKind::Number(literal.clone()), token.span), 1)?
+            }
+            TokenKind::Ident(name) => push_operand(&mut stack, Expr::new(ExprKind::Var(name.clone()), token.span), 1)?,
+            TokenKind::UnOp(op) => {
+                let (arg, depth) = pop_operand(&mut stack, token)?;
+                let span = token.span.join(arg.span);
+                push_operand(&mut stack, Expr::new(ExprKind::Unary(*op, Box::new(arg)), span), depth + 1)?;
+            }
+            TokenKind::PostOp(op) => {
+                let (arg, depth) = pop_operand(&mut stack, token)?;
+                let span = arg.span.join(token.span);
+                push_operand(&mut stack, Expr::new(ExprKind::Postfix(*op, Box::new(arg)), span), depth + 1)?;
+            }
+            TokenKind::BinOp(op) => {
+                let (rhs, rhs_depth) = pop_operand(&mut stack, token)?;
+                let (lhs, lhs_depth) = pop_operand(&mut stack, token)?;
+                let span = lhs.span.join(rhs.span);
+                push_operand(
+                    &mut stack,
+                    Expr::new(ExprKind::Binary(*op, Box::new(lhs), Box::new(rhs)), span),
+                    lhs_depth.max(rhs_depth) + 1,
+                )?;
+            }
+            // `if(cond, a, b)` is another way to write `cond ? a : b`
+            TokenKind::Call(name, argc) if name == "if" => {
+                if *argc != 3 {
+                    return Err(CalcError::rpn(format!("if expects 3 arguments, got {argc}"), token.span));
+                }
+                let (otherwise, otherwise_depth) = pop_operand(&mut stack, token)?;
+                let (then, then_depth) = pop_operand(&mut stack, token)?;
+                let (cond, cond_depth) = pop_operand(&mut stack, token)?;
+                let depth = cond_depth.max(then_depth).max(otherwise_depth) + 1;
+                push_operand(&mut stack, conditional(cond, then, otherwise, token.span), depth)?;
+            }
+            TokenKind::Call(name, argc) => {
+                if stack.len() < *argc {
+                    return Err(CalcError::rpn(format!("missing arguments for '{name}'"), token.span));
+                }
+                let (args, depth) = pop_operands(&mut stack, *argc);
+                push_operand(&mut stack, Expr::new(ExprKind::Call(name.clone(), args), token.span), depth + 1)?;
+            }
+            TokenKind::Vector(len) => {
+                if stack.len() < *len {
+                    return Err(CalcError::rpn("missing elements for a vector", token.span));
+                }
+                let (items, depth) = pop_operands(&mut stack, *len);
+                push_operand(&mut stack, Expr::new(ExprKind::Vector(items), token.span), depth + 1)?;
+            }
+            TokenKind::Convert(unit) => {
+                let (value, depth) = pop_operand(&mut stack, token)?;
+                let span = value.span.join(token.span);
+                push_operand(
+                    &mut stack,
+                    Expr::new(ExprKind::Convert(Box::new(value), unit.clone(), token.span), span),
+                    depth + 1,
+                )?;
+            }
+            TokenKind::Assign => {
+                let (rhs, rhs_depth) = pop_operand(&mut stack, token)?;
+                let (lhs, lhs_depth) = pop_operand(&mut stack, token)?;
+                let span = lhs.span.join(rhs.span);
+                push_operand(
+                    &mut stack,
+                    Expr::new(ExprKind::Equation(Box::new(lhs), Box::new(rhs)), span),
+                    lhs_depth.max(rhs_depth) + 1,
+                )?;
+            }
+            TokenKind::Colon => {
+                let (otherwise, otherwise_depth) = pop_operand(&mut stack, token)?;
+                let (then, then_depth) = pop_operand(&mut stack, token)?;
+                let (cond, cond_depth) = pop_operand(&mut stack, token)?;
+                let span = cond.span.join(otherwise.span);
+                let depth = cond_depth.max(then_depth).max(otherwise_depth) + 1;
+                push_operand(&mut stack, conditional(cond, then, otherwise, span), depth)?;
+            }
+            TokenKind::Question => return Err(CalcError::rpn("missing ':' for '?'", token.span)),
+            TokenKind::LParen
+            | TokenKind::RParen
+            | TokenKind::LBracket
+            | TokenKind::RBracket
+            | TokenKind::Comma => {
+                return Err(CalcError::rpn(format!("unexpected '{token}'"), token.span));
+            }
+        }
+    }
+
+    let tree = stack.pop();
+    match (tree, stack.pop()) {
+        (Some((tree, _)), None) => Ok(tree),
+        (Some((tree, _)), Some(_)) => Err(CalcError::rpn("missing operator", tree.span)),
+        (None, _) => Err(CalcError::new(Stage::Rpn, "empty expression", None)),
+    }
+}
+
+fn parse_params(params: &[Token]) -> Result<Vec<String>, CalcError> {
+    let mut names: Vec<String> = Vec::new();
+
+    for (i, token) in params.iter().enumerate() {
+        match &token.kind {
+            TokenKind::Ident(name) if i % 2 == 0 => {
+                if names.contains(name) {
+                    return Err(CalcError::rpn(format!("duplicate parameter '{name}'"), token.span));
+                }
+                names.push(name.clone());
+            }
+            TokenKind::Comma if i % 2 == 1 && i + 1 < params.len() => {}
+            _ => return Err(CalcError::rpn("expected a parameter name", token.span)),
+        }
+    }
+
+    Ok(names)
+}
+
+// splits `name = expr` and `name(a, b) = expr` into the target and the tokens
+// of its value
+pub fn split_assignment(tokens: &[Token]) -> Result<(Option<Target>, &[Token]), CalcError> {
+    let mut depth = 0;
+    let mut assign = None;
+    for (i, token) in tokens.iter().enumerate() {
+        match token.kind {
+            TokenKind::LParen | TokenKind::LBracket => depth += 1,
+            TokenKind::RParen | TokenKind::RBracket => depth -= 1,
+            TokenKind::Assign if depth == 0 => {
+                assign = Some(i);
+                break;
+            }
+            _ => {}
+        }
+    }
+    let assign = match assign {
+        Some(assign) => assign,
+        None => return Ok((None, tokens)),
+    };
+
+    let (head, rest) = (&tokens[..assign], &tokens[assign + 1..]);
+    let span = tokens[assign].span;
+    if rest.is_empty() {
+        return Err(CalcError::rpn("missing value to assign", span));
+    }
+
+    let target = match head {
+        [Token { kind: TokenKind::Ident(name), .. }] => Target::Var(name.clone()),
+        [Token { kind: TokenKind::Ident(name), .. }, Token { kind: TokenKind::LParen, .. }, params @ .., Token { kind: TokenKind::RParen, .. }] => {
+            Target::Func(name.clone(), parse_params(params)?)
+        }
+        _ => {
+            return Err(CalcError::rpn(
+                "can only assign to a variable or a function signature",
+                span,
+            ))
+        }
+    };
+    Ok((Some(target), rest))
+}
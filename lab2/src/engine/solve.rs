@@ -0,0 +1,167 @@
+use crate::engine::error::{CalcError, Span};
+
+pub static MAX_ITERATIONS: usize = 100;
+static TOLERANCE: f64 = 1e-12;
+
+type Function<'a> = &'a dyn Fn(f64) -> Result<f64, CalcError>;
+type System<'a> = &'a dyn Fn(&[f64]) -> Result<Vec<f64>, CalcError>;
+
+fn converged(step: f64, x: f64) -> bool {
+    step.abs() <= TOLERANCE * (1.0 + x.abs())
+}
+
+fn central_difference(f: Function, x: f64) -> Result<f64, CalcError> {
+    let h = 1e-6 * (1.0 + x.abs());
+    Ok((f(x + h)? - f(x - h)?) / (2.0 * h))
+}
+
+// Newton's method from `x0`; without a symbolic derivative the slope is
+// estimated numerically
+pub fn newton(f: Function, df: Option<Function>, x0: f64, var: &str, span: Span) -> Result<f64, CalcError> {
+    let mut x = x0;
+    for _ in 0..MAX_ITERATIONS {
+        let fx = f(x)?;
+        if !fx.is_finite() {
+            return Err(CalcError::calculate(format!("solve: the equation is undefined at {var} = {x}"), span));
+        }
+        if fx == 0.0 {
+            return Ok(x);
+        }
+        let slope = match df {
+            Some(df) => df(x)?,
+            None => central_difference(f, x)?,
+        };
+        if slope == 0.0 || !slope.is_finite() {
+            return Err(CalcError::calculate(
+                format!("solve: the derivative vanishes at {var} = {x}, try another starting value"),
+                span,
+            ));
+        }
+        let step = fx / slope;
+        x -= step;
+        if converged(step, x) {
+            return Ok(x);
+        }
+    }
+    Err(CalcError::calculate(
+        format!(
+            "solve: no convergence after {MAX_ITERATIONS} steps from {var} = {x0}, \
+             try another starting value or an interval solve(f, {var}, a, b)"
+        ),
+        span,
+    ))
+}
+
+// bisection of `[a, b]`, which has to contain a sign change
+pub fn bisect(f: Function, a: f64, b: f64, var: &str, span: Span) -> Result<f64, CalcError> {
+    let (mut lo, mut hi) = (a.min(b), a.max(b));
+    let (mut f_lo, f_hi) = (f(lo)?, f(hi)?);
+    if f_lo == 0.0 {
+        return Ok(lo);
+    }
+    if f_hi == 0.0 {
+        return Ok(hi);
+    }
+    if !(f_lo.is_finite() && f_hi.is_finite()) || f_lo.signum() == f_hi.signum() {
+        return Err(CalcError::calculate(
+            format!("solve: the equation does not change sign between {var} = {lo} and {var} = {hi}"),
+            span,
+        ));
+    }
+
+    // every step halves the interval, so this ends well before the budget
+    for _ in 0..4 * MAX_ITERATIONS {
+        let mid = lo + (hi - lo) / 2.0;
+        if converged(hi - lo, mid) || mid == lo || mid == hi {
+            return Ok(mid);
+        }
+        let f_mid = f(mid)?;
+        if f_mid == 0.0 {
+            return Ok(mid);
+        }
+        if f_mid.signum() == f_lo.signum() {
+            lo = mid;
+            f_lo = f_mid;
+        } else {
+            hi = mid;
+        }
+    }
+    Ok(lo + (hi - lo) / 2.0)
+}
+
+// solves `a * x = b` by Gaussian elimination with partial pivoting
+fn solve_linear(mut a: Vec<Vec<f64>>, mut b: Vec<f64>) -> Option<Vec<f64>> {
+    let n = b.len();
+    for col in 0..n {
+        let pivot = (col..n).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))?;
+        if a[pivot][col].abs() < 1e-300 {
+            return None;
+        }
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+        let (upper, lower) = a.split_at_mut(col + 1);
+        let pivot_row = &upper[col];
+        for (offset, row) in lower.iter_mut().enumerate() {
+            let factor = row[col] / pivot_row[col];
+            for (value, pivot_value) in row[col..].iter_mut().zip(&pivot_row[col..]) {
+                *value -= factor * pivot_value;
+            }
+            b[col + 1 + offset] -= factor * b[col];
+        }
+    }
+
+    let mut x = vec![0.0; n];
+    for row in (0..n).rev() {
+        let sum: f64 = (row + 1..n).map(|k| a[row][k] * x[k]).sum();
+        x[row] = (b[row] - sum) / a[row][row];
+    }
+    Some(x)
+}
+
+// Newton's method for `f(x) = 0` with `f` from R^n to R^n, the Jacobian is
+// estimated by central differences
+pub fn newton_system(f: System, x0: Vec<f64>, span: Span) -> Result<Vec<f64>, CalcError> {
+    let n = x0.len();
+    let mut x = x0;
+    for _ in 0..MAX_ITERATIONS {
+        let fx = f(&x)?;
+        if fx.iter().any(|value| !value.is_finite()) {
+            return Err(CalcError::calculate(format!("nsolve: the equations are undefined at {x:?}"), span));
+        }
+        if fx.iter().all(|value| *value == 0.0) {
+            return Ok(x);
+        }
+
+        let mut jacobian = vec![vec![0.0; n]; n];
+        for j in 0..n {
+            let h = 1e-6 * (1.0 + x[j].abs());
+            let (mut ahead, mut behind) = (x.clone(), x.clone());
+            ahead[j] += h;
+            behind[j] -= h;
+            let (f_ahead, f_behind) = (f(&ahead)?, f(&behind)?);
+            for i in 0..n {
+                jacobian[i][j] = (f_ahead[i] - f_behind[i]) / (2.0 * h);
+            }
+        }
+
+        let step = match solve_linear(jacobian, fx.iter().map(|value| -value).collect()) {
+            Some(step) => step,
+            None => {
+                return Err(CalcError::calculate(
+                    format!("nsolve: the equations are degenerate at {x:?}, try other starting values"),
+                    span,
+                ))
+            }
+        };
+        for (xi, dx) in x.iter_mut().zip(&step) {
+            *xi += dx;
+        }
+        if x.iter().zip(&step).all(|(xi, dx)| converged(*dx, *xi)) {
+            return Ok(x);
+        }
+    }
+    Err(CalcError::calculate(
+        format!("nsolve: no convergence after {MAX_ITERATIONS} steps, try other starting values"),
+        span,
+    ))
+}
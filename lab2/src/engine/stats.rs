@@ -0,0 +1,75 @@
+use std::cmp::Ordering;
+
+use crate::engine::number::{integer, Number};
+
+fn sum(values: &[Number]) -> Number {
+    values.iter().fold(integer(0), |sum, value| sum.add(value))
+}
+
+fn mean(values: &[Number]) -> Result<Number, String> {
+    Ok(sum(values).div(&integer(values.len() as i64))?)
+}
+
+// the sample variance, divided by n - 1
+fn variance(values: &[Number]) -> Result<Number, String> {
+    if values.len() < 2 {
+        return Err(String::from("needs at least 2 values"));
+    }
+    let mean = mean(values)?;
+    let squares: Vec<Number> = values
+        .iter()
+        .map(|value| {
+            let diff = value.sub(&mean);
+            diff.mul(&diff)
+        })
+        .collect();
+    Ok(sum(&squares).div(&integer(values.len() as i64 - 1))?)
+}
+
+fn sorted(values: &[Number]) -> Result<Vec<Number>, String> {
+    if values.iter().any(Number::is_complex) {
+        return Err(String::from("complex numbers cannot be ordered"));
+    }
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.compare(b).unwrap_or(Ordering::Equal));
+    Ok(sorted)
+}
+
+// linear interpolation between the two nearest ranks, so the 50th percentile
+// is the median
+fn percentile(values: &[Number], p: &Number) -> Result<Number, String> {
+    let share = p.to_f64();
+    if p.is_complex() || !(0.0..=100.0).contains(&share) {
+        return Err(String::from("the percentile must be between 0 and 100"));
+    }
+    let sorted = sorted(values)?;
+    let rank = p.mul(&integer(sorted.len() as i64 - 1)).div(&integer(100))?;
+    let low = (rank.to_f64().floor() as usize).min(sorted.len() - 1);
+    let high = (low + 1).min(sorted.len() - 1);
+    let frac = rank.sub(&integer(low as i64));
+    Ok(sorted[low].add(&sorted[high].sub(&sorted[low]).mul(&frac)))
+}
+
+// `name` over `values`; `percentile` takes the percentage as its last value
+pub fn statistic(name: &str, values: &[Number]) -> Result<Number, String> {
+    let (values, p) = match name {
+        "percentile" => match values.split_last() {
+            Some((p, values)) => (values, Some(p)),
+            None => (values, None),
+        },
+        _ => (values, None),
+    };
+    if values.is_empty() {
+        return Err(format!("{name} needs at least one value"));
+    }
+    let res = match (name, p) {
+        ("sum", _) => Ok(sum(values)),
+        ("mean", _) => mean(values),
+        ("median", _) => percentile(values, &integer(50)),
+        ("var", _) => variance(values),
+        ("stdev", _) => variance(values).map(|var| var.sqrt().unwrap_or_else(|| Number::Float(var.to_f64().sqrt()))),
+        ("percentile", Some(p)) => percentile(values, p),
+        _ => Err(String::from("unknown statistic")),
+    };
+    res.map_err(|err| format!("{name}: {err}"))
+}
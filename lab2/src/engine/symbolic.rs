@@ -0,0 +1,575 @@
+use num_traits::{One, Signed, Zero};
+
+use crate::engine::ast::{Expr, ExprKind};
+use crate::engine::env::Env;
+use crate::engine::error::{CalcError, Span};
+use crate::engine::functions::{find_builtin, is_array_function};
+use crate::engine::number::{format_decimal, integer, Literal, Number};
+use crate::engine::token::{BinOp, PostOp, UnOp};
+
+// builds new nodes of a derivative; they all point at the `diff(...)` call
+struct Builder {
+    span: Span,
+}
+
+impl Builder {
+    fn num(&self, value: i64) -> Expr {
+        self.constant(&integer(value)).unwrap()
+    }
+
+    fn literal(&self, text: &str) -> Option<Expr> {
+        Some(Expr::new(ExprKind::Number(Literal::parse(text).ok()?), self.span))
+    }
+
+    // an exact constant as a literal, or as a fraction such as `1/3` when it
+    // has no finite decimal form; `-` in front when negative
+    fn constant(&self, value: &Number) -> Option<Expr> {
+        let value = value.to_rational()?;
+        let mut denom = value.denom().clone();
+        let mut digits = 0;
+        for factor in [2u32, 5] {
+            let mut count = 0;
+            while (&denom % factor).is_zero() {
+                denom /= factor;
+                count += 1;
+            }
+            digits = digits.max(count);
+        }
+        let expr = if denom.is_one() {
+            self.literal(&format_decimal(&value.abs(), digits))?
+        } else {
+            let numer = self.literal(&value.numer().abs().to_string())?;
+            self.bin(BinOp::Div, numer, self.literal(&value.denom().to_string())?)
+        };
+        Some(if value.is_negative() { self.neg(expr) } else { expr })
+    }
+
+    fn neg(&self, arg: Expr) -> Expr {
+        Expr::new(ExprKind::Unary(UnOp::Minus, Box::new(arg)), self.span)
+    }
+
+    fn bin(&self, op: BinOp, lhs: Expr, rhs: Expr) -> Expr {
+        Expr::new(ExprKind::Binary(op, Box::new(lhs), Box::new(rhs)), self.span)
+    }
+
+    fn call(&self, name: &str, arg: Expr) -> Expr {
+        Expr::new(ExprKind::Call(name.to_string(), vec![arg]), self.span)
+    }
+
+    fn error(&self, message: String) -> CalcError {
+        CalcError::calculate(message, self.span)
+    }
+}
+
+fn literal_value(expr: &Expr) -> Option<Number> {
+    match &expr.kind {
+        ExprKind::Number(literal) if literal.unit.is_none() && !literal.imaginary => {
+            Some(Number::Rational(literal.exact.clone()))
+        }
+        _ => None,
+    }
+}
+
+// the exact value of a literal, a fraction of two, or a negated one: `-0.5`,
+// `-1/3`
+fn number_value(expr: &Expr) -> Option<Number> {
+    match &expr.kind {
+        ExprKind::Unary(UnOp::Minus, arg) => number_value(arg).map(|value| value.neg()),
+        ExprKind::Binary(BinOp::Div, numer, denom) => literal_value(numer)?.div(&literal_value(denom)?).ok(),
+        _ => literal_value(expr),
+    }
+}
+
+fn is_number(expr: &Expr, value: i64) -> bool {
+    number_value(expr) == Some(integer(value))
+}
+
+fn is_integer(value: &Number) -> bool {
+    value.to_rational().is_some_and(|value| value.is_integer())
+}
+
+fn depends_on(expr: &Expr, var: &str) -> bool {
+    match &expr.kind {
+        ExprKind::Number(_) => false,
+        ExprKind::Var(name) => name == var,
+        ExprKind::Unary(_, arg) | ExprKind::Postfix(_, arg) | ExprKind::Convert(arg, _, _) => depends_on(arg, var),
+        ExprKind::Binary(_, lhs, rhs) | ExprKind::Equation(lhs, rhs) => {
+            depends_on(lhs, var) || depends_on(rhs, var)
+        }
+        ExprKind::Conditional(cond, then, otherwise) => {
+            depends_on(cond, var) || depends_on(then, var) || depends_on(otherwise, var)
+        }
+        ExprKind::Vector(items) => items.iter().any(|item| depends_on(item, var)),
+        // user functions may read `var` as a global, so their calls count as dependent
+        ExprKind::Call(name, args) => {
+            find_builtin(name).is_none() || args.iter().any(|arg| depends_on(arg, var))
+        }
+    }
+}
+
+// replaces the parameters of a user function by its arguments
+fn substitute(expr: &Expr, params: &[String], args: &[Expr]) -> Expr {
+    let kind = match &expr.kind {
+        ExprKind::Var(name) => match params.iter().position(|param| param == name) {
+            Some(i) => return args[i].clone(),
+            None => ExprKind::Var(name.clone()),
+        },
+        ExprKind::Number(literal) => ExprKind::Number(literal.clone()),
+        ExprKind::Unary(op, arg) => ExprKind::Unary(*op, Box::new(substitute(arg, params, args))),
+        ExprKind::Postfix(op, arg) => ExprKind::Postfix(*op, Box::new(substitute(arg, params, args))),
+        ExprKind::Binary(op, lhs, rhs) => ExprKind::Binary(
+            *op,
+            Box::new(substitute(lhs, params, args)),
+            Box::new(substitute(rhs, params, args)),
+        ),
+        ExprKind::Call(name, call_args) => ExprKind::Call(
+            name.clone(),
+            call_args.iter().map(|arg| substitute(arg, params, args)).collect(),
+        ),
+        ExprKind::Convert(value, unit, span) => {
+            ExprKind::Convert(Box::new(substitute(value, params, args)), unit.clone(), *span)
+        }
+        ExprKind::Equation(lhs, rhs) => ExprKind::Equation(
+            Box::new(substitute(lhs, params, args)),
+            Box::new(substitute(rhs, params, args)),
+        ),
+        ExprKind::Conditional(cond, then, otherwise) => ExprKind::Conditional(
+            Box::new(substitute(cond, params, args)),
+            Box::new(substitute(then, params, args)),
+            Box::new(substitute(otherwise, params, args)),
+        ),
+        ExprKind::Vector(items) => ExprKind::Vector(items.iter().map(|item| substitute(item, params, args)).collect()),
+    };
+    Expr::new(kind, expr.span)
+}
+
+struct Differentiator<'a> {
+    env: &'a Env,
+    var: &'a str,
+    build: Builder,
+    // the user functions being inlined, so recursive ones fail cleanly
+    inlining: Vec<String>,
+}
+
+impl Differentiator<'_> {
+    // `ln(base)`, which is 1 for the constant `e`
+    fn ln(&self, base: Expr) -> Expr {
+        match &base.kind {
+            ExprKind::Var(name) if name == "e" && self.env.get(name).is_none() => self.build.num(1),
+            _ => self.build.call("ln", base),
+        }
+    }
+
+    fn derive(&mut self, expr: &Expr) -> Result<Expr, CalcError> {
+        if !depends_on(expr, self.var) {
+            return Ok(self.build.num(0));
+        }
+        let b = &self.build;
+        match &expr.kind {
+            ExprKind::Number(_) => Ok(b.num(0)),
+            ExprKind::Var(_) => Ok(b.num(1)),
+            ExprKind::Unary(UnOp::Plus, arg) => self.derive(arg),
+            ExprKind::Unary(UnOp::Minus, arg) => {
+                let darg = self.derive(arg)?;
+                Ok(self.build.neg(darg))
+            }
+            // `a + b%` is `a + a*b%`
+            ExprKind::Binary(op @ (BinOp::Add | BinOp::Sub), lhs, rhs)
+                if matches!(rhs.kind, ExprKind::Postfix(PostOp::Percent, _)) =>
+            {
+                let share = b.bin(BinOp::Mul, (**lhs).clone(), (**rhs).clone());
+                let expanded = b.bin(*op, (**lhs).clone(), share);
+                self.derive(&expanded)
+            }
+            ExprKind::Binary(op, lhs, rhs) => self.derive_binary(*op, lhs, rhs),
+            // piecewise: the condition picks which derivative applies
+            ExprKind::Conditional(cond, then, otherwise) => {
+                let (dthen, dotherwise) = (self.derive(then)?, self.derive(otherwise)?);
+                Ok(Expr::new(
+                    ExprKind::Conditional(cond.clone(), Box::new(dthen), Box::new(dotherwise)),
+                    self.build.span,
+                ))
+            }
+            ExprKind::Vector(items) => {
+                let ditems = items.iter().map(|item| self.derive(item)).collect::<Result<_, _>>()?;
+                Ok(Expr::new(ExprKind::Vector(ditems), self.build.span))
+            }
+            ExprKind::Postfix(PostOp::Percent, arg) => {
+                let darg = self.derive(arg)?;
+                Ok(self.build.bin(BinOp::Div, darg, self.build.num(100)))
+            }
+            ExprKind::Postfix(op, _) => Err(b.error(format!("cannot differentiate '{}'", op.symbol()))),
+            ExprKind::Call(name, args) => self.derive_call(name, args),
+            ExprKind::Unary(op, _) => {
+                Err(b.error(format!("cannot differentiate '{}'", op.symbol())))
+            }
+            ExprKind::Convert(_, _, _) => Err(b.error(String::from("cannot differentiate a unit conversion"))),
+            ExprKind::Equation(_, _) => Err(b.error(String::from("cannot differentiate an equation"))),
+        }
+    }
+
+    fn derive_binary(&mut self, op: BinOp, lhs: &Expr, rhs: &Expr) -> Result<Expr, CalcError> {
+        let (dlhs, drhs) = (self.derive(lhs)?, self.derive(rhs)?);
+        let (lhs, rhs) = (lhs.clone(), rhs.clone());
+        let b = &self.build;
+        match op {
+            BinOp::Add | BinOp::Sub => Ok(b.bin(op, dlhs, drhs)),
+            BinOp::Mul => Ok(b.bin(
+                BinOp::Add,
+                b.bin(BinOp::Mul, dlhs, rhs.clone()),
+                b.bin(BinOp::Mul, lhs, drhs),
+            )),
+            BinOp::Div if !depends_on(&rhs, self.var) => Ok(b.bin(BinOp::Div, dlhs, rhs)),
+            BinOp::Div => {
+                let numer = b.bin(
+                    BinOp::Sub,
+                    b.bin(BinOp::Mul, dlhs, rhs.clone()),
+                    b.bin(BinOp::Mul, lhs, drhs),
+                );
+                Ok(b.bin(BinOp::Div, numer, b.bin(BinOp::Pow, rhs, b.num(2))))
+            }
+            // power rule when the exponent is constant, exponential rule when
+            // the base is, the general form otherwise
+            BinOp::Pow if !depends_on(&rhs, self.var) => {
+                let exp = b.bin(BinOp::Sub, rhs.clone(), b.num(1));
+                Ok(b.bin(BinOp::Mul, b.bin(BinOp::Mul, rhs, b.bin(BinOp::Pow, lhs, exp)), dlhs))
+            }
+            BinOp::Pow if !depends_on(&lhs, self.var) => {
+                let pow = b.bin(BinOp::Pow, lhs.clone(), rhs);
+                Ok(b.bin(BinOp::Mul, b.bin(BinOp::Mul, pow, self.ln(lhs)), drhs))
+            }
+            BinOp::Pow => {
+                let pow = b.bin(BinOp::Pow, lhs.clone(), rhs.clone());
+                let inner = b.bin(
+                    BinOp::Add,
+                    b.bin(BinOp::Mul, drhs, self.ln(lhs.clone())),
+                    b.bin(BinOp::Div, b.bin(BinOp::Mul, rhs, dlhs), lhs),
+                );
+                Ok(b.bin(BinOp::Mul, pow, inner))
+            }
+            _ => Err(b.error(format!("cannot differentiate '{}'", op.symbol()))),
+        }
+    }
+
+    fn derive_call(&mut self, name: &str, args: &[Expr]) -> Result<Expr, CalcError> {
+        if name == "diff" {
+            let inner = self.expand_diff(args)?;
+            return self.derive(&inner);
+        }
+        if is_array_function(name) {
+            return Err(self.build.error(format!("cannot differentiate '{name}'")));
+        }
+        if find_builtin(name).is_none() {
+            return self.derive_user_function(name, args);
+        }
+
+        let b = &self.build;
+        // `log(base, u)` is `ln(u)/ln(base)`
+        if let ("log", [base, u]) = (name, args) {
+            let quotient = b.bin(BinOp::Div, b.call("ln", u.clone()), self.ln(base.clone()));
+            return self.derive(&quotient);
+        }
+        let u = match args {
+            [u] => u.clone(),
+            _ => return Err(b.error(format!("cannot differentiate '{name}' with {} arguments", args.len()))),
+        };
+        let one_over = |expr: Expr| b.bin(BinOp::Div, b.num(1), expr);
+        let square = |expr: Expr| b.bin(BinOp::Pow, expr, b.num(2));
+        let sqrt_one_minus_square = || b.call("sqrt", b.bin(BinOp::Sub, b.num(1), square(u.clone())));
+        let outer = match name {
+            "sin" => b.call("cos", u.clone()),
+            "cos" => b.neg(b.call("sin", u.clone())),
+            "tan" => one_over(square(b.call("cos", u.clone()))),
+            "asin" => one_over(sqrt_one_minus_square()),
+            "acos" => b.neg(one_over(sqrt_one_minus_square())),
+            "atan" => one_over(b.bin(BinOp::Add, b.num(1), square(u.clone()))),
+            "sinh" => b.call("cosh", u.clone()),
+            "cosh" => b.call("sinh", u.clone()),
+            "tanh" => one_over(square(b.call("cosh", u.clone()))),
+            "exp" => b.call("exp", u.clone()),
+            "ln" => one_over(u.clone()),
+            "log" | "log10" => one_over(b.bin(BinOp::Mul, u.clone(), b.call("ln", b.num(10)))),
+            "log2" => one_over(b.bin(BinOp::Mul, u.clone(), b.call("ln", b.num(2)))),
+            "sqrt" => one_over(b.bin(BinOp::Mul, b.num(2), b.call("sqrt", u.clone()))),
+            "cbrt" => one_over(b.bin(BinOp::Mul, b.num(3), square(b.call("cbrt", u.clone())))),
+            "abs" => b.call("sign", u.clone()),
+            _ => return Err(b.error(format!("cannot differentiate '{name}'"))),
+        };
+        let inner = self.derive(&u)?;
+        Ok(self.build.bin(BinOp::Mul, outer, inner))
+    }
+
+    fn derive_user_function(&mut self, name: &str, args: &[Expr]) -> Result<Expr, CalcError> {
+        let func = match self.env.function(name) {
+            Some(func) => func,
+            None => return Err(self.build.error(format!("unknown function '{name}'"))),
+        };
+        if func.params.len() != args.len() {
+            return Err(self.build.error(format!(
+                "{name} expects {} arguments, got {}",
+                func.params.len(),
+                args.len()
+            )));
+        }
+        if self.inlining.iter().any(|outer| outer == name) {
+            return Err(self.build.error(format!("cannot differentiate recursive function '{name}'")));
+        }
+        let body = substitute(&func.body, &func.params, args);
+        self.inlining.push(name.to_string());
+        let res = self.derive(&body);
+        self.inlining.pop();
+        res
+    }
+
+    // the derivative of the arguments of an inner `diff(expr, var)`
+    fn expand_diff(&mut self, args: &[Expr]) -> Result<Expr, CalcError> {
+        let (expr, var) = diff_args(args, self.build.span)?;
+        let mut inner = Differentiator {
+            env: self.env,
+            var,
+            build: Builder { span: self.build.span },
+            inlining: self.inlining.clone(),
+        };
+        Ok(simplify(inner.derive(expr)?))
+    }
+}
+
+fn diff_args(args: &[Expr], span: Span) -> Result<(&Expr, &str), CalcError> {
+    match args {
+        [expr, Expr { kind: ExprKind::Var(var), .. }] => Ok((expr, var)),
+        [_, other] => Err(CalcError::calculate("diff expects a variable name", other.span)),
+        _ => Err(CalcError::calculate(
+            format!("diff expects 2 arguments, got {}", args.len()),
+            span,
+        )),
+    }
+}
+
+// the simplified derivative of the call `diff(expr, var)`
+pub fn differentiate(call: &Expr, args: &[Expr], env: &Env) -> Result<Expr, CalcError> {
+    let (expr, var) = diff_args(args, call.span)?;
+    derivative(expr, var, env, call.span)
+}
+
+// the simplified derivative of `expr` with respect to `var`
+pub fn derivative(expr: &Expr, var: &str, env: &Env, span: Span) -> Result<Expr, CalcError> {
+    let mut diff = Differentiator { env, var, build: Builder { span }, inlining: Vec::new() };
+    Ok(simplify(diff.derive(expr)?))
+}
+
+// replaces every `diff(...)` in `expr` by its derivative
+pub fn expand_derivatives(expr: &Expr, env: &Env) -> Result<Expr, CalcError> {
+    let kind = match &expr.kind {
+        ExprKind::Call(name, args) if name == "diff" => return differentiate(expr, args, env),
+        ExprKind::Number(_) | ExprKind::Var(_) => return Ok(expr.clone()),
+        ExprKind::Unary(op, arg) => ExprKind::Unary(*op, Box::new(expand_derivatives(arg, env)?)),
+        ExprKind::Postfix(op, arg) => ExprKind::Postfix(*op, Box::new(expand_derivatives(arg, env)?)),
+        ExprKind::Binary(op, lhs, rhs) => ExprKind::Binary(
+            *op,
+            Box::new(expand_derivatives(lhs, env)?),
+            Box::new(expand_derivatives(rhs, env)?),
+        ),
+        ExprKind::Call(name, args) => ExprKind::Call(
+            name.clone(),
+            args.iter().map(|arg| expand_derivatives(arg, env)).collect::<Result<_, _>>()?,
+        ),
+        ExprKind::Convert(value, unit, span) => {
+            ExprKind::Convert(Box::new(expand_derivatives(value, env)?), unit.clone(), *span)
+        }
+        ExprKind::Equation(lhs, rhs) => ExprKind::Equation(
+            Box::new(expand_derivatives(lhs, env)?),
+            Box::new(expand_derivatives(rhs, env)?),
+        ),
+        ExprKind::Conditional(cond, then, otherwise) => ExprKind::Conditional(
+            Box::new(expand_derivatives(cond, env)?),
+            Box::new(expand_derivatives(then, env)?),
+            Box::new(expand_derivatives(otherwise, env)?),
+        ),
+        ExprKind::Vector(items) => {
+            ExprKind::Vector(items.iter().map(|item| expand_derivatives(item, env)).collect::<Result<_, _>>()?)
+        }
+    };
+    Ok(Expr::new(kind, expr.span))
+}
+
+// whether `expr` differentiates with respect to a name that has no value, in
+// which case the result can only be shown symbolically
+pub fn has_symbolic_diff(expr: &Expr, env: &Env) -> bool {
+    match &expr.kind {
+        ExprKind::Call(name, args) if name == "diff" => match args.get(1) {
+            Some(Expr { kind: ExprKind::Var(var), .. }) => env.get(var).is_none(),
+            _ => false,
+        },
+        // the solvers give the variable its values
+        ExprKind::Call(name, _) if name == "solve" || name == "nsolve" => false,
+        ExprKind::Number(_) | ExprKind::Var(_) => false,
+        ExprKind::Unary(_, arg) | ExprKind::Postfix(_, arg) | ExprKind::Convert(arg, _, _) => {
+            has_symbolic_diff(arg, env)
+        }
+        ExprKind::Binary(_, lhs, rhs) | ExprKind::Equation(lhs, rhs) => {
+            has_symbolic_diff(lhs, env) || has_symbolic_diff(rhs, env)
+        }
+        ExprKind::Conditional(cond, then, otherwise) => {
+            has_symbolic_diff(cond, env) || has_symbolic_diff(then, env) || has_symbolic_diff(otherwise, env)
+        }
+        ExprKind::Call(_, args) | ExprKind::Vector(args) => args.iter().any(|arg| has_symbolic_diff(arg, env)),
+    }
+}
+
+// algebraic clean-up of a derivative: drops zeros and ones, folds constants
+// and pulls signs out of products
+pub fn simplify(expr: Expr) -> Expr {
+    let span = expr.span;
+    let b = Builder { span };
+    match expr.kind {
+        ExprKind::Unary(op, arg) => {
+            let arg = simplify(*arg);
+            match (op, arg.kind) {
+                (UnOp::Plus, kind) => Expr::new(kind, arg.span),
+                (UnOp::Minus, ExprKind::Unary(UnOp::Minus, inner)) => *inner,
+                (op, kind) => {
+                    let arg = Expr::new(kind, arg.span);
+                    if op == UnOp::Minus && is_number(&arg, 0) {
+                        arg
+                    } else {
+                        Expr::new(ExprKind::Unary(op, Box::new(arg)), span)
+                    }
+                }
+            }
+        }
+        ExprKind::Postfix(op, arg) => Expr::new(ExprKind::Postfix(op, Box::new(simplify(*arg))), span),
+        ExprKind::Binary(op, lhs, rhs) => simplify_binary(&b, op, simplify(*lhs), simplify(*rhs)),
+        ExprKind::Call(name, args) => Expr::new(ExprKind::Call(name, args.into_iter().map(simplify).collect()), span),
+        ExprKind::Conditional(cond, then, otherwise) => Expr::new(
+            ExprKind::Conditional(Box::new(simplify(*cond)), Box::new(simplify(*then)), Box::new(simplify(*otherwise))),
+            span,
+        ),
+        ExprKind::Vector(items) => Expr::new(ExprKind::Vector(items.into_iter().map(simplify).collect()), span),
+        kind => Expr::new(kind, span),
+    }
+}
+
+fn negated(expr: &Expr) -> Option<&Expr> {
+    match &expr.kind {
+        ExprKind::Unary(UnOp::Minus, arg) => Some(arg),
+        _ => None,
+    }
+}
+
+fn simplify_binary(b: &Builder, op: BinOp, lhs: Expr, rhs: Expr) -> Expr {
+    let (a, c) = (number_value(&lhs), number_value(&rhs));
+    if let (Some(a), Some(c)) = (&a, &c) {
+        let folded = match op {
+            BinOp::Add => Ok(a.add(c)),
+            BinOp::Sub => Ok(a.sub(c)),
+            BinOp::Mul => Ok(a.mul(c)),
+            BinOp::Div => a.div(c),
+            BinOp::Pow => a.pow(c),
+            _ => Err("not folded"),
+        };
+        if let Some(folded) = folded.ok().and_then(|value| b.constant(&value)) {
+            return folded;
+        }
+    }
+    match op {
+        BinOp::Add => {
+            if is_number(&lhs, 0) {
+                return rhs;
+            }
+            if is_number(&rhs, 0) {
+                return lhs;
+            }
+            if let Some(rhs) = negated(&rhs) {
+                return b.bin(BinOp::Sub, lhs, rhs.clone());
+            }
+        }
+        BinOp::Sub => {
+            if is_number(&rhs, 0) {
+                return lhs;
+            }
+            if is_number(&lhs, 0) {
+                return simplify(b.neg(rhs));
+            }
+            if let Some(rhs) = negated(&rhs) {
+                return b.bin(BinOp::Add, lhs, rhs.clone());
+            }
+        }
+        BinOp::Mul => {
+            if is_number(&lhs, 0) || is_number(&rhs, 0) {
+                return b.num(0);
+            }
+            if is_number(&lhs, 1) {
+                return rhs;
+            }
+            if is_number(&rhs, 1) {
+                return lhs;
+            }
+            if let Some(inner) = negated(&lhs) {
+                return simplify(b.neg(simplify_binary(b, op, inner.clone(), rhs)));
+            }
+            if let Some(inner) = negated(&rhs) {
+                return simplify(b.neg(simplify_binary(b, op, lhs, inner.clone())));
+            }
+            // `1/u*v` is `v/u`
+            if let ExprKind::Binary(BinOp::Div, numer, denom) = &lhs.kind {
+                if is_number(numer, 1) {
+                    return simplify_binary(b, BinOp::Div, rhs, (**denom).clone());
+                }
+            }
+            if let ExprKind::Binary(BinOp::Div, numer, denom) = &rhs.kind {
+                if is_number(numer, 1) {
+                    return simplify_binary(b, BinOp::Div, lhs, (**denom).clone());
+                }
+            }
+            // constants go first: `x*2` is `2*x`, `2*(3*x)` is `6*x`
+            if a.is_none() && c.is_some() {
+                return simplify_binary(b, op, rhs, lhs);
+            }
+            if let (Some(a), ExprKind::Binary(BinOp::Mul, inner_lhs, inner_rhs)) = (&a, &rhs.kind) {
+                if let Some(product) = number_value(inner_lhs).and_then(|c| b.constant(&a.mul(&c))) {
+                    return simplify_binary(b, op, product, (**inner_rhs).clone());
+                }
+            }
+        }
+        BinOp::Div => {
+            if is_number(&lhs, 0) && !is_number(&rhs, 0) {
+                return b.num(0);
+            }
+            if is_number(&rhs, 1) {
+                return lhs;
+            }
+            // spans differ between copies, so compare the printed forms
+            if !is_number(&rhs, 0) && lhs.to_string() == rhs.to_string() {
+                return b.num(1);
+            }
+            if let Some(inner) = negated(&lhs) {
+                return simplify(b.neg(b.bin(op, inner.clone(), rhs)));
+            }
+            // `u/v/w` is `u/(v*w)`
+            if let ExprKind::Binary(BinOp::Div, numer, denom) = &lhs.kind {
+                if a.is_none() {
+                    let denom = simplify_binary(b, BinOp::Mul, (**denom).clone(), rhs);
+                    return simplify_binary(b, BinOp::Div, (**numer).clone(), denom);
+                }
+            }
+        }
+        BinOp::Pow => {
+            if is_number(&rhs, 0) {
+                return b.num(1);
+            }
+            if is_number(&rhs, 1) {
+                return lhs;
+            }
+            // `(x^2)^3` is `x^6`; only whole outer exponents, `(x^2)^0.5` is `abs(x)`
+            if let (ExprKind::Binary(BinOp::Pow, base, inner), Some(c)) = (&lhs.kind, &c) {
+                if is_integer(c) {
+                    if let Some(power) = number_value(inner).and_then(|a| b.constant(&a.mul(c))) {
+                        return simplify_binary(b, BinOp::Pow, (**base).clone(), power);
+                    }
+                }
+            }
+        }
+        _ => {}
+    }
+    b.bin(op, lhs, rhs)
+}
@@ -0,0 +1,835 @@
+use crate::engine::compile::compile;
+use crate::engine::env::Env;
+use crate::engine::error::{CalcError, Span, Stage};
+use crate::engine::lexer::parse_expression_to_tokens;
+use crate::engine::number::{Number, NumberMode, WordSize};
+use crate::engine::token::join_tokens;
+use crate::engine::units::UnitTable;
+use crate::engine::value::Value;
+use crate::engine::eval::calculate_at;
+use crate::engine::format::{Format, Notation};
+use crate::engine::{calc, define_constants, define_units, format_solution, parse, store_data, Outcome};
+
+static EPS: f64 = 0.0000000001;
+
+fn eval_in(expr: &str, env: &mut Env) -> Result<f64, CalcError> {
+    match calc(expr, env)? {
+        Outcome::Value(value) => Ok(value.number().to_f64()),
+        other => panic!("{expr}: expected a value, got {other:?}"),
+    }
+}
+
+fn eval(expr: &str) -> Result<f64, CalcError> {
+    eval_in(expr, &mut Env::new())
+}
+
+fn assert_calc(expr: &str, expected: f64) {
+    match eval(expr) {
+        Ok(res) => assert!(
+            (res - expected).abs() < EPS,
+            "{expr}: expected {expected}, got {res}"
+        ),
+        Err(err) => panic!("{expr}: {err}"),
+    }
+}
+
+#[test]
+fn binary_operators_respect_priority() {
+    assert_calc("2+3*4", 14.0);
+    assert_calc("2*3+4", 10.0);
+    assert_calc("10-4-3", 3.0);
+    assert_calc("24/4/3", 2.0);
+}
+
+#[test]
+fn parentheses_group_sub_expressions() {
+    assert_calc("(2+3)*4", 20.0);
+    assert_calc("((1+2)*(3+4))", 21.0);
+    assert!(eval("2*(3").is_err());
+    assert!(eval("2)").is_err());
+    assert!(eval("()").is_err());
+}
+
+#[test]
+fn exponent_is_right_associative() {
+    assert_calc("2^3^2", 512.0);
+    assert_calc("(2^3)^2", 64.0);
+}
+
+#[test]
+fn unary_minus_and_plus() {
+    assert_calc("-3", -3.0);
+    assert_calc("+3", 3.0);
+    assert_calc("3*-2", -6.0);
+    assert_calc("-3*2", -6.0);
+    assert_calc("2--3", 5.0);
+    assert_calc("--2", 2.0);
+    assert_calc("-(2+3)", -5.0);
+    assert_calc("4/-2", -2.0);
+}
+
+#[test]
+fn unary_minus_against_exponent() {
+    assert_calc("-2^2", -4.0);
+    assert_calc("2^-2", 0.25);
+    assert_calc("(-2)^2", 4.0);
+    assert_calc("-2^-2", -0.25);
+}
+
+#[test]
+fn unary_tokens_are_decided_by_tokenizer() {
+    let tokens = parse_expression_to_tokens("-2*-(3)", &UnitTable::new()).unwrap();
+    assert_eq!(join_tokens(&tokens), "u- 2 * u- ( 3 )");
+    let tokens = parse_expression_to_tokens("(1) - 2", &UnitTable::new()).unwrap();
+    assert_eq!(join_tokens(&tokens), "( 1 ) - 2");
+    assert!(eval("*2").is_err());
+}
+
+fn assert_error(expr: &str, stage: Stage, span: Span) {
+    match eval(expr) {
+        Ok(res) => panic!("{expr}: expected an error, got {res}"),
+        Err(err) => {
+            assert_eq!(err.stage, stage, "{expr}: {err}");
+            assert_eq!(err.span, Some(span), "{expr}: {err}");
+        }
+    }
+}
+
+#[test]
+fn errors_point_at_the_failing_characters() {
+    assert_error("2+#", Stage::Parse, Span::new(2, 3));
+    assert_error("1+", Stage::Rpn, Span::new(1, 2));
+    assert_error("2 3", Stage::Rpn, Span::new(2, 3));
+    assert_error("(1+2", Stage::Rpn, Span::new(0, 1));
+    assert_error("1+2)", Stage::Rpn, Span::new(3, 4));
+    assert_error("1+(2*3)/(4-4)", Stage::Calculate, Span::new(9, 12));
+    assert!(eval("").is_err());
+}
+
+#[test]
+fn builtin_functions() {
+    assert_calc("sqrt(16)", 4.0);
+    assert_calc("sin(0)+cos(0)", 1.0);
+    assert_calc("log(2, 8)", 3.0);
+    assert_calc("log(1000)", 3.0);
+    assert_calc("max(1, 7, 3) - min(4, 2)", 5.0);
+    assert_calc("round(2.345, 2)", 2.35);
+    assert_calc("floor(-2.5) + abs(-3)", 0.0);
+    assert_calc("-sqrt(4)^2", -4.0);
+    assert_calc("2*max(1, (2+3)*2)", 20.0);
+}
+
+#[test]
+fn function_call_errors() {
+    assert_error("sin(1, 2)", Stage::Calculate, Span::new(0, 9));
+    assert_error("foo(1)", Stage::Calculate, Span::new(0, 6));
+    assert_error("max(i, 2)", Stage::Calculate, Span::new(0, 9));
+    assert_error("max(1,)", Stage::Rpn, Span::new(6, 7));
+    assert_error("max(,1)", Stage::Rpn, Span::new(4, 5));
+    assert_error("1, 2", Stage::Rpn, Span::new(1, 2));
+    assert_error("sin", Stage::Calculate, Span::new(0, 3));
+    assert!(eval("max()").is_err());
+}
+
+#[test]
+fn variables_persist_in_env() {
+    let mut env = Env::new();
+    assert_eq!(eval_in("x = 3.5", &mut env), Ok(3.5));
+    assert_eq!(eval_in("2*x + 1", &mut env), Ok(8.0));
+    assert_eq!(eval_in("y = x * -2", &mut env), Ok(-7.0));
+    assert_eq!(eval_in("x = x + y", &mut env), Ok(-3.5));
+    assert_eq!(env.get("x"), Some(&Value::Number(Number::Float(-3.5))));
+    assert_eq!(env.variables().count(), 2);
+    assert_eq!(eval_in("z = -1", &mut env), Ok(-1.0));
+    assert_eq!(eval_in("z=-z", &mut env), Ok(1.0));
+}
+
+#[test]
+fn variable_errors() {
+    let mut env = Env::new();
+    let err = calc("2 * width", &mut env).unwrap_err();
+    assert_eq!(err.span, Some(Span::new(4, 9)));
+    assert!(err.message.contains("width"));
+    assert!(calc("x =", &mut env).is_err());
+    assert!(calc("1 = 2", &mut env).is_err());
+    assert!(calc("x = y = 2", &mut env).is_err());
+    assert!(calc("x = undefined", &mut env).is_err());
+    assert_eq!(env.get("x"), None);
+}
+
+#[test]
+fn user_defined_functions() {
+    let mut env = Env::new();
+    assert_eq!(
+        calc("f(x,y)=x^2 + y", &mut env),
+        Ok(Outcome::Defined("f(x, y) = x^2 + y".to_string()))
+    );
+    assert_eq!(eval_in("f(2, 3)", &mut env), Ok(7.0));
+    assert_eq!(eval_in("x = 10", &mut env), Ok(10.0));
+    // parameters shadow globals, globals stay visible in the body
+    assert_eq!(eval_in("f(1, x)", &mut env), Ok(11.0));
+    calc("g(t) = f(t, x) * 2", &mut env).unwrap();
+    assert_eq!(eval_in("g(3)", &mut env), Ok(38.0));
+    calc("zero() = 0", &mut env).unwrap();
+    assert_eq!(eval_in("zero() + 1", &mut env), Ok(1.0));
+    assert_eq!(calc("neg(x) = -x", &mut env), Ok(Outcome::Defined("neg(x) = -x".to_string())));
+    assert_eq!(eval_in("neg(4)", &mut env), Ok(-4.0));
+}
+
+#[test]
+fn user_function_errors() {
+    let mut env = Env::new();
+    assert!(calc("sin(x) = x", &mut env).is_err());
+    assert!(calc("f(x, x) = x", &mut env).is_err());
+    assert!(calc("f(x,) = x", &mut env).is_err());
+    assert!(calc("f(1) = 2", &mut env).is_err());
+
+    calc("f(x) = x / y", &mut env).unwrap();
+    assert!(eval_in("f(1, 2)", &mut env).is_err());
+    // errors inside the body point at the call site
+    let err = eval_in("1 + f(2)", &mut env).unwrap_err();
+    assert_eq!(err.span, Some(Span::new(4, 8)));
+    assert!(err.message.contains("undefined variable 'y'"));
+}
+
+#[test]
+fn recursion_is_limited() {
+    let mut env = Env::new();
+    calc("f(n) = f(n - 1) + 1", &mut env).unwrap();
+    assert!(eval_in("f(3)", &mut env).unwrap_err().message.contains("recursion depth"));
+    calc("g(n) = g(n - 1) + g(n - 1)", &mut env).unwrap();
+    assert!(eval_in("g(3)", &mut env).is_err());
+}
+
+#[test]
+fn nesting_is_limited() {
+    let sum = vec!["1"; 256].join("+");
+    assert_calc(&sum, 256.0);
+    let sum = vec!["1"; 100_000].join("+");
+    assert_eq!(eval(&sum).unwrap_err().message, "expression is too deeply nested");
+    let negations = format!("{}1", "-".repeat(100_000));
+    assert_eq!(eval(&negations).unwrap_err().message, "expression is too deeply nested");
+}
+
+fn show_in(expr: &str, mode: NumberMode) -> String {
+    let mut env = Env::new();
+    env.set_mode(mode);
+    match calc(expr, &mut env) {
+        Ok(Outcome::Value(value)) => value.to_string(),
+        Ok(Outcome::Defined(text) | Outcome::Symbolic(text)) => text,
+        Ok(Outcome::Solution(roots)) => format_solution(&roots, &Format::default()),
+        Err(err) => err.to_string(),
+    }
+}
+
+#[test]
+fn exact_rational_mode() {
+    assert_eq!(show_in("0.1+0.2", NumberMode::Float), "0.30000000000000004");
+    assert_eq!(show_in("0.1+0.2", NumberMode::Exact), "3/10");
+    assert_eq!(show_in("1/3 + 1/6", NumberMode::Exact), "1/2");
+    assert_eq!(show_in("2^100", NumberMode::Exact), "1267650600228229401496703205376");
+    assert_eq!(show_in("(2/3)^-2", NumberMode::Exact), "9/4");
+    assert_eq!(show_in("sqrt(16/9)", NumberMode::Exact), "4/3");
+    assert_eq!(show_in("abs(-1/3) + floor(7/2)", NumberMode::Exact), "10/3");
+    assert_eq!(show_in("max(1/3, 0.3)", NumberMode::Exact), "1/3");
+    assert_eq!(show_in("sqrt(2)", NumberMode::Exact), "1.4142135623730951");
+    assert!(show_in("1/(1-1)", NumberMode::Exact).contains("division by 0"));
+    assert!(show_in("(2^100000)^1000", NumberMode::Exact).contains("result is too large"));
+    assert!(show_in("(2^-100000)^-100000", NumberMode::Exact).contains("result is too large"));
+    assert!(show_in("(3/2)^100000", NumberMode::Exact).len() > 10_000);
+}
+
+#[test]
+fn decimal_mode() {
+    assert_eq!(show_in("1/3", NumberMode::Decimal(5)), "0.33333");
+    assert_eq!(show_in("2/3", NumberMode::Decimal(5)), "0.66667");
+    assert_eq!(show_in("0.1+0.2", NumberMode::Decimal(20)), "0.3");
+    assert_eq!(show_in("-1/8", NumberMode::Decimal(2)), "-0.13");
+    assert_eq!(
+        show_in("sqrt(2)", NumberMode::Decimal(30)),
+        "1.41421356237309504880168872421"
+    );
+    assert_eq!(show_in("round(2.345, 2)", NumberMode::Decimal(10)), "2.35");
+    assert_eq!(show_in("12345678901234567890 * 10", NumberMode::Decimal(2)), "123456789012345678900");
+    // values that only have a float form keep the digits of the float
+    assert_eq!(show_in("pi", NumberMode::Decimal(30)), "3.141592653589793");
+    assert_eq!(show_in("2^0.5", NumberMode::Decimal(30)), "1.4142135623730951");
+    assert_eq!(show_in("sin(1)", NumberMode::Decimal(5)), "0.84147");
+    assert_eq!(show_in("30 deg in rad", NumberMode::Decimal(30)), "0.52359877559829885 rad");
+}
+
+#[test]
+fn complex_numbers() {
+    assert_eq!(show_in("sqrt(-4)", NumberMode::Float), "2i");
+    assert_eq!(show_in("i^2", NumberMode::Float), "-1");
+    assert_eq!(show_in("(1+2i)*(3-i)", NumberMode::Float), "5+5i");
+    assert_eq!(show_in("(1+2i)*(1-2i)", NumberMode::Float), "5");
+    assert_eq!(show_in("conj(3+4i)", NumberMode::Float), "3-4i");
+    assert_eq!(show_in("abs(3+4i)", NumberMode::Float), "5");
+    assert_eq!(show_in("arg(2i)", NumberMode::Float), std::f64::consts::FRAC_PI_2.to_string());
+    assert_eq!(show_in("re(1-i) + im(1-i)", NumberMode::Float), "0");
+    assert_eq!(show_in("1/i", NumberMode::Float), "-i");
+    assert_eq!(show_in("sqrt(-9)", NumberMode::Exact), "3i");
+    assert!(show_in("max(i, 1)", NumberMode::Float).contains("not defined for complex"));
+    assert!(show_in("1/(i-i)", NumberMode::Float).contains("division by 0"));
+
+    let mut env = Env::new();
+    calc("i = 3", &mut env).unwrap();
+    assert_eq!(eval_in("i * 2", &mut env), Ok(6.0));
+}
+
+#[test]
+fn complex_roots_of_negative_numbers() {
+    let mut env = Env::new();
+    match calc("(-8)^(1/3)", &mut env).unwrap() {
+        Outcome::Value(value) => {
+            let root = value.number().to_complex();
+            assert!((root.re - 1.0).abs() < EPS && (root.im - 3f64.sqrt()).abs() < EPS);
+        }
+        other => panic!("{other:?}"),
+    }
+    assert_calc("cbrt(-8)", -2.0);
+}
+
+#[test]
+fn units_convert_and_check_dimensions() {
+    assert_eq!(show_in("3 km + 200 m", NumberMode::Exact), "16/5 km");
+    assert_eq!(show_in("3 km + 200 m", NumberMode::Decimal(2)), "3.2 km");
+    assert_eq!(show_in("2 h in min", NumberMode::Float), "120 min");
+    assert_eq!(show_in("10 m / 2 s", NumberMode::Float), "5 m/s");
+    assert_eq!(show_in("(3 m)^2", NumberMode::Float), "9 m^2");
+    assert_eq!(show_in("sqrt(16 m^2)", NumberMode::Float), "4 m");
+    assert_eq!(show_in("1 km / 1 m", NumberMode::Float), "1000");
+    assert_eq!(show_in("90 deg to deg", NumberMode::Float), "90 deg");
+    assert_eq!(show_in("60 kg * 9.81 m/s^2 in N", NumberMode::Exact), "2943/5 N");
+    assert_calc("sin(30 deg)", 0.5);
+    assert_calc("(1 ft + 1 inch) in inch", 13.0);
+
+    assert_error("3 m + 2 s", Stage::Calculate, Span::new(0, 9));
+    assert_error("2 m in kg", Stage::Calculate, Span::new(0, 9));
+    assert_error("2 parsec", Stage::Rpn, Span::new(2, 8));
+    assert_error("2 m in parsec", Stage::Calculate, Span::new(4, 13));
+    // a name that is not a unit is not taken for one
+    assert!(eval("2pi").unwrap_err().message.contains("'pi' is not a unit"));
+    let mut env = Env::new();
+    calc("x = 3", &mut env).unwrap();
+    assert_eq!(calc("2 x", &mut env).unwrap_err().span, Some(Span::new(2, 3)));
+    assert_eq!(eval_in("2 m/x in m", &mut env), Ok(2.0 / 3.0));
+    assert_error("2 m in", Stage::Parse, Span::new(4, 6));
+    assert!(show_in("sin(2 m)", NumberMode::Float).contains("dimensionless"));
+}
+
+#[test]
+fn units_from_config() {
+    let mut env = Env::new();
+    let config = "# lengths\nfurlong = 201.168 m\n\nchain = 0.1 furlong\n";
+    assert_eq!(define_units(&mut env, config), Ok(()));
+    match calc("1 furlong in chain", &mut env).unwrap() {
+        Outcome::Value(value) => assert_eq!(value.to_string(), "10 chain"),
+        other => panic!("{other:?}"),
+    }
+    assert!(define_units(&mut env, "x = 1 m\n2 = 3").unwrap_err().starts_with("line 2"));
+}
+
+#[test]
+fn integer_and_bitwise_operators() {
+    assert_calc("0xFF + 0o17 + 0b1010", 280.0);
+    assert_calc("7 // 2", 3.0);
+    assert_calc("-7 // 2", -4.0);
+    assert_calc("-7 % 3", 2.0);
+    assert_calc("5.5 % 2", 1.5);
+    assert_calc("0b1100 & 0b1010 | 1", 9.0);
+    assert_calc("6 xor 3", 5.0);
+    assert_calc("1 << 4 + 1", 32.0);
+    assert_calc("256 >> 4", 16.0);
+    assert_calc("~5", -6.0);
+    assert_error("1.5 & 1", Stage::Calculate, Span::new(0, 7));
+    assert_error("1 % (2 - 2)", Stage::Calculate, Span::new(5, 10));
+    assert_error("0x", Stage::Rpn, Span::new(1, 2));
+    assert_error("0b102", Stage::Parse, Span::new(0, 5));
+}
+
+#[test]
+fn programmer_mode_wraps_to_the_word_size() {
+    let byte = NumberMode::Programmer(WordSize { bits: 8, signed: false });
+    let signed_byte = NumberMode::Programmer(WordSize { bits: 8, signed: true });
+    assert_eq!(show_in("0xF0 | 0x0F", byte), "255 (0xFF, 0o377, 0b11111111)");
+    assert_eq!(show_in("255 + 1", byte), "0 (0x0, 0o0, 0b0)");
+    assert_eq!(show_in("~0", byte), "255 (0xFF, 0o377, 0b11111111)");
+    assert_eq!(show_in("7 / 2", byte), "3 (0x3, 0o3, 0b11)");
+    assert_eq!(show_in("127 + 1", signed_byte), "-128 (0x80, 0o200, 0b10000000)");
+    assert_eq!(show_in("-1", signed_byte), "-1 (0xFF, 0o377, 0b11111111)");
+    assert_eq!(show_in("1 << 8", signed_byte), "0 (0x0, 0o0, 0b0)");
+    assert!(show_in("3.5 + 1", byte).contains("programmer mode needs an integer"));
+    let mut env = Env::new();
+    env.set_mode(byte);
+    assert_eq!(calc("2 * 1e-1", &mut env).unwrap_err().span, Some(Span::new(4, 8)));
+    assert_eq!(show_in("2.5e1", byte), "25 (0x19, 0o31, 0b11001)");
+    assert!(show_in("3i", byte).contains("programmer mode has no complex numbers"));
+    assert!(show_in("sin(1)", byte).contains("sin: programmer mode needs an integer"));
+    assert!(show_in("pi", byte).contains("programmer mode needs an integer"));
+    assert!(show_in("sqrt(-4)", signed_byte).contains("sqrt: programmer mode has no complex numbers"));
+    assert_eq!(show_in("cos(0) + 1", byte), "2 (0x2, 0o2, 0b10)");
+    assert!(show_in("1 << 100000", signed_byte).contains("does not fit in the word size"));
+    assert!(show_in("256", byte).contains("does not fit in the word size"));
+    assert_eq!(show_in("0xFF", signed_byte), "-1 (0xFF, 0o377, 0b11111111)");
+}
+
+#[test]
+fn symbolic_derivatives() {
+    let derive = |expr: &str| show_in(expr, NumberMode::Float);
+    assert_eq!(derive("diff(x^2*sin(x), x)"), "2*x*sin(x) + x^2*cos(x)");
+    assert_eq!(derive("diff(3*x^3 - 2*x + 7, x)"), "9*x^2 - 2");
+    assert_eq!(derive("diff(exp(2*x), x)"), "2*exp(2*x)");
+    assert_eq!(derive("diff(1/x, x)"), "-1/x^2");
+    assert_eq!(derive("diff(a*x + b, x)"), "a");
+    assert_eq!(derive("diff(diff(x^3, x), x)"), "6*x");
+    assert_eq!(derive("diff(cos(x), x) + 1"), "-sin(x) + 1");
+    assert_eq!(derive("diff(x^-1, x)"), "-x^(-2)");
+    assert_eq!(derive("diff(x^0.5, x)"), "0.5*x^(-0.5)");
+    assert_eq!(derive("diff(4*x^1.5 - x/4, x)"), "6*x^0.5 - 0.25");
+    assert_eq!(derive("diff(x^(1/3), x)"), "x^(-2/3)/3");
+    assert_eq!(derive("diff(x/3, x)"), "1/3");
+    assert_eq!(derive("diff(2*x/6 + x^2/3, x)"), "1/3 + 2*x/3");
+    assert_eq!(derive("diff(log(2, x), x)"), "1/(x*ln(2))");
+    assert_eq!(derive("diff(log(x, 8), x)"), "-ln(8)/(x*ln(x)^2)");
+    assert_eq!(derive("diff(e^x, x)"), "e^x");
+    assert_eq!(derive("diff(e^(2*x), x)"), "2*e^(2*x)");
+    assert_eq!(derive("diff(2^x, x)"), "2^x*ln(2)");
+    assert!(derive("diff(floor(x), x)").contains("cannot differentiate 'floor'"));
+    assert!(derive("diff(x^2, 2)").contains("expects a variable"));
+
+    let mut env = Env::new();
+    calc("f(t) = t^2 + 1", &mut env).unwrap();
+    assert_eq!(
+        calc("g(t) = diff(f(t)*t, t)", &mut env),
+        Ok(Outcome::Defined("g(t) = 2*t*t + f(t)".to_string()))
+    );
+    assert_eq!(eval_in("g(2)", &mut env), Ok(13.0));
+    calc("x = 3", &mut env).unwrap();
+    assert_eq!(eval_in("diff(x^2, x)", &mut env), Ok(6.0));
+    calc("r(n) = r(n) + n", &mut env).unwrap();
+    assert!(eval_in("diff(r(x), x)", &mut env).unwrap_err().message.contains("recursive"));
+}
+
+#[test]
+fn solving_equations() {
+    assert_calc("solve(x^3 - 2*x - 5 = 0, x, 2)", 2.0945514815423265);
+    assert_calc("solve(x^2 = 2, x, 1, 2)", 2f64.sqrt());
+    assert_calc("solve(cos(x) = x, x, 0, 1)", 0.7390851332151607);
+    assert_calc("solve(atan2(x, 1) = 0.5, x, 1)", 0.5f64.tan());
+    assert_calc("2 * solve(x^2 - 9, x, 1)", 6.0);
+    assert_calc("solve(x = -2, x, 1)", -2.0);
+    assert_calc("solve(x^3 = -8, x, 1)", -2.0);
+    assert_eq!(
+        show_in("nsolve(x^2 + y^2 = 2, x - y = 0, x, y, 2, 1)", NumberMode::Float),
+        "x = 1, y = 1"
+    );
+    assert_calc("1 + nsolve(exp(t) = 1, t, 3)", 1.0);
+
+    assert!(show_in("solve(x^2 + 1 = 0, x, 0.5)", NumberMode::Float).contains("no convergence"));
+    assert!(show_in("solve(sqrt(x) = -1, x, 1)", NumberMode::Float).contains("undefined at"));
+    assert!(show_in("solve(x^2 = 2, x, 2, 3)", NumberMode::Float).contains("does not change sign"));
+    assert!(show_in("solve(x^2 = 2, 3, 1)", NumberMode::Float).contains("variable name"));
+    assert!(show_in("nsolve(x = 1, y = 1, x, y)", NumberMode::Float).contains("n starting values"));
+    assert_error("1 + (x = 2)", Stage::Rpn, Span::new(7, 8));
+    assert_error("sin(x = 2)", Stage::Calculate, Span::new(4, 9));
+
+    let mut env = Env::new();
+    calc("x = 10", &mut env).unwrap();
+    calc("f(a) = a^2 - x", &mut env).unwrap();
+    assert!((eval_in("solve(f(t), t, 3)", &mut env).unwrap() - 10f64.sqrt()).abs() < EPS);
+    assert_eq!(eval_in("x", &mut env), Ok(10.0));
+}
+
+#[test]
+fn parsed_expressions_evaluate_per_sample() {
+    let mut env = Env::new();
+    eval_in("a = 3", &mut env).unwrap();
+    eval_in("x = 100", &mut env).unwrap();
+    calc("f(t) = t^2", &mut env).unwrap();
+
+    let tree = parse("a*x + f(x)", &env).unwrap();
+    for x in [-2.0, 0.0, 0.5, 4.0] {
+        let y = calculate_at(&tree, &env, "x", x).unwrap();
+        assert!((y - (3.0 * x + x * x)).abs() < EPS, "x = {x}: got {y}");
+    }
+
+    let slope = parse("diff(sin(x), x)", &env).unwrap();
+    assert!((calculate_at(&slope, &env, "x", 0.0).unwrap() - 1.0).abs() < EPS);
+
+    assert!(calculate_at(&parse("sqrt(x)", &env).unwrap(), &env, "x", -1.0).is_err());
+    assert!(parse("y = 2*x", &env).is_err());
+    assert!(parse("2*(x", &env).is_err());
+}
+
+#[test]
+fn compiled_expressions_match_the_evaluator() {
+    let mut env = Env::new();
+    eval_in("a = 2.5", &mut env).unwrap();
+    calc("f(t) = t^2 - a", &mut env).unwrap();
+    calc("g(u, v) = f(u) * v + u", &mut env).unwrap();
+
+    let exprs = [
+        "3*x^2 - 2*x + 1",
+        "-x // 2 + x % 3",
+        "sin(x)*exp(-x/4) + max(x, 1, a)",
+        "g(x, y) - f(y)",
+        "diff(x^3 + y*x, x)",
+        "log(2, abs(x) + 1)",
+    ];
+    for expr in exprs {
+        let tree = parse(expr, &env).unwrap();
+        let program = compile(&tree, &env, &["x", "y"]).unwrap();
+        for (x, y) in [(-3.5, 1.0), (0.0, 2.0), (1.25, -0.5), (7.0, 3.0)] {
+            let expected = {
+                let mut env = env.clone();
+                env.set("x", Value::Number(Number::Float(x)));
+                env.set("y", Value::Number(Number::Float(y)));
+                calculate_at(&tree, &env, "x", x).unwrap()
+            };
+            let res = program.eval(&[x, y]);
+            assert!((res - expected).abs() < EPS, "{expr} at ({x}, {y}): expected {expected}, got {res}");
+        }
+    }
+
+    let program = compile(&parse("sqrt(x)", &env).unwrap(), &env, &["x"]).unwrap();
+    assert!(program.eval(&[-1.0]).is_nan());
+
+    for expr in ["2 km * x", "x & 1", "i*x", "solve(t = x, t, 0)", "h(x)", "z + x"] {
+        assert!(compile(&parse(expr, &env).unwrap(), &env, &["x"]).is_err(), "{expr}");
+    }
+    calc("r(n) = n * r(n - 1)", &mut env).unwrap();
+    assert!(compile(&parse("r(x)", &env).unwrap(), &env, &["x"]).is_err());
+}
+
+#[test]
+fn previous_results_are_referenced() {
+    let mut env = Env::new();
+    assert_eq!(eval_in("2 + 3", &mut env).unwrap(), 5.0);
+    assert_eq!(eval_in("ans * 2", &mut env).unwrap(), 10.0);
+    calc("f(n) = n + ans", &mut env).unwrap();
+    assert_eq!(eval_in("x = $1 + $2", &mut env).unwrap(), 15.0);
+    assert_eq!(env.results().len(), 3);
+    assert_eq!(eval_in("f(1)", &mut env).unwrap(), 16.0);
+    assert_eq!(eval_in("$3*$1 - ans", &mut env).unwrap(), 59.0);
+
+    let err = eval_in("$9 + 1", &mut env).unwrap_err();
+    assert_eq!(err.message, "there is no result $9 yet");
+    assert_eq!(err.span, Some(Span::new(0, 2)));
+    assert!(eval_in("ans", &mut Env::new()).is_err());
+    assert_eq!(eval_in("$ + 1", &mut env).unwrap_err().stage, Stage::Parse);
+    assert!(eval_in("ans = 3", &mut env).is_err());
+    assert!(eval_in("$2 = 3", &mut env).is_err());
+}
+
+#[test]
+fn float_literal_syntax() {
+    assert_calc("6.02e23 / 1e23", 6.02);
+    assert_calc(".5 + 5.", 5.5);
+    assert_calc("1_000_000 + 0xFF_FF", 1_000_000.0 + 65535.0);
+    assert_calc("2.5E+2 * 4e-2", 10.0);
+    assert_calc("1e2i * i", -100.0);
+    assert_eq!(show_in("1e-3 + 1/1000", NumberMode::Exact), "1/500");
+    assert_eq!(show_in("1.5e3 m in km", NumberMode::Float), "1.5 km");
+    assert_error("1e99999 + 1", Stage::Parse, Span::new(0, 7));
+    assert_error("1__0", Stage::Rpn, Span::new(1, 4));
+}
+
+#[test]
+fn results_past_the_float_range_overflow() {
+    let show = |expr: &str| show_in(expr, NumberMode::Float);
+    assert!(show("1e999999999").contains("overflow in number '1e999999999'"));
+    assert!(show("1e-999999999").contains("underflow in number"));
+    assert_error("2 * 1e400", Stage::Calculate, Span::new(4, 9));
+    assert_error("171!", Stage::Calculate, Span::new(0, 4));
+    assert_error("10^9999", Stage::Calculate, Span::new(0, 7));
+    assert_error("[1, exp(1000)]", Stage::Calculate, Span::new(4, 13));
+    assert!(show("1/0").contains("division by 0"));
+    assert_eq!(show("170! > 0"), "true");
+    assert_eq!(show_in("1e400 / 1e399", NumberMode::Exact), "10");
+}
+
+#[test]
+fn named_constants() {
+    assert_calc("pi", std::f64::consts::PI);
+    assert_calc("tau / 2 - pi", 0.0);
+    assert_calc("ln(e)", 1.0);
+    assert_calc("phi^2 - phi", 1.0);
+    assert_calc("e = 2", 2.0);
+
+    let mut env = Env::new();
+    let config = "# physics\nc = 299792458 m/s\nhalf = 1/2\nyear_c = c * 1 yr  # a light year\n";
+    assert_eq!(define_constants(&mut env, config), Ok(()));
+    assert_eq!(eval_in("half + 1", &mut env).unwrap(), 1.5);
+    env.set_mode(NumberMode::Exact);
+    match calc("half + 1/3", &mut env).unwrap() {
+        Outcome::Value(value) => assert_eq!(value.to_string(), "5/6"),
+        other => panic!("{other:?}"),
+    }
+    env.set_mode(NumberMode::Float);
+    let tree = parse("half * x", &env).unwrap();
+    assert_eq!(compile(&tree, &env, &["x"]).unwrap().eval(&[3.0]), 1.5);
+    assert!(calc("year_c in km", &mut env).is_ok());
+    assert!(define_constants(&mut env, "ok = 1\nsin = 2").unwrap_err().starts_with("line 2"));
+    assert!(define_constants(&mut env, "bad = nothing").is_err());
+}
+
+#[test]
+fn factorial_percent_and_mod() {
+    assert_calc("5!", 120.0);
+    assert_calc("-3!", -6.0);
+    assert_calc("2^3!", 64.0);
+    assert_calc("3!!", 720.0);
+    assert_calc("0.5!", std::f64::consts::PI.sqrt() / 2.0);
+    assert_calc("gamma(0.5)^2", std::f64::consts::PI);
+    assert_calc("(-0.5)!", std::f64::consts::PI.sqrt());
+    assert_eq!(show_in("25!", NumberMode::Exact), "15511210043330985984000000");
+    assert_error("(-2)!", Stage::Calculate, Span::new(1, 5));
+    assert_error("%3", Stage::Rpn, Span::new(0, 1));
+
+    assert_calc("50%", 0.5);
+    assert_calc("200 + 10%", 220.0);
+    assert_calc("200 - 10% - 5", 175.0);
+    assert_calc("200 * 10%", 20.0);
+    assert_calc("(100 + 50%) * 2", 300.0);
+    assert_calc("7 % 3 + 7 mod 4 + -7 mod 3", 1.0 + 3.0 + 2.0);
+    assert_calc("5! mod 7", 1.0);
+    assert_eq!(show_in("1/3 + 50%", NumberMode::Exact), "1/2");
+
+    let env = Env::new();
+    for expr in ["x! + 1", "-(x!)", "(x + 1)%", "x - 5%", "10% % x"] {
+        assert_eq!(parse(expr, &env).unwrap().to_string(), expr.replace("-(x!)", "-x!"));
+    }
+    let program = compile(&parse("x! + 200 + x%", &env).unwrap(), &env, &["x"]).unwrap();
+    assert!((program.eval(&[4.0]) - 224.0 * 1.04).abs() < EPS);
+    match calc("diff(x^2 + 10%, x)", &mut Env::new()).unwrap() {
+        Outcome::Symbolic(text) => assert_eq!(text, "2*x + 2*x*10%"),
+        other => panic!("{other:?}"),
+    }
+}
+
+#[test]
+fn comparisons_and_conditionals() {
+    let show = |expr: &str| show_in(expr, NumberMode::Float);
+    assert_eq!(show("2 < 3"), "true");
+    assert_eq!(show("3 >= 4"), "false");
+    assert_eq!(show("1 != 1"), "false");
+    assert_eq!(show_in("1/3 + 1/3 == 2/3", NumberMode::Exact), "true");
+    assert_eq!(show("1 + 1 == 2 && 3 > 2"), "true");
+    assert_eq!(show("5 & 1 == 1"), "true");
+    assert_eq!(show("!(1 > 2) && !0"), "true");
+    assert_eq!(show("1 km > 900 m"), "true");
+    assert_eq!(show("i == i"), "true");
+    // the right side is not evaluated once the left decides
+    assert_eq!(show("1 < 2 || 1/0 > 0"), "true");
+    assert_eq!(show("0 && 1/0"), "false");
+    assert_calc("(1 < 2) * 5 + !1", 5.0);
+    assert_calc("3! != 6 ? 1 : 2", 2.0);
+
+    assert_calc("2 > 1 ? 10 : 20", 10.0);
+    assert_calc("if(0, 1, 2)", 2.0);
+    assert_calc("-2 < 0 ? -1 : -2 == 0 ? 0 : 1", -1.0);
+    assert_calc("1 ? 2 ? 3 : 4 : 5", 3.0);
+    let mut env = Env::new();
+    calc("fact(n) = n <= 1 ? 1 : n * fact(n - 1)", &mut env).unwrap();
+    calc("fib(n) = if(n < 2, n, fib(n - 1) + fib(n - 2))", &mut env).unwrap();
+    assert_eq!(eval_in("fact(10)", &mut env).unwrap(), 3628800.0);
+    assert_eq!(eval_in("fib(15)", &mut env).unwrap(), 610.0);
+
+    assert!(eval("1 km < 1 s").unwrap_err().message.contains("cannot compare"));
+    assert!(eval("i < 1").unwrap_err().message.contains("ordered"));
+    assert!(eval("1 km ? 1 : 2").is_err());
+    assert_error("1 ? 2", Stage::Rpn, Span::new(2, 3));
+    assert_error("1 : 2", Stage::Rpn, Span::new(2, 3));
+    assert_error("if(1, 2)", Stage::Rpn, Span::new(0, 8));
+
+    for expr in ["x > 0 ? x : -x", "!(a*b) || c <= 2", "(a ? b : c) ? d : e ? f : g"] {
+        assert_eq!(parse(expr, &env).unwrap().to_string(), expr);
+    }
+    assert_eq!(parse("if(x, 1, 2)", &env).unwrap().to_string(), "x ? 1 : 2");
+
+    let program = compile(&parse("x < 0 ? -x : x^2", &env).unwrap(), &env, &["x"]).unwrap();
+    assert_eq!((program.eval(&[-3.0]), program.eval(&[2.0])), (3.0, 4.0));
+    assert!(compile(&parse("fact(x)", &env).unwrap(), &env, &["x"]).is_err());
+    match calc("diff(x > 0 ? x^2 : -x, x)", &mut Env::new()).unwrap() {
+        Outcome::Symbolic(text) => assert_eq!(text, "x > 0 ? 2*x : -1"),
+        other => panic!("{other:?}"),
+    }
+}
+
+#[test]
+fn vectors_and_matrices() {
+    let show = |expr: &str| show_in(expr, NumberMode::Exact);
+    assert_eq!(show("[1, 2, 3]"), "[1, 2, 3]");
+    assert_eq!(show("[[1, 2], [3, 4]]"), "[[1, 2], [3, 4]]");
+    assert_eq!(show("[1, 2] + [3, 4] * 2"), "[7, 10]");
+    assert_eq!(show("[1, 2] .* [3, 4] - 1"), "[2, 7]");
+    assert_eq!(show("[[1, 2], [3, 4]] ./ [[1, 2], [3, 8]]"), "[[1, 1], [1, 1/2]]");
+    assert_eq!(show("-[1, 1 < 2]"), "[-1, -1]");
+    assert_eq!(show("sqrt([4, 9])"), "[2, 3]");
+
+    assert_eq!(show("[[1, 2], [3, 4]] * [[0, 1], [1, 0]]"), "[[2, 1], [4, 3]]");
+    assert_eq!(show("[[1, 2], [3, 4]] * [1, 1]"), "[3, 7]");
+    assert_eq!(show("[1, 1] * [[1, 2], [3, 4]]"), "[4, 6]");
+    assert_eq!(show("[[1, 1], [1, 0]]^10"), "[[89, 55], [55, 34]]");
+    assert!(show("[[2, 0], [0, 2]]^100000000").contains("exponent is too large"));
+    assert!(show("[[2^1000, 0], [0, 1]]^5000").contains("result is too large"));
+    assert_eq!(show("det([[2, 0, 1], [1, 3, 2], [1, 1, 2]])"), "6");
+    assert_eq!(show("inv([[4, 7], [2, 6]])"), "[[3/5, -7/10], [-1/5, 2/5]]");
+    assert_eq!(show("[[4, 7], [2, 6]]^-1 * [[4, 7], [2, 6]] == [[1, 0], [0, 1]]"), "true");
+    assert_eq!(show("transpose([[1, 2, 3], [4, 5, 6]])"), "[[1, 4], [2, 5], [3, 6]]");
+    assert_eq!(show("dot([1, 2, 3], [4, 5, 6])"), "32");
+    assert_eq!(show("cross([1, 0, 0], [0, 1, 0])"), "[0, 0, 1]");
+    let mut env = Env::new();
+    calc("m = [[1, 2], [3, 4]]", &mut env).unwrap();
+    assert_eq!(eval_in("det(m)", &mut env).unwrap(), -2.0);
+
+    let message = |expr: &str| eval(expr).unwrap_err().message;
+    assert!(message("[[1, 2], [3]]").contains("same length"));
+    assert!(message("[1, [2]]").contains("vector of rows"));
+    assert!(message("[1, 2] * [3, 4]").contains("dot"));
+    assert!(message("[1, 2] + [1, 2, 3]").contains("length 3"));
+    assert!(message("[[1, 2], [3, 4]] * [[1, 2]]").contains("2×2"));
+    assert!(message("inv([[1, 2], [2, 4]])").contains("singular"));
+    assert!(message("det([1, 2])").contains("matrix"));
+    assert!(message("atan2([1, 2], 3)").contains("vector"));
+    assert!(message("[1, 2] < [3, 4]").contains("compared"));
+    assert!(message("det").contains("function"));
+    assert_error("[]", Stage::Rpn, Span::new(1, 2));
+    assert_error("[1, 2", Stage::Rpn, Span::new(0, 1));
+    assert_error("(1 + 2]", Stage::Rpn, Span::new(6, 7));
+    assert!(calc("det(x) = x", &mut Env::new()).is_err());
+
+    for expr in ["[x, 2*y]", "[[1, 2], [a .* b, -c]]"] {
+        assert_eq!(parse(expr, &env).unwrap().to_string(), expr);
+    }
+    let program = compile(&parse("x .* 3 ./ 2", &env).unwrap(), &env, &["x"]).unwrap();
+    assert_eq!(program.eval(&[2.0]), 3.0);
+    assert!(compile(&parse("[x, 1]", &env).unwrap(), &env, &["x"]).is_err());
+    match calc("diff([x^2, 3*x], x)", &mut Env::new()).unwrap() {
+        Outcome::Symbolic(text) => assert_eq!(text, "[2*x, 3]"),
+        other => panic!("{other:?}"),
+    }
+}
+
+#[test]
+fn statistics_over_lists() {
+    let show = |expr: &str| show_in(expr, NumberMode::Exact);
+    assert_eq!(show("sum(1, 2, 3, 4)"), "10");
+    assert_eq!(show("mean([1, 2, 3, 4])"), "5/2");
+    assert_eq!(show("mean([1, 2], 3, [[4, 5]])"), "3");
+    assert_eq!(show("median(5, 1, 3)"), "3");
+    assert_eq!(show("median([4, 1, 3, 2])"), "5/2");
+    assert_eq!(show("var(2, 4, 4, 4, 5, 5, 7, 9)"), "32/7");
+    assert_eq!(show("stdev(1, 3)"), "1.4142135623730951");
+    assert_eq!(show("stdev([1, 3, 5])"), "2");
+    assert_eq!(show("percentile([1, 2, 3, 4, 5], 25)"), "2");
+    assert_eq!(show("percentile(10, 20, 90)"), "19");
+    assert_eq!(show("max([3, 8], 5) - min([3, 8], 5)"), "5");
+    assert_calc("mean(1 < 2, 1 > 2)", 0.5);
+
+    let message = |expr: &str| eval(expr).unwrap_err().message;
+    assert!(message("mean([])").contains("empty vector"));
+    assert!(message("var(1)").contains("at least 2"));
+    assert!(message("percentile([1, 2], 120)").contains("between 0 and 100"));
+    assert!(message("percentile(50)").contains("at least one value"));
+    assert!(message("median(1 m, 2 m)").contains("plain numbers"));
+    assert!(message("median(i, 1)").contains("ordered"));
+    assert!(calc("mean(x) = x", &mut Env::new()).is_err());
+
+    let mut env = Env::new();
+    env.set_mode(NumberMode::Exact);
+    let data = "12\n 15.5\n\n3*4\n";
+    assert_eq!(store_data(&mut env, "data", data).unwrap().to_string(), "[12, 31/2, 12]");
+    assert_eq!(eval_in("mean(data)", &mut env).unwrap(), 13.166666666666666);
+    assert_eq!(store_data(&mut env, "d", "1\nx").unwrap_err(), "line 2: Calculate Error: undefined variable 'x' (column 1)");
+    assert!(store_data(&mut env, "d", "[1, 2]").unwrap_err().contains("line 1: expected a number"));
+    assert!(store_data(&mut env, "$1", "1").is_err());
+    assert!(store_data(&mut env, "d", " \n").is_err());
+}
+
+#[test]
+fn traces_explain_the_steps() {
+    let mut env = Env::new();
+    env.set_trace(true);
+    calc("x = 3", &mut env).unwrap();
+    calc("-x^2 + max(1, 2) * 10%", &mut env).unwrap();
+    let trace = env.last_trace().unwrap();
+    assert_eq!(trace.tokens, "u- x ^ 2 + max ( 1 , 2 ) * 10 %");
+    assert_eq!(trace.rpn, "x 2 ^ u- 1 2 max(·,·) 10 % * +");
+    let step = |token: &str| trace.steps.iter().find(|step| step.token == token).unwrap();
+    assert_eq!((step("+").stack.as_str(), step("+").output.as_str()), ("+", "x 2 ^ u-"));
+    assert_eq!(step(",").stack, "+ max(·,·) (");
+    assert_eq!(trace.steps.last().unwrap().output, trace.rpn);
+    assert_eq!(
+        trace.reductions,
+        ["x = 3", "3^2 = 9", "-9 = -9", "max(1, 2) = 2", "10% = 0.1", "2*0.1 = 0.2", "(-9) + 0.2 = -8.8"]
+    );
+
+    // only the branch taken is evaluated, once
+    calc("f(n) = n < 1 ? 0 : f(n - 1)", &mut env).unwrap();
+    calc("x > 1 || 1/0 > 0 ? f(2) + 5% : 1/0", &mut env).unwrap();
+    assert_eq!(
+        env.last_trace().unwrap().reductions,
+        ["x = 3", "3 > 1 = true", "true || 1/0 > 0 = true", "f(2) = 0", "0 + 5% = 0", "true ? 0 : 1/0 = 0"]
+    );
+
+    // a failed calculation keeps the steps up to the error
+    assert!(calc("2 * (1 +", &mut env).is_err());
+    let trace = env.last_trace().unwrap();
+    assert_eq!(trace.steps.last().unwrap().stack, "* ( +");
+    assert!(trace.rpn.is_empty());
+    assert!(calc("[1, 2] * [3, 4]", &mut env).is_err());
+    assert_eq!(env.last_trace().unwrap().reductions, Vec::<String>::new());
+    assert!(Env::new().last_trace().is_none());
+}
+
+#[test]
+fn results_follow_the_format() {
+    fn value_in(expr: &str, env: &mut Env) -> Value {
+        match calc(expr, env) {
+            Ok(Outcome::Value(value)) => value,
+            other => panic!("{expr}: expected a value, got {other:?}"),
+        }
+    }
+
+    let mut env = Env::new();
+    let mut show = |expr: &str, notation: Notation| {
+        let value = value_in(expr, &mut env);
+        Format { notation, ..Format::default() }.value(&value)
+    };
+    assert_eq!(show("0.1 + 0.2", Notation::Auto), "0.3");
+    assert_eq!(show("2^70", Notation::Auto), "1.18059162071741e21");
+    assert_eq!(show("1/3", Notation::Fixed(3)), "0.333");
+    assert_eq!(show("2/3", Notation::Significant(4)), "0.6667");
+    assert_eq!(show("12345.678", Notation::Significant(2)), "12000");
+    assert_eq!(show("12345.678", Notation::Scientific(3)), "1.23e4");
+    assert_eq!(show("12345.678", Notation::Engineering(3)), "12.3e3");
+    assert_eq!(show("-0.00012", Notation::Engineering(2)), "-120e-6");
+    assert_eq!(show("1 + 2i", Notation::Fixed(1)), "1.0+2.0i");
+    assert_eq!(show("[1/4, 5] * 2", Notation::Fixed(2)), "[0.50, 10.00]");
+    assert_eq!(show("-0.001", Notation::Fixed(2)), "0.00");
+    assert_eq!(show("-0.001", Notation::Fixed(0)), "0");
+    assert_eq!(show("-0.006", Notation::Fixed(2)), "-0.01");
+    assert_eq!(show("-0", Notation::Auto), "0");
+
+    let format = Format { notation: Notation::Fixed(2), grouping: true, decimal_comma: false };
+    assert_eq!(format.value(&value_in("-1234567.891", &mut env)), "-1,234,567.89");
+    let format = Format { grouping: true, decimal_comma: true, ..format };
+    assert_eq!(format.value(&value_in("[1234.5, 2]", &mut env)), "[1 234,50; 2,00]");
+
+    env.set_mode(NumberMode::Exact);
+    assert_eq!(format.value(&value_in("1000/3", &mut env)), "1 000/3");
+    env.set_mode(NumberMode::Decimal(30));
+    assert_eq!(format.value(&value_in("1/8", &mut env)), "0,13");
+    assert_eq!(format.value(&value_in("-0.001", &mut env)), "0,00");
+
+    // a decimal comma in the input, with `;` between arguments
+    env.set_mode(NumberMode::Float);
+    env.set_format(format);
+    assert_eq!(eval_in("1,5 + max(2,5; 1)", &mut env).unwrap(), 4.0);
+    assert_eq!(value_in("[1,5; 2]", &mut env).to_string(), "[1.5, 2]");
+    assert_eq!(eval_in("max(1, 2)", &mut env).unwrap(), 2.0);
+    assert_eq!(calc("1,5 +", &mut env).unwrap_err().span, Some(Span::new(4, 5)));
+}
@@ -0,0 +1,265 @@
+use std::fmt;
+
+use crate::engine::error::Span;
+use crate::engine::number::Literal;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+    IntDiv,
+    Mod,
+    BitAnd,
+    BitOr,
+    BitXor,
+    Shl,
+    Shr,
+    Less,
+    LessEq,
+    Greater,
+    GreaterEq,
+    Equal,
+    NotEqual,
+    And,
+    Or,
+    // `.*` and `./`, element by element where `*` is the matrix product
+    ElemMul,
+    ElemDiv,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnOp {
+    Plus,
+    Minus,
+    Not,
+    // `!x`, logical negation; after an operand `!` is the factorial
+    LogicalNot,
+}
+
+// operators written after their operand
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PostOp {
+    Factorial,
+    // `10%` is 0.1, but `200 + 10%` adds 10% of 200
+    Percent,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenKind {
+    Number(Literal),
+    BinOp(BinOp),
+    UnOp(UnOp),
+    PostOp(PostOp),
+    LParen,
+    RParen,
+    Comma,
+    Assign,
+    Ident(String),
+    // a function call with its argument count, produced by the parser
+    Call(String, usize),
+    // postfix `in km` / `to km`
+    Convert(String),
+    // `cond ? a : b`
+    Question,
+    Colon,
+    // `[1, 2]`; the parser turns the opening bracket into a `Vector` with
+    // its element count
+    LBracket,
+    RBracket,
+    Vector(usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Span,
+}
+
+impl BinOp {
+    pub fn from_char(ch: char) -> Option<BinOp> {
+        match ch {
+            '+' => Some(BinOp::Add),
+            '-' => Some(BinOp::Sub),
+            '*' => Some(BinOp::Mul),
+            '/' => Some(BinOp::Div),
+            '^' => Some(BinOp::Pow),
+            '%' => Some(BinOp::Mod),
+            '&' => Some(BinOp::BitAnd),
+            '|' => Some(BinOp::BitOr),
+            '<' => Some(BinOp::Less),
+            '>' => Some(BinOp::Greater),
+            _ => None,
+        }
+    }
+
+    // operators written with two characters
+    pub fn from_pair(pair: &str) -> Option<BinOp> {
+        match pair {
+            "//" => Some(BinOp::IntDiv),
+            "<<" => Some(BinOp::Shl),
+            ">>" => Some(BinOp::Shr),
+            "<=" => Some(BinOp::LessEq),
+            ">=" => Some(BinOp::GreaterEq),
+            "==" => Some(BinOp::Equal),
+            "!=" => Some(BinOp::NotEqual),
+            "&&" => Some(BinOp::And),
+            "||" => Some(BinOp::Or),
+            ".*" => Some(BinOp::ElemMul),
+            "./" => Some(BinOp::ElemDiv),
+            _ => None,
+        }
+    }
+
+    // operators written as words, `6 xor 3` and `7 mod 3`
+    pub fn from_word(word: &str) -> Option<BinOp> {
+        match word {
+            "xor" => Some(BinOp::BitXor),
+            "mod" => Some(BinOp::Mod),
+            _ => None,
+        }
+    }
+
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            BinOp::Add => "+",
+            BinOp::Sub => "-",
+            BinOp::Mul => "*",
+            BinOp::Div => "/",
+            BinOp::Pow => "^",
+            BinOp::IntDiv => "//",
+            BinOp::Mod => "%",
+            BinOp::BitAnd => "&",
+            BinOp::BitOr => "|",
+            BinOp::BitXor => "xor",
+            BinOp::Shl => "<<",
+            BinOp::Shr => ">>",
+            BinOp::Less => "<",
+            BinOp::LessEq => "<=",
+            BinOp::Greater => ">",
+            BinOp::GreaterEq => ">=",
+            BinOp::Equal => "==",
+            BinOp::NotEqual => "!=",
+            BinOp::And => "&&",
+            BinOp::Or => "||",
+            BinOp::ElemMul => ".*",
+            BinOp::ElemDiv => "./",
+        }
+    }
+
+    // comparisons bind looser than the bitwise operators, so `x & 1 == 0`
+    // tests the low bit
+    pub fn priority(&self) -> i8 {
+        match self {
+            BinOp::Or => 2,
+            BinOp::And => 3,
+            BinOp::Less | BinOp::LessEq | BinOp::Greater | BinOp::GreaterEq | BinOp::Equal | BinOp::NotEqual => 4,
+            BinOp::BitOr => 5,
+            BinOp::BitXor => 6,
+            BinOp::BitAnd => 7,
+            BinOp::Shl | BinOp::Shr => 8,
+            BinOp::Add | BinOp::Sub => 9,
+            BinOp::Mul | BinOp::Div | BinOp::IntDiv | BinOp::Mod | BinOp::ElemMul | BinOp::ElemDiv => 10,
+            BinOp::Pow => 12,
+        }
+    }
+
+    pub fn is_right_associative(&self) -> bool {
+        *self == BinOp::Pow
+    }
+}
+
+impl UnOp {
+    pub fn from_char(ch: char) -> Option<UnOp> {
+        match ch {
+            '+' => Some(UnOp::Plus),
+            '-' => Some(UnOp::Minus),
+            '~' => Some(UnOp::Not),
+            _ => None,
+        }
+    }
+
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            UnOp::Plus => "+",
+            UnOp::Minus => "-",
+            UnOp::Not => "~",
+            UnOp::LogicalNot => "!",
+        }
+    }
+}
+
+impl PostOp {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            PostOp::Factorial => "!",
+            PostOp::Percent => "%",
+        }
+    }
+}
+
+// unary operators bind tighter than `*` but looser than `^`, so `-2^2` is -4
+// while `2^-2` still works because prefix operators never pop the stack
+pub static UNARY_PRIORITY: i8 = 11;
+
+// postfix operators bind tightest of all, `-3!` is -6 and `2^3!` is 64; they
+// never wait on the operator stack, so this only orders them for printing
+pub static POSTFIX_PRIORITY: i8 = 13;
+
+// `?:` binds loosest of the operators and groups to the right, so
+// `a ? b : c ? d : e` picks one of three values
+pub static CONDITIONAL_PRIORITY: i8 = 1;
+
+impl TokenKind {
+    pub fn priority(&self) -> i8 {
+        match self {
+            TokenKind::BinOp(op) => op.priority(),
+            TokenKind::UnOp(_) => UNARY_PRIORITY,
+            TokenKind::PostOp(_) => POSTFIX_PRIORITY,
+            TokenKind::Question | TokenKind::Colon => CONDITIONAL_PRIORITY,
+            _ => 0,
+        }
+    }
+}
+
+impl Token {
+    pub fn new(kind: TokenKind, span: Span) -> Token {
+        Token { kind, span }
+    }
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.kind {
+            TokenKind::Number(literal) => match &literal.unit {
+                Some(unit) => write!(f, "{}[{unit}]", literal.text),
+                None => write!(f, "{}", literal.text),
+            },
+            TokenKind::BinOp(op) => write!(f, "{}", op.symbol()),
+            TokenKind::UnOp(op) => write!(f, "u{}", op.symbol()),
+            TokenKind::PostOp(op) => write!(f, "{}", op.symbol()),
+            TokenKind::LParen => write!(f, "("),
+            TokenKind::RParen => write!(f, ")"),
+            TokenKind::Comma => write!(f, ","),
+            TokenKind::Assign => write!(f, "="),
+            TokenKind::Ident(name) => write!(f, "{name}"),
+            TokenKind::Call(name, argc) => write!(f, "{name}({})", vec!["·"; *argc].join(",")),
+            TokenKind::Convert(unit) => write!(f, "in[{unit}]"),
+            TokenKind::Question => write!(f, "?"),
+            TokenKind::Colon => write!(f, ":"),
+            TokenKind::LBracket => write!(f, "["),
+            TokenKind::RBracket => write!(f, "]"),
+            TokenKind::Vector(len) => write!(f, "[{}]", vec!["·"; *len].join(",")),
+        }
+    }
+}
+
+pub fn join_tokens(tokens: &[Token]) -> String {
+    tokens
+        .iter()
+        .map(|token| token.to_string())
+        .collect::<Vec<String>>()
+        .join(" ")
+}
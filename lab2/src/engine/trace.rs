@@ -0,0 +1,229 @@
+use std::fmt;
+
+use crate::engine::ast::{Expr, ExprKind};
+use crate::engine::env::Env;
+use crate::engine::error::CalcError;
+use crate::engine::eval::calculate;
+use crate::engine::functions::is_special_form;
+use crate::engine::token::{join_tokens, BinOp, PostOp, Token};
+use crate::engine::value::Value;
+
+// the shunting-yard state after reading one token
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseStep {
+    pub token: String,
+    pub stack: String,
+    pub output: String,
+}
+
+impl ParseStep {
+    pub fn new(token: &str, stack: &[Token], output: &[Token]) -> ParseStep {
+        ParseStep { token: token.to_string(), stack: join_tokens(stack), output: join_tokens(output) }
+    }
+}
+
+// how `calc` got to its result, for the explain panel and `lab2-cli -v`;
+// parts that were not reached are left empty
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Trace {
+    pub tokens: String,
+    pub steps: Vec<ParseStep>,
+    pub rpn: String,
+    // `3 * 4 = 12` for every operator and call, in the order of the RPN
+    pub reductions: Vec<String>,
+}
+
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Tokens: {}", self.tokens)?;
+        if !self.steps.is_empty() {
+            let token_width = self.steps.iter().map(|step| step.token.chars().count()).max().unwrap_or(0).max(5);
+            let stack_width = self.steps.iter().map(|step| step.stack.chars().count()).max().unwrap_or(0).max(5);
+            writeln!(f, "Shunting-yard:")?;
+            writeln!(f, "  {:token_width$}  {:stack_width$}  output", "token", "stack")?;
+            for step in &self.steps {
+                writeln!(f, "  {:token_width$}  {:stack_width$}  {}", step.token, step.stack, step.output)?;
+            }
+        }
+        if !self.rpn.is_empty() {
+            writeln!(f, "RPN: {}", self.rpn)?;
+        }
+        if !self.reductions.is_empty() {
+            writeln!(f, "Reductions:")?;
+            for reduction in &self.reductions {
+                writeln!(f, "  {reduction}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// a value as it appears inside a step, parenthesized unless it is a single word
+fn operand_text(value: &Value) -> String {
+    let text = value.to_string();
+    if text.starts_with('[') || text.chars().all(|ch| ch.is_alphanumeric() || ch == '.') {
+        text
+    } else {
+        format!("({text})")
+    }
+}
+
+// evaluates the operands of every node before the node itself, as the RPN
+// is reduced, and records each step with the values it combined
+struct Reducer<'a> {
+    // the environment plus the intermediate values, under names that no
+    // expression can spell
+    scratch: Env,
+    count: usize,
+    reductions: &'a mut Vec<String>,
+}
+
+impl Reducer<'_> {
+    // `expr` reduced to a value, then stood in for by a variable: one to
+    // evaluate the parent with, one to show it
+    fn operand(&mut self, expr: &Expr) -> Result<(Value, Expr, Expr), CalcError> {
+        let value = self.reduce(expr)?;
+        let name = format!("\u{0}{}", self.count);
+        self.count += 1;
+        self.scratch.set(&name, value.clone());
+        let shown = Expr::new(ExprKind::Var(operand_text(&value)), expr.span);
+        Ok((value, Expr::new(ExprKind::Var(name), expr.span), shown))
+    }
+
+    fn operands(&mut self, exprs: &[Expr]) -> Result<(Vec<Expr>, Vec<Expr>), CalcError> {
+        let mut vars = Vec::with_capacity(exprs.len());
+        let mut shown = Vec::with_capacity(exprs.len());
+        for expr in exprs {
+            let (_, var, text) = self.operand(expr)?;
+            vars.push(var);
+            shown.push(text);
+        }
+        Ok((vars, shown))
+    }
+
+    // the larger kinds of node are taken apart in their own methods, as in
+    // `calculate`, to keep the frame of `reduce` small while it recurses
+    fn reduce(&mut self, expr: &Expr) -> Result<Value, CalcError> {
+        let (eval, shown) = match &expr.kind {
+            ExprKind::Number(_) => return calculate(expr, &self.scratch),
+            ExprKind::Var(name) => {
+                let value = calculate(expr, &self.scratch)?;
+                self.reductions.push(format!("{name} = {value}"));
+                return Ok(value);
+            }
+            ExprKind::Unary(op, arg) => {
+                let (_, var, text) = self.operand(arg)?;
+                (ExprKind::Unary(*op, Box::new(var)), ExprKind::Unary(*op, Box::new(text)))
+            }
+            ExprKind::Postfix(op, arg) => {
+                let (_, var, text) = self.operand(arg)?;
+                (ExprKind::Postfix(*op, Box::new(var)), ExprKind::Postfix(*op, Box::new(text)))
+            }
+            ExprKind::Binary(op @ (BinOp::Add | BinOp::Sub), lhs, rhs)
+                if matches!(rhs.kind, ExprKind::Postfix(PostOp::Percent, _)) =>
+            {
+                self.reduce_share(*op, lhs, rhs)?
+            }
+            ExprKind::Binary(op @ (BinOp::And | BinOp::Or), lhs, rhs) => self.reduce_logic(*op, lhs, rhs)?,
+            ExprKind::Binary(op, lhs, rhs) => self.reduce_binary(*op, lhs, rhs)?,
+            ExprKind::Conditional(cond, then, otherwise) => self.reduce_conditional(cond, then, otherwise)?,
+            ExprKind::Call(name, args) if !is_special_form(name) => {
+                let (vars, texts) = self.operands(args)?;
+                (ExprKind::Call(name.clone(), vars), ExprKind::Call(name.clone(), texts))
+            }
+            ExprKind::Vector(items) => {
+                let (vars, texts) = self.operands(items)?;
+                (ExprKind::Vector(vars), ExprKind::Vector(texts))
+            }
+            ExprKind::Convert(value, unit, span) => {
+                let (_, var, text) = self.operand(value)?;
+                (ExprKind::Convert(Box::new(var), unit.clone(), *span), ExprKind::Convert(Box::new(text), unit.clone(), *span))
+            }
+            // `diff`, `solve` and `nsolve` work on their arguments as expressions
+            ExprKind::Call(_, _) | ExprKind::Equation(_, _) => (expr.kind.clone(), expr.kind.clone()),
+        };
+        self.record(expr, &Expr::new(eval, expr.span), &Expr::new(shown, expr.span))
+    }
+
+    // `200 + 10%` takes 10% of 200, so the `%` stays in place
+    fn reduce_share(&mut self, op: BinOp, lhs: &Expr, rhs: &Expr) -> Result<(ExprKind, ExprKind), CalcError> {
+        let share = match &rhs.kind {
+            ExprKind::Postfix(_, share) => share,
+            _ => unreachable!(),
+        };
+        let (_, lhs_var, lhs_text) = self.operand(lhs)?;
+        let (_, share_var, share_text) = self.operand(share)?;
+        let percent = |arg: Expr| Box::new(Expr::new(ExprKind::Postfix(PostOp::Percent, Box::new(arg)), rhs.span));
+        Ok((
+            ExprKind::Binary(op, Box::new(lhs_var), percent(share_var)),
+            ExprKind::Binary(op, Box::new(lhs_text), percent(share_text)),
+        ))
+    }
+
+    // the right side only when the left one does not decide
+    fn reduce_logic(&mut self, op: BinOp, lhs: &Expr, rhs: &Expr) -> Result<(ExprKind, ExprKind), CalcError> {
+        let (value, lhs_var, lhs_text) = self.operand(lhs)?;
+        let decided = value.truth().is_ok_and(|truth| truth == (op == BinOp::Or));
+        let (rhs_var, rhs_text) = if decided {
+            (rhs.clone(), rhs.clone())
+        } else {
+            let (_, var, text) = self.operand(rhs)?;
+            (var, text)
+        };
+        Ok((
+            ExprKind::Binary(op, Box::new(lhs_var), Box::new(rhs_var)),
+            ExprKind::Binary(op, Box::new(lhs_text), Box::new(rhs_text)),
+        ))
+    }
+
+    fn reduce_binary(&mut self, op: BinOp, lhs: &Expr, rhs: &Expr) -> Result<(ExprKind, ExprKind), CalcError> {
+        let (_, lhs_var, lhs_text) = self.operand(lhs)?;
+        let (_, rhs_var, rhs_text) = self.operand(rhs)?;
+        Ok((
+            ExprKind::Binary(op, Box::new(lhs_var), Box::new(rhs_var)),
+            ExprKind::Binary(op, Box::new(lhs_text), Box::new(rhs_text)),
+        ))
+    }
+
+    // only the branch that is taken
+    fn reduce_conditional(
+        &mut self,
+        cond: &Expr,
+        then: &Expr,
+        otherwise: &Expr,
+    ) -> Result<(ExprKind, ExprKind), CalcError> {
+        let (value, cond_var, cond_text) = self.operand(cond)?;
+        let (mut then, mut otherwise) = (then.clone(), otherwise.clone());
+        let (mut then_text, mut otherwise_text) = (then.clone(), otherwise.clone());
+        match value.truth() {
+            Ok(true) => (_, then, then_text) = self.operand(&then)?,
+            Ok(false) => (_, otherwise, otherwise_text) = self.operand(&otherwise)?,
+            Err(_) => {}
+        }
+        Ok((
+            ExprKind::Conditional(Box::new(cond_var), Box::new(then), Box::new(otherwise)),
+            ExprKind::Conditional(Box::new(cond_text), Box::new(then_text), Box::new(otherwise_text)),
+        ))
+    }
+
+    // evaluates the node with its operands in place and records the step
+    fn record(&mut self, expr: &Expr, eval: &Expr, shown: &Expr) -> Result<Value, CalcError> {
+        let value = calculate(eval, &self.scratch)?;
+        let shown = shown.to_string();
+        // a negative literal or a vector of plain numbers is no step
+        let literal = match &expr.kind {
+            ExprKind::Unary(_, arg) => matches!(arg.kind, ExprKind::Number(_)),
+            ExprKind::Vector(_) => true,
+            _ => false,
+        };
+        if !(literal && shown == value.to_string()) {
+            self.reductions.push(format!("{shown} = {value}"));
+        }
+        Ok(value)
+    }
+}
+
+// `calculate`, recording the reductions; every node is evaluated once
+pub fn reduce(tree: &Expr, env: &Env, reductions: &mut Vec<String>) -> Result<Value, CalcError> {
+    Reducer { scratch: env.clone(), count: 0, reductions }.reduce(tree)
+}
@@ -0,0 +1,263 @@
+use std::collections::BTreeMap;
+use std::f64::consts::PI;
+use std::fmt;
+
+use num_rational::BigRational;
+use num_traits::{One, ToPrimitive, Zero};
+
+use crate::engine::number::float_to_rational;
+
+// exponents of the SI base quantities: length, mass, time, current,
+// temperature, amount of substance, luminous intensity
+pub type Dimension = [i32; 7];
+
+pub static DIMENSIONLESS: Dimension = [0; 7];
+
+static PREFIXES: [(&str, i32); 12] = [
+    ("T", 12), ("G", 9), ("M", 6), ("k", 3), ("h", 2), ("da", 1),
+    ("d", -1), ("c", -2), ("m", -3), ("u", -6), ("µ", -6), ("n", -9),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnitDef {
+    // size of one unit in SI base units
+    pub factor: BigRational,
+    pub dim: Dimension,
+    pub prefixable: bool,
+}
+
+// a product of named units, e.g. `kg*m/s^2` is {kg: 1, m: 1, s: -2}
+#[derive(Debug, Clone, PartialEq)]
+pub struct Unit {
+    pub parts: BTreeMap<String, i32>,
+    pub factor: BigRational,
+    pub dim: Dimension,
+}
+
+#[derive(Debug, Clone)]
+pub struct UnitTable {
+    units: BTreeMap<String, UnitDef>,
+}
+
+fn dim(exps: &[(usize, i32)]) -> Dimension {
+    let mut dim = DIMENSIONLESS;
+    for (base, exp) in exps {
+        dim[*base] = *exp;
+    }
+    dim
+}
+
+fn decimal(text: &str) -> BigRational {
+    let (int_part, frac_part) = text.split_once('.').unwrap_or((text, ""));
+    let digits: num_bigint::BigInt = format!("{int_part}{frac_part}").parse().unwrap();
+    BigRational::new(digits, num_bigint::BigInt::from(10).pow(frac_part.len() as u32))
+}
+
+fn pow10(exp: i32) -> BigRational {
+    BigRational::from_integer(10.into()).pow(exp)
+}
+
+impl Unit {
+    pub fn one() -> Unit {
+        Unit { parts: BTreeMap::new(), factor: BigRational::one(), dim: DIMENSIONLESS }
+    }
+
+    pub fn is_dimensionless(&self) -> bool {
+        self.dim == DIMENSIONLESS
+    }
+
+    pub fn mul(&self, other: &Unit) -> Unit {
+        let mut parts = self.parts.clone();
+        for (name, exp) in &other.parts {
+            *parts.entry(name.clone()).or_insert(0) += exp;
+        }
+        parts.retain(|_, exp| *exp != 0);
+
+        let mut dim = self.dim;
+        for (i, exp) in other.dim.iter().enumerate() {
+            dim[i] += exp;
+        }
+        Unit { parts, factor: &self.factor * &other.factor, dim }
+    }
+
+    pub fn div(&self, other: &Unit) -> Unit {
+        self.mul(&other.powi(-1))
+    }
+
+    pub fn powi(&self, exp: i32) -> Unit {
+        Unit {
+            parts: self.parts.iter().map(|(name, e)| (name.clone(), e * exp)).collect(),
+            factor: self.factor.pow(exp),
+            dim: self.dim.map(|e| e * exp),
+        }
+    }
+
+    // `sqrt(m^2)` is `m`, `sqrt(m)` has no unit
+    pub fn root(&self, n: i32) -> Option<Unit> {
+        if self.parts.values().any(|exp| exp % n != 0) {
+            return None;
+        }
+        let factor = self.factor.to_f64()?.powf(1.0 / n as f64);
+        Some(Unit {
+            parts: self.parts.iter().map(|(name, e)| (name.clone(), e / n)).collect(),
+            factor: float_to_rational(factor)?,
+            dim: self.dim.map(|e| e / n),
+        })
+    }
+
+    // how many of `other` make one of `self`
+    pub fn ratio(&self, other: &Unit) -> BigRational {
+        &self.factor / &other.factor
+    }
+}
+
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let show = |name: &String, exp: i32| {
+            if exp == 1 { name.clone() } else { format!("{name}^{exp}") }
+        };
+        let over: Vec<String> = self.parts.iter()
+            .filter(|(_, exp)| **exp > 0)
+            .map(|(name, exp)| show(name, *exp))
+            .collect();
+        let under: Vec<String> = self.parts.iter()
+            .filter(|(_, exp)| **exp < 0)
+            .map(|(name, exp)| show(name, -exp))
+            .collect();
+
+        let over = if over.is_empty() { String::from("1") } else { over.join("*") };
+        if under.is_empty() {
+            write!(f, "{over}")
+        } else {
+            write!(f, "{over}/{}", under.join("/"))
+        }
+    }
+}
+
+impl UnitTable {
+    pub fn new() -> UnitTable {
+        let (m, kg, s, a, k, mol, cd) = (0, 1, 2, 3, 4, 5, 6);
+        let mut table = UnitTable { units: BTreeMap::new() };
+        let mut add = |name: &str, factor: &str, exps: &[(usize, i32)], prefixable: bool| {
+            table.units.insert(
+                name.to_string(),
+                UnitDef { factor: decimal(factor), dim: dim(exps), prefixable },
+            );
+        };
+
+        add("m", "1", &[(m, 1)], true);
+        add("g", "0.001", &[(kg, 1)], true);
+        add("s", "1", &[(s, 1)], true);
+        add("A", "1", &[(a, 1)], true);
+        add("K", "1", &[(k, 1)], true);
+        add("mol", "1", &[(mol, 1)], true);
+        add("cd", "1", &[(cd, 1)], true);
+
+        add("inch", "0.0254", &[(m, 1)], false);
+        add("ft", "0.3048", &[(m, 1)], false);
+        add("yd", "0.9144", &[(m, 1)], false);
+        add("mi", "1609.344", &[(m, 1)], false);
+        add("nmi", "1852", &[(m, 1)], false);
+        add("t", "1000", &[(kg, 1)], false);
+        add("lb", "0.45359237", &[(kg, 1)], false);
+        add("oz", "0.028349523125", &[(kg, 1)], false);
+        add("min", "60", &[(s, 1)], false);
+        add("h", "3600", &[(s, 1)], false);
+        add("day", "86400", &[(s, 1)], false);
+        add("week", "604800", &[(s, 1)], false);
+        add("yr", "31557600", &[(s, 1)], false);
+
+        add("N", "1", &[(kg, 1), (m, 1), (s, -2)], true);
+        add("J", "1", &[(kg, 1), (m, 2), (s, -2)], true);
+        add("W", "1", &[(kg, 1), (m, 2), (s, -3)], true);
+        add("Pa", "1", &[(kg, 1), (m, -1), (s, -2)], true);
+        add("Hz", "1", &[(s, -1)], true);
+        add("C", "1", &[(a, 1), (s, 1)], true);
+        add("V", "1", &[(kg, 1), (m, 2), (s, -3), (a, -1)], true);
+        add("ohm", "1", &[(kg, 1), (m, 2), (s, -3), (a, -2)], true);
+        add("L", "0.001", &[(m, 3)], true);
+        add("ha", "10000", &[(m, 2)], false);
+        add("bar", "100000", &[(kg, 1), (m, -1), (s, -2)], true);
+        add("atm", "101325", &[(kg, 1), (m, -1), (s, -2)], false);
+        add("cal", "4.184", &[(kg, 1), (m, 2), (s, -2)], true);
+        add("Wh", "3600", &[(kg, 1), (m, 2), (s, -2)], true);
+        add("eV", "0.0000000000000000001602176634", &[(kg, 1), (m, 2), (s, -2)], true);
+
+        add("rad", "1", &[], false);
+        table.units.insert(
+            String::from("deg"),
+            UnitDef {
+                factor: float_to_rational(PI / 180.0).unwrap(),
+                dim: DIMENSIONLESS,
+                prefixable: false,
+            },
+        );
+
+        table
+    }
+
+    pub fn define(&mut self, name: &str, factor: BigRational, dim: Dimension) {
+        self.units.insert(name.to_string(), UnitDef { factor, dim, prefixable: false });
+    }
+
+    // a unit name, possibly with an SI prefix such as `km` or `mA`
+    fn lookup(&self, name: &str) -> Option<(BigRational, Dimension)> {
+        if let Some(def) = self.units.get(name) {
+            return Some((def.factor.clone(), def.dim));
+        }
+        PREFIXES.iter().find_map(|(prefix, exp)| {
+            let def = self.units.get(name.strip_prefix(prefix)?)?;
+            def.prefixable.then(|| (&def.factor * pow10(*exp), def.dim))
+        })
+    }
+
+    pub fn is_unit(&self, name: &str) -> bool {
+        self.lookup(name).is_some()
+    }
+
+    // `kg*m/s^2`: `/` applies to the next factor only
+    pub fn parse(&self, text: &str) -> Result<Unit, String> {
+        let mut unit = Unit::one();
+        let mut divide = false;
+        let mut rest = text;
+
+        loop {
+            let end = rest.find(['*', '/']).unwrap_or(rest.len());
+            let (factor, tail) = rest.split_at(end);
+            let (name, exp) = match factor.split_once('^') {
+                Some((name, exp)) => match exp.parse::<i32>() {
+                    Ok(exp) => (name, exp),
+                    Err(_) => return Err(format!("invalid exponent in unit '{factor}'")),
+                },
+                None => (factor, 1),
+            };
+            let (factor, dim) = match self.lookup(name) {
+                Some(found) => found,
+                None => return Err(format!("unknown unit '{name}'")),
+            };
+
+            let named = Unit { parts: BTreeMap::from([(name.to_string(), 1)]), factor, dim };
+            let named = named.powi(if divide { -exp } else { exp });
+            unit = unit.mul(&named);
+
+            match tail.chars().next() {
+                Some(op) => {
+                    divide = op == '/';
+                    rest = &tail[1..];
+                }
+                None => break,
+            }
+        }
+
+        if unit.factor.is_zero() {
+            return Err(format!("unit '{text}' has no size"));
+        }
+        Ok(unit)
+    }
+}
+
+impl Default for UnitTable {
+    fn default() -> UnitTable {
+        UnitTable::new()
+    }
+}
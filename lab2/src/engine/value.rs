@@ -0,0 +1,361 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+use num_rational::BigRational;
+
+use crate::engine::matrix::Matrix;
+use crate::engine::number::Number;
+use crate::engine::units::Unit;
+
+// what expressions evaluate to. Quantities carry a dimension, except the
+// result of converting to a dimensionless unit such as `deg`, which is kept
+// for display and folded back by `settle` before any arithmetic. Comparisons
+// give booleans, which `settle` turns into 1 and 0
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Number(Number),
+    // the unit is boxed to keep values small: every frame of the recursive
+    // evaluator holds several of them
+    Quantity(Number, Box<Unit>),
+    Bool(bool),
+    // `[1, 2, 3]`, multiplied by a matrix as a row or a column as fits
+    Vector(Vec<Number>),
+    // `[[1, 2], [3, 4]]`
+    Matrix(Matrix),
+}
+
+static ZERO: Number = Number::Float(0.0);
+static ONE: Number = Number::Float(1.0);
+static NAN: Number = Number::Float(f64::NAN);
+
+fn join(nums: &[Number]) -> String {
+    nums.iter().map(Number::to_string).collect::<Vec<_>>().join(", ")
+}
+
+fn scale(num: &Number, factor: BigRational) -> Number {
+    num.mul(&Number::Rational(factor))
+}
+
+impl Value {
+    // dimensionless results such as `km/m` fold back into plain numbers
+    pub fn quantity(num: Number, unit: Unit) -> Value {
+        if unit.is_dimensionless() {
+            Value::Number(scale(&num, unit.factor))
+        } else {
+            Value::Quantity(num, Box::new(unit))
+        }
+    }
+
+    pub fn settle(&self) -> Value {
+        match self {
+            Value::Quantity(num, unit) if unit.is_dimensionless() => {
+                Value::quantity(num.clone(), (**unit).clone())
+            }
+            Value::Bool(value) => Value::Number(Number::Rational(BigRational::from_integer(u8::from(*value).into()))),
+            _ => self.clone(),
+        }
+    }
+
+    // NaN for vectors and matrices
+    pub fn number(&self) -> &Number {
+        match self {
+            Value::Number(num) | Value::Quantity(num, _) => num,
+            Value::Bool(true) => &ONE,
+            Value::Bool(false) => &ZERO,
+            Value::Vector(_) | Value::Matrix(_) => &NAN,
+        }
+    }
+
+    // any number in it is infinite
+    pub fn overflows(&self) -> bool {
+        match self {
+            Value::Number(num) | Value::Quantity(num, _) => num.is_infinite(),
+            Value::Bool(_) => false,
+            Value::Vector(nums) => nums.iter().any(Number::is_infinite),
+            Value::Matrix(matrix) => matrix.data().iter().any(Number::is_infinite),
+        }
+    }
+
+    pub fn is_array(&self) -> bool {
+        matches!(self, Value::Vector(_) | Value::Matrix(_))
+    }
+
+    // applies `func` to every element of a vector or matrix
+    pub fn try_map<E>(&self, mut func: impl FnMut(&Number) -> Result<Number, E>) -> Option<Result<Value, E>> {
+        match self {
+            Value::Vector(nums) => Some(nums.iter().map(func).collect::<Result<_, _>>().map(Value::Vector)),
+            Value::Matrix(matrix) => Some(matrix.try_map(&mut func).map(Value::Matrix)),
+            _ => None,
+        }
+    }
+
+    // an operator applied element by element, with a plain number standing in
+    // for every element; `None` when neither side is a vector or matrix
+    fn elementwise(
+        &self,
+        other: &Value,
+        verb: &str,
+        op: impl Fn(&Number, &Number) -> Result<Number, &'static str>,
+    ) -> Option<Result<Value, String>> {
+        let (lhs, rhs) = (self.settle(), other.settle());
+        let res = match (&lhs, &rhs) {
+            (Value::Vector(a), Value::Vector(b)) if a.len() == b.len() => {
+                a.iter().zip(b).map(|(a, b)| op(a, b)).collect::<Result<_, _>>().map(Value::Vector)
+            }
+            (Value::Matrix(a), Value::Matrix(b)) if a.rows() == b.rows() && a.cols() == b.cols() => {
+                let mut rhs = b.data().iter();
+                a.try_map(|a| op(a, rhs.next().unwrap())).map(Value::Matrix)
+            }
+            (array, Value::Number(b)) if array.is_array() => array.try_map(|a| op(a, b))?,
+            (Value::Number(a), array) if array.is_array() => array.try_map(|b| op(a, b))?,
+            _ if lhs.is_array() || rhs.is_array() => {
+                return Some(Err(format!("cannot {verb} {} and {}", lhs.describe_unit(), rhs.describe_unit())))
+            }
+            _ => return None,
+        };
+        Some(res.map_err(String::from))
+    }
+
+    pub fn unit(&self) -> Option<&Unit> {
+        match self {
+            Value::Quantity(_, unit) => Some(unit.as_ref()),
+            _ => None,
+        }
+    }
+
+    // conditions take booleans or numbers, anything but 0 is true
+    pub fn truth(&self) -> Result<bool, String> {
+        match self.settle() {
+            Value::Number(num) => Ok(!num.is_zero()),
+            _ => Err(format!("expected true or false, got {}", self.describe_unit())),
+        }
+    }
+
+    // both numbers in one unit, for comparing them
+    fn aligned(&self, other: &Value) -> Result<(Number, Number), String> {
+        match (&self.settle(), &other.settle()) {
+            (Value::Number(a), Value::Number(b)) => Ok((a.clone(), b.clone())),
+            (Value::Quantity(a, unit), Value::Quantity(b, other_unit)) if unit.dim == other_unit.dim => {
+                Ok((a.clone(), scale(b, other_unit.ratio(unit))))
+            }
+            (lhs, rhs) if lhs.is_array() || rhs.is_array() => {
+                Err(String::from("vectors and matrices cannot be compared, only tested for equality"))
+            }
+            (lhs, rhs) => Err(format!("cannot compare {} and {}", lhs.describe_unit(), rhs.describe_unit())),
+        }
+    }
+
+    pub fn equals(&self, other: &Value) -> Result<bool, String> {
+        let (a, b) = match (self, other) {
+            (Value::Vector(a), Value::Vector(b)) => (a.as_slice(), b.as_slice()),
+            (Value::Matrix(a), Value::Matrix(b)) if a.rows() == b.rows() => (a.data(), b.data()),
+            (Value::Vector(_) | Value::Matrix(_), Value::Vector(_) | Value::Matrix(_)) => return Ok(false),
+            _ => {
+                let (a, b) = self.aligned(other)?;
+                return Ok(numbers_equal(&a, &b));
+            }
+        };
+        Ok(a.len() == b.len() && a.iter().zip(b).all(|(a, b)| numbers_equal(a, b)))
+    }
+
+    // `None` when either side is NaN
+    pub fn compare(&self, other: &Value) -> Result<Option<Ordering>, String> {
+        let (a, b) = self.aligned(other)?;
+        if a.is_complex() || b.is_complex() {
+            return Err(String::from("complex numbers cannot be ordered"));
+        }
+        Ok(a.compare(&b))
+    }
+
+    // expresses `self` in `unit`, which must have the same dimension
+    pub fn convert(&self, unit: &Unit) -> Result<Value, String> {
+        match self {
+            Value::Bool(_) => self.settle().convert(unit),
+            Value::Quantity(num, own) if own.dim == unit.dim => {
+                Ok(Value::Quantity(scale(num, own.ratio(unit)), Box::new(unit.clone())))
+            }
+            Value::Number(num) if unit.is_dimensionless() => {
+                Ok(Value::Quantity(scale(num, Unit::one().ratio(unit)), Box::new(unit.clone())))
+            }
+            _ => Err(format!("cannot convert {} to {unit}", self.describe_unit())),
+        }
+    }
+
+    fn add_like(
+        &self,
+        other: &Value,
+        verb: &str,
+        op: impl Fn(&Number, &Number) -> Number,
+    ) -> Result<Value, String> {
+        if let Some(res) = self.elementwise(other, verb, |a, b| Ok(op(a, b))) {
+            return res;
+        }
+        match (&self.settle(), &other.settle()) {
+            (Value::Number(a), Value::Number(b)) => Ok(Value::Number(op(a, b))),
+            (Value::Quantity(a, unit), Value::Quantity(b, other_unit)) if unit.dim == other_unit.dim => {
+                let b = scale(b, other_unit.ratio(unit));
+                Ok(Value::Quantity(op(a, &b), unit.clone()))
+            }
+            (lhs, rhs) => Err(format!("cannot {verb} {} and {}", lhs.describe_unit(), rhs.describe_unit())),
+        }
+    }
+
+    pub fn describe_unit(&self) -> String {
+        match self {
+            Value::Number(_) => String::from("a plain number"),
+            Value::Quantity(_, unit) => unit.to_string(),
+            Value::Bool(_) => String::from("true/false"),
+            Value::Vector(nums) => format!("a vector of length {}", nums.len()),
+            Value::Matrix(matrix) => format!("a {} matrix", matrix.shape()),
+        }
+    }
+
+    pub fn add(&self, other: &Value) -> Result<Value, String> {
+        self.add_like(other, "add", Number::add)
+    }
+
+    pub fn sub(&self, other: &Value) -> Result<Value, String> {
+        self.add_like(other, "subtract", Number::sub)
+    }
+
+    // the matrix product when both sides are vectors or matrices
+    pub fn mul(&self, other: &Value) -> Result<Value, String> {
+        if self.is_array() && other.is_array() {
+            return self.matrix_product(other);
+        }
+        if let Some(res) = self.elementwise(other, "multiply", |a, b| Ok(a.mul(b))) {
+            return res;
+        }
+        let (lhs, rhs) = (self.settle(), other.settle());
+        let num = lhs.number().mul(rhs.number());
+        Ok(match (lhs.unit(), rhs.unit()) {
+            (None, None) => Value::Number(num),
+            (Some(unit), None) | (None, Some(unit)) => Value::Quantity(num, Box::new(unit.clone())),
+            (Some(a), Some(b)) => Value::quantity(num, a.mul(b)),
+        })
+    }
+
+    pub fn div(&self, other: &Value) -> Result<Value, String> {
+        if other.is_array() {
+            return Err(format!("cannot divide by {}, multiply by inv(...) or use './'", other.describe_unit()));
+        }
+        if let Some(res) = self.elementwise(other, "divide", Number::div) {
+            return res;
+        }
+        let (lhs, rhs) = (self.settle(), other.settle());
+        let num = lhs.number().div(rhs.number())?;
+        Ok(match (lhs.unit(), rhs.unit()) {
+            (None, None) => Value::Number(num),
+            (Some(unit), None) => Value::Quantity(num, Box::new(unit.clone())),
+            (None, Some(unit)) => Value::quantity(num, unit.powi(-1)),
+            (Some(a), Some(b)) => Value::quantity(num, a.div(b)),
+        })
+    }
+
+    pub fn pow(&self, other: &Value) -> Result<Value, String> {
+        if self.is_array() || other.is_array() {
+            return self.matrix_pow(other);
+        }
+        let (base, exp) = (self.settle(), other.settle());
+        let exp = match &exp {
+            Value::Number(exp) => exp,
+            _ => return Err(String::from("an exponent must be dimensionless")),
+        };
+        let num = base.number().pow(exp)?;
+        match base.unit() {
+            None => Ok(Value::Number(num)),
+            Some(unit) => {
+                let whole = exp.to_f64();
+                if whole.fract() != 0.0 || whole.abs() > i32::MAX as f64 {
+                    return Err(format!("cannot raise {unit} to a fractional power"));
+                }
+                Ok(Value::quantity(num, unit.powi(whole as i32)))
+            }
+        }
+    }
+
+    // integer and bitwise operators are only defined for plain numbers
+    pub fn plain(
+        &self,
+        other: &Value,
+        symbol: &str,
+        op: impl Fn(&Number, &Number) -> Result<Number, &'static str>,
+    ) -> Result<Value, String> {
+        match (self.settle(), other.settle()) {
+            (Value::Number(a), Value::Number(b)) => Ok(Value::Number(op(&a, &b)?)),
+            (a, b) if a.is_array() || b.is_array() => Err(format!("'{symbol}' is not defined for vectors")),
+            _ => Err(format!("'{symbol}' is not defined for quantities")),
+        }
+    }
+
+    // `.*` and `./`
+    pub fn elementwise_mul(&self, other: &Value) -> Result<Value, String> {
+        self.elementwise(other, "multiply", |a, b| Ok(a.mul(b))).unwrap_or_else(|| self.mul(other))
+    }
+
+    pub fn elementwise_div(&self, other: &Value) -> Result<Value, String> {
+        self.elementwise(other, "divide", Number::div).unwrap_or_else(|| self.div(other))
+    }
+
+    // a vector is a row on the left of a matrix and a column on its right
+    fn matrix_product(&self, other: &Value) -> Result<Value, String> {
+        match (self, other) {
+            (Value::Matrix(a), Value::Matrix(b)) => a.mul(b).map(Value::Matrix),
+            (Value::Matrix(a), Value::Vector(b)) => {
+                Ok(Value::Vector(a.mul(&Matrix::column_vector(b.clone()))?.into_data()))
+            }
+            (Value::Vector(a), Value::Matrix(b)) => Ok(Value::Vector(Matrix::row_vector(a.clone()).mul(b)?.into_data())),
+            _ => Err(String::from("cannot multiply two vectors, use dot(u, v) or u .* v")),
+        }
+    }
+
+    // whole powers of square matrices
+    fn matrix_pow(&self, other: &Value) -> Result<Value, String> {
+        let matrix = match self {
+            Value::Matrix(matrix) => matrix,
+            _ => return Err(format!("cannot raise {} to a power", self.describe_unit())),
+        };
+        let exp = match other.settle() {
+            Value::Number(exp) if exp.to_f64().fract() == 0.0 && exp.to_f64().abs() <= i64::MAX as f64 => exp.to_f64(),
+            _ => return Err(String::from("a matrix can only be raised to a whole power")),
+        };
+        matrix.pow(exp as i64).map(Value::Matrix)
+    }
+
+    pub fn neg(&self) -> Value {
+        match self {
+            Value::Number(num) => Value::Number(num.neg()),
+            Value::Quantity(num, unit) => Value::Quantity(num.neg(), unit.clone()),
+            Value::Bool(_) => self.settle().neg(),
+            array => array.try_map(|num| Ok::<_, String>(num.neg())).unwrap().unwrap(),
+        }
+    }
+}
+
+fn numbers_equal(a: &Number, b: &Number) -> bool {
+    if a.is_complex() || b.is_complex() {
+        return a.to_complex() == b.to_complex();
+    }
+    a.compare(b) == Some(Ordering::Equal)
+}
+
+impl From<Number> for Value {
+    fn from(num: Number) -> Value {
+        Value::Number(num)
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Number(num) => write!(f, "{num}"),
+            Value::Quantity(num, unit) => write!(f, "{num} {unit}"),
+            Value::Bool(value) => write!(f, "{value}"),
+            Value::Vector(nums) => write!(f, "[{}]", join(nums)),
+            Value::Matrix(matrix) => {
+                let rows: Vec<String> = (0..matrix.rows()).map(|row| format!("[{}]", join(matrix.row(row)))).collect();
+                write!(f, "[{}]", rows.join(", "))
+            }
+        }
+    }
+}
@@ -0,0 +1,190 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// older entries are dropped beyond this
+pub static MAX_ENTRIES: usize = 1000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub expr: String,
+    // empty for definitions, whose `expr` is the normalized text
+    pub result: String,
+    // `n` when this session can refer to the result as `$n`; not saved
+    pub index: Option<usize>,
+    // the cells of a matrix result as shown, one `Vec` per row
+    pub grid: Option<Vec<Vec<String>>>,
+}
+
+impl Entry {
+    // the entry without its result, `$2: a + b`
+    pub fn heading(&self) -> String {
+        match self.index {
+            Some(index) => format!("${index}: {}", self.expr),
+            None => self.expr.clone(),
+        }
+    }
+
+    pub fn text(&self) -> String {
+        if self.result.is_empty() {
+            self.heading()
+        } else {
+            format!("{} = {}", self.heading(), self.result)
+        }
+    }
+
+    // a case-insensitive substring of the expression or the result
+    pub fn matches(&self, query: &str) -> bool {
+        let query = query.to_lowercase();
+        self.expr.to_lowercase().contains(&query) || self.result.to_lowercase().contains(&query)
+    }
+}
+
+// past calculations, kept in a file with one `expr<TAB>result` line each and
+// the cells of a matrix result in a third field
+#[derive(Debug, Default)]
+pub struct History {
+    entries: Vec<Entry>,
+    path: Option<PathBuf>,
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('\t', "\\t").replace('\n', "\\n")
+}
+
+fn unescape(text: &str) -> String {
+    let mut res = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            res.push(ch);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => res.push('\t'),
+            Some('n') => res.push('\n'),
+            Some(other) => res.push(other),
+            None => res.push('\\'),
+        }
+    }
+    res
+}
+
+// rows on lines of their own, cells separated by tabs
+fn join_grid(grid: &[Vec<String>]) -> String {
+    grid.iter().map(|row| row.join("\t")).collect::<Vec<_>>().join("\n")
+}
+
+fn split_grid(text: &str) -> Option<Vec<Vec<String>>> {
+    if text.is_empty() {
+        return None;
+    }
+    Some(text.split('\n').map(|row| row.split('\t').map(String::from).collect()).collect())
+}
+
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+fn markdown_cell(text: &str) -> String {
+    text.replace('|', "\\|").replace('\n', " ")
+}
+
+impl History {
+    // the history saved at `path`; a missing file is an empty history
+    pub fn load(path: &Path) -> io::Result<History> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(err),
+        };
+        let entries = text
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(|line| {
+                let mut fields = line.splitn(3, '\t');
+                let (expr, result) = (fields.next().unwrap_or(""), fields.next().unwrap_or(""));
+                let grid = split_grid(&unescape(fields.next().unwrap_or("")));
+                Entry { expr: unescape(expr), result: unescape(result), index: None, grid }
+            })
+            .collect();
+        Ok(History { entries, path: Some(path.to_path_buf()) })
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    // adds an entry and saves the history when it has a file
+    pub fn push(&mut self, entry: Entry) -> io::Result<()> {
+        self.entries.push(entry);
+        if self.entries.len() > MAX_ENTRIES {
+            self.entries.drain(..self.entries.len() - MAX_ENTRIES);
+        }
+        self.save()
+    }
+
+    pub fn clear(&mut self) -> io::Result<()> {
+        self.entries.clear();
+        self.save()
+    }
+
+    fn save(&self) -> io::Result<()> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let text: String = self
+            .entries
+            .iter()
+            .map(|entry| match &entry.grid {
+                Some(grid) => {
+                    format!("{}\t{}\t{}\n", escape(&entry.expr), escape(&entry.result), escape(&join_grid(grid)))
+                }
+                None => format!("{}\t{}\n", escape(&entry.expr), escape(&entry.result)),
+            })
+            .collect();
+        fs::write(path, text)
+    }
+
+    // a matrix takes a line per row and a column per cell
+    pub fn to_csv(&self) -> String {
+        let mut res = String::from("expression,result\n");
+        for entry in &self.entries {
+            match &entry.grid {
+                Some(grid) => {
+                    for (i, row) in grid.iter().enumerate() {
+                        let expr = if i == 0 { csv_field(&entry.expr) } else { String::new() };
+                        let cells: Vec<String> = row.iter().map(|cell| csv_field(cell)).collect();
+                        res += &format!("{expr},{}\n", cells.join(","));
+                    }
+                }
+                None => res += &format!("{},{}\n", csv_field(&entry.expr), csv_field(&entry.result)),
+            }
+        }
+        res
+    }
+
+    // a matrix is written with a line per row and each cell as code
+    pub fn to_markdown(&self) -> String {
+        let mut res = String::from("| Expression | Result |\n| --- | --- |\n");
+        for entry in &self.entries {
+            let row = |row: &Vec<String>| {
+                row.iter().map(|cell| format!("`{}`", markdown_cell(cell))).collect::<Vec<_>>().join(" ")
+            };
+            let result = match &entry.grid {
+                Some(grid) => grid.iter().map(row).collect::<Vec<_>>().join("<br>"),
+                None => markdown_cell(&entry.result),
+            };
+            res += &format!("| {} | {result} |\n", markdown_cell(&entry.expr));
+        }
+        res
+    }
+}
@@ -0,0 +1,238 @@
+use std::rc::Rc;
+use std::cell::RefCell;
+use gtk::{prelude::*, Box, Button, DropDown, Entry, Grid, Label, Orientation, Text};
+use gtk::glib;
+
+use lab2::engine::env::Env;
+use lab2::engine::eval::calculate;
+use lab2::engine::functions::{find_builtin, is_array_function};
+use lab2::engine::number::Number;
+use lab2::engine::parse;
+use lab2::engine::value::Value;
+
+type Rows = Vec<Vec<String>>;
+
+const BASIC: &[&[&str]] = &[
+    &["MC", "MR", "M+", "M-", "AC"],
+    &["7", "8", "9", "/", "⌫"],
+    &["4", "5", "6", "*", "("],
+    &["1", "2", "3", "-", ")"],
+    &["0", ".", "%", "+", "="],
+];
+
+const SCIENTIFIC: &[&[&str]] = &[
+    &["sin", "cos", "tan", "MC", "MR", "M+", "M-", "AC"],
+    &["asin", "acos", "atan", "7", "8", "9", "/", "⌫"],
+    &["ln", "log", "exp", "4", "5", "6", "*", "("],
+    &["sqrt", "^", "!", "1", "2", "3", "-", ")"],
+    &["pi", "e", "ans", "0", ".", "%", "+", "="],
+];
+
+const PROGRAMMER: &[&[&str]] = &[
+    &["MC", "MR", "M+", "M-", "AC", "⌫"],
+    &["0x", "0b", "&", "|", "xor", "~"],
+    &["D", "E", "F", "<<", ">>", "mod"],
+    &["A", "B", "C", "(", ")", "//"],
+    &["7", "8", "9", "*", "/", "ans"],
+    &["4", "5", "6", "+", "-", "^"],
+    &["1", "2", "3", "0", "!", "="],
+];
+
+// the built-in layouts, then `custom` from ~/.config/lab2/keypad.conf when it
+// exists: one row per line, keys separated by spaces, `#` starts a comment
+fn layouts() -> Vec<(String, Rows)> {
+    let rows = |layout: &[&[&str]]| layout.iter().map(|row| row.iter().map(|key| key.to_string()).collect()).collect();
+    let mut layouts =
+        vec![("basic".to_string(), rows(BASIC)), ("scientific".to_string(), rows(SCIENTIFIC)), ("programmer".to_string(), rows(PROGRAMMER))];
+
+    let path = glib::user_config_dir().join("lab2").join("keypad.conf");
+    if let Ok(text) = std::fs::read_to_string(path) {
+        let custom: Rows = text
+            .lines()
+            .map(|line| line.split('#').next().unwrap_or("").split_whitespace().map(String::from).collect::<Vec<_>>())
+            .filter(|row| !row.is_empty())
+            .collect();
+        if !custom.is_empty() {
+            layouts.push(("custom".to_string(), custom));
+        }
+    }
+    layouts
+}
+
+// what a key types: functions open their parentheses and operator words get
+// spaces around them
+fn key_text(key: &str) -> String {
+    if find_builtin(key).is_some() || is_array_function(key) {
+        format!("{key}(")
+    } else if matches!(key, "xor" | "mod") {
+        format!(" {key} ")
+    } else {
+        key.to_string()
+    }
+}
+
+// replaces the selection, if any, and leaves the cursor after `text`
+fn insert(field_input: &Entry, text: &str) {
+    field_input.delete_selection();
+    let mut pos = field_input.position();
+    field_input.insert_text(text, &mut pos);
+    field_input.set_position(pos);
+}
+
+// the memory as input: a word without its bit patterns, and parenthesized
+// unless it is a plain number or a vector, so that `2*MR` with `1/3` stored
+// is `2*(1/3)`
+fn recalled_text(value: &Value) -> String {
+    let text = match value {
+        Value::Number(Number::Word(num, _)) => num.to_string(),
+        value => value.to_string(),
+    };
+    if text.starts_with('[') || text.chars().all(|ch| ch.is_ascii_digit() || ch == '.') {
+        text
+    } else {
+        format!("({text})")
+    }
+}
+
+struct Keypad {
+    env: Rc<RefCell<Env>>,
+    field_input: Entry,
+    evaluate_btn: Button,
+    err_text: Text,
+    memory: RefCell<Option<Value>>,
+    memory_text: Label,
+}
+
+impl Keypad {
+    // the value of the input, or the last result when it is empty
+    fn input_value(&self) -> Result<Value, String> {
+        let env = self.env.borrow();
+        let text = self.field_input.text();
+        if text.trim().is_empty() {
+            return env.get("ans").cloned().ok_or_else(|| String::from("nothing to store yet"));
+        }
+        let tree = parse(&text, &env).map_err(|err| err.to_string())?;
+        calculate(&tree, &env).map_err(|err| err.to_string())
+    }
+
+    // `M+` and `M-`
+    fn add_to_memory(&self, negate: bool) -> Result<(), String> {
+        let value = self.input_value()?;
+        let value = if negate { value.neg() } else { value };
+        let sum = match self.memory.borrow().as_ref() {
+            Some(memory) => memory.add(&value)?,
+            None => value,
+        };
+        self.set_memory(Some(sum));
+        Ok(())
+    }
+
+    fn set_memory(&self, value: Option<Value>) {
+        match &value {
+            Some(value) => self.memory_text.set_text(&format!("M = {}", self.env.borrow().format().value(value))),
+            None => self.memory_text.set_text(""),
+        }
+        *self.memory.borrow_mut() = value;
+    }
+
+    fn press(&self, key: &str) {
+        let res = match key {
+            "=" => {
+                self.evaluate_btn.emit_clicked();
+                Ok(())
+            }
+            "AC" => {
+                self.field_input.set_text("");
+                self.err_text.set_text("");
+                Ok(())
+            }
+            "⌫" => {
+                let pos = self.field_input.position();
+                if self.field_input.selection_bounds().is_some() {
+                    self.field_input.delete_selection();
+                } else if pos > 0 {
+                    self.field_input.delete_text(pos - 1, pos);
+                }
+                Ok(())
+            }
+            "MC" => {
+                self.set_memory(None);
+                Ok(())
+            }
+            "MR" => {
+                if let Some(value) = self.memory.borrow().as_ref() {
+                    insert(&self.field_input, &recalled_text(value));
+                }
+                Ok(())
+            }
+            "M+" => self.add_to_memory(false),
+            "M-" => self.add_to_memory(true),
+            "." if self.env.borrow().format().decimal_comma => {
+                insert(&self.field_input, ",");
+                Ok(())
+            }
+            _ => {
+                insert(&self.field_input, &key_text(key));
+                Ok(())
+            }
+        };
+        if let Err(err) = res {
+            self.err_text.set_text(&err);
+        }
+        self.field_input.grab_focus_without_selecting();
+    }
+}
+
+fn show_layout(keypad: &Rc<Keypad>, rows: &Rows, grid: &Grid) {
+    while let Some(child) = grid.first_child() {
+        grid.remove(&child);
+    }
+
+    for (i, row) in rows.iter().enumerate() {
+        for (j, key) in row.iter().enumerate() {
+            let key_btn = Button::builder().label(key).hexpand(true).build();
+            let keypad = Rc::clone(keypad);
+            let key = key.clone();
+            key_btn.connect_clicked(move |_| keypad.press(&key));
+            grid.attach(&key_btn, j as i32, i as i32, 1, 1);
+        }
+    }
+}
+
+// the key grid with a layout switch; `=` clicks `evaluate_btn` and the memory
+// keys keep a value of their own
+pub fn build_keypad(env: Rc<RefCell<Env>>, field_input: &Entry, evaluate_btn: &Button, err_text: &Text) -> Box {
+    let layouts = layouts();
+    let names: Vec<&str> = layouts.iter().map(|(name, _)| name.as_str()).collect();
+    let layout_btn = DropDown::from_strings(&names);
+    let memory_text = Label::builder().xalign(0.0).hexpand(true).build();
+    let grid = Grid::builder().row_spacing(3).column_spacing(3).build();
+
+    let keypad = Rc::new(Keypad {
+        env,
+        field_input: field_input.clone(),
+        evaluate_btn: evaluate_btn.clone(),
+        err_text: err_text.clone(),
+        memory: RefCell::new(None),
+        memory_text: memory_text.clone(),
+    });
+    show_layout(&keypad, &layouts[0].1, &grid);
+    {
+        let grid = grid.clone();
+        layout_btn.connect_selected_notify(move |layout_btn| {
+            if let Some((_, rows)) = layouts.get(layout_btn.selected() as usize) {
+                show_layout(&keypad, rows, &grid);
+            }
+        });
+    }
+
+    let controls = Box::new(Orientation::Horizontal, 5);
+    controls.append(&memory_text);
+    controls.append(&layout_btn);
+
+    let vbox = Box::new(Orientation::Vertical, 5);
+    vbox.append(&controls);
+    vbox.append(&grid);
+
+    vbox
+}
@@ -1,206 +1,473 @@
+mod data;
+mod keypad;
+mod plot;
+
+use std::rc::Rc;
+use std::cell::{Cell, RefCell};
 use gtk::{
-    prelude::*, ApplicationWindow, Box, Button, Entry, ListBox, Orientation, ScrolledWindow, Text, PolicyType
+    prelude::*, ApplicationWindow, Box, Button, CheckButton, DropDown, Entry, EventControllerKey, Expander,
+    FileChooserAction, FileChooserNative, Grid, Label, ListBox, Notebook, Orientation, PropagationPhase, ResponseType,
+    ScrolledWindow, SearchEntry, SpinButton, Text, TextView, PolicyType
 };
-use gtk::{glib, Application};
+use gtk::{gdk, glib, Application};
+
+use lab2::engine::{calc, format_solution, Outcome, CONFIG_FILES};
+use lab2::engine::env::Env;
+use lab2::engine::format::{Format, Notation};
+use lab2::engine::number::{NumberMode, WordSize};
+use lab2::engine::value::Value;
+use lab2::history::{self, History};
+
+use crate::data::build_data;
+use crate::keypad::build_keypad;
+use crate::plot::build_plot;
 
 const APP_ID: &str = "org.gtk_rs.lab2";
 
-static COPS: [char; 5] = ['+', '-', '*', '/', '^'];
-static SOPS: [&str; 5] = ["+", "-", "*", "/", "^"];
-static UN: [&str; 2] = ["+", "-"];
-static EPS: f64 = 0.0000000001;
-
-fn calculate_expr(
-    oprnd1: f64, oprnd2: f64, op: String
-) -> Result<f64, &'static str> {
-    return match op.as_str() {
-        "+" => Ok(oprnd1 + oprnd2),
-        "-" => Ok(oprnd1 - oprnd2),
-        "*" => Ok(oprnd1 * oprnd2),
-        "/" => if oprnd2.abs() > EPS {Ok(oprnd1 / oprnd2)}
-               else {Err("divisaion by 0")},
-        "^" => Ok(oprnd1.powf(oprnd2)),
-        _ => Err("invalid operator"),
-    };
+fn show_variables(env: Rc<RefCell<Env>>, vars_box: &ListBox) {
+    while let Some(child) = vars_box.first_child() {
+        vars_box.remove(&child);
+    }
+
+    let env = env.borrow();
+    for (name, value) in env.variables() {
+        vars_box.append(
+            &Text::builder().text(format!("{name} = {}", env.format().value(value))).build()
+        );
+    }
+    for (_, func) in env.functions() {
+        vars_box.append(&Text::builder().text(&func.text).build());
+    }
 }
 
-fn get_operator_priority(op: String) -> i8 {
-    if op == "+" || op == "-" {
-        return  1;
-    } else if op == "*" || op == "/" {
-        return  2;
-    } else if op == "^" {
-        return 3;
+fn show_history(history: &History, res_box: &ListBox) {
+    while let Some(child) = res_box.first_child() {
+        res_box.remove(&child);
+    }
+
+    for entry in history.entries() {
+        match &entry.grid {
+            Some(cells) => res_box.append(&matrix_row(&entry.heading(), cells)),
+            None => res_box.append(&Label::builder().label(entry.text()).xalign(0.0).build()),
+        }
     }
-    
-    return 0;
 }
 
-fn parse_expression_to_tokens(expr: &str) -> Result<Vec<String>, &'static str> {
-    let bexpr: Vec<char> = expr.chars().collect();
-    let mut s = String::from("");
-    let mut el_type = "empty";
-    let mut tokens= Vec::new();
-
-    for ch in bexpr {
-        if el_type != "float" && ch.is_digit(10) {
-            s.push(ch);
-            el_type = "int";
-        } else if COPS.contains(&ch) {
-            if !s.is_empty() {
-                tokens.push(s.clone());
-            }
-            tokens.push(ch.to_string());
-            s.clear();
-            el_type = "op";
-        } else if el_type == "int" && ch == '.' {
-            s.push(ch);
-            el_type = "float"
-        } else if el_type == "float" && ch.is_digit(10){
-            s.push(ch);
-        } else {
-            return Err("invalid token");
+// a matrix result laid out as a grid after its expression
+fn matrix_row(heading: &str, cells: &[Vec<String>]) -> Box {
+    let row = Box::new(Orientation::Horizontal, 5);
+    row.append(&Label::new(Some(&format!("{heading} ="))));
+    let grid = Grid::builder().column_spacing(10).build();
+    for (i, cells) in cells.iter().enumerate() {
+        for (j, cell) in cells.iter().enumerate() {
+            grid.attach(&Label::builder().label(cell).xalign(1.0).build(), j as i32, i as i32, 1, 1);
         }
     }
-    tokens.push(s.clone());
+    row.append(&grid);
+    row
+}
 
-    return Ok(tokens);
+// `results` is the number of results so far, values are the last of them
+fn history_entry(expr: &str, outcome: Outcome, results: usize, format: &Format) -> history::Entry {
+    let grid = match &outcome {
+        Outcome::Value(Value::Matrix(matrix)) => Some(format.cells(matrix)),
+        _ => None,
+    };
+    let (expr, result, index) = match outcome {
+        Outcome::Value(value) => (expr.to_string(), format.value(&value), Some(results)),
+        Outcome::Defined(text) => (text, String::new(), None),
+        Outcome::Symbolic(text) => (expr.to_string(), text, None),
+        Outcome::Solution(roots) => (expr.to_string(), format_solution(&roots, format), None),
+    };
+    history::Entry { expr, result, index, grid }
 }
 
-fn parse_tokens_to_rpn(tokens: Vec<String>) -> Result<Vec<String>, &'static str> {
-    let mut expr_ops: Vec<String> = Vec::new();
-    let mut rpn_expr: Vec<String> = Vec::new();
+fn export_history(btn: &Button, history: Rc<RefCell<History>>, markdown: bool, err_text: Text) {
+    let parent = btn.root().and_downcast::<gtk::Window>();
+    let dialog = FileChooserNative::new(
+        Some("Export history"),
+        parent.as_ref(),
+        FileChooserAction::Save,
+        Some("Export"),
+        Some("Cancel"),
+    );
+    dialog.set_current_name(if markdown { "history.md" } else { "history.csv" });
 
-    for token in tokens {
-        if token.parse::<f64>().is_ok() {
-            rpn_expr.push(token.clone());
-        }
-        else if !SOPS.contains(&token.as_str()) {
-            return Err("invalid token");
-        } else if expr_ops.is_empty(){
-            expr_ops.push(token.clone());
-        } else if 
-            get_operator_priority(expr_ops.last().unwrap().clone()) < 
-            get_operator_priority(token.clone()) 
-        {
-            expr_ops.push(token.clone());
-        } else {
-            while 
-                !expr_ops.is_empty() && 
-                get_operator_priority(expr_ops.last().unwrap().clone()) >= 
-                get_operator_priority(token.clone()) 
-            {
-                rpn_expr.push(expr_ops.pop().unwrap().clone());
+    // the closure keeps the dialog alive until it is answered
+    let dialog_ref = dialog.clone();
+    dialog.connect_response(move |_, response| {
+        if response == ResponseType::Accept {
+            if let Some(path) = dialog_ref.file().and_then(|file| file.path()) {
+                let history = history.borrow();
+                let text = if markdown { history.to_markdown() } else { history.to_csv() };
+                if let Err(err) = std::fs::write(&path, text) {
+                    err_text.set_text(&format!("{}: {err}", path.display()));
+                }
             }
-            expr_ops.push(token.clone());
         }
-    }
-
-    for op in expr_ops.iter().rev() {
-        rpn_expr.push(op.clone());
-    }
-
-    return Ok(rpn_expr);
+        dialog_ref.destroy();
+    });
+    dialog.show();
+}
 
+#[derive(Clone)]
+struct ModeButtons {
+    exact_btn: CheckButton,
+    decimal_btn: CheckButton,
+    digits_btn: SpinButton,
+    programmer_btn: CheckButton,
+    bits_btn: DropDown,
+    signed_btn: CheckButton,
 }
 
-fn calculate_rpn(rpn: Vec<String>) -> Result<f64, &'static str> {
-    let mut nums: Vec<f64> = Vec::new();
-
-    for token in rpn {
-        let res = token.parse::<f64>();
-        let is_un = UN.contains(&token.as_str());
-        let is_op = SOPS.contains(&token.as_str());
-        if res.is_ok() {
-            nums.push(res.unwrap());
-        } else if is_un && nums.len() < 2 {
-            let oprnd = nums.pop().unwrap();
-            
-            match token.as_str() {
-                "-" => nums.push(-oprnd),
-                _ => {}
-            };
-        } else if is_op {
-            let oprnd2 = nums.pop().unwrap();
-            let oprnd1 = nums.pop().unwrap();
+const WORD_BITS: [u32; 4] = [8, 16, 32, 64];
 
-            match calculate_expr(oprnd1, oprnd2, token) {
-                Ok(res) => nums.push(res),
-                Err(err) => return Err(err),
-            };
+impl ModeButtons {
+    fn selected_mode(&self) -> NumberMode {
+        if self.exact_btn.is_active() {
+            NumberMode::Exact
+        } else if self.decimal_btn.is_active() {
+            NumberMode::Decimal(self.digits_btn.value_as_int() as u32)
+        } else if self.programmer_btn.is_active() {
+            NumberMode::Programmer(WordSize {
+                bits: WORD_BITS[self.bits_btn.selected() as usize],
+                signed: self.signed_btn.is_active(),
+            })
         } else {
-            return Err("invalid token");
+            NumberMode::Float
         }
     }
-
-    return Ok(nums[0]);
 }
 
-fn calc(expr: &str) -> Result<f64, String> {
-    let mut tokens: Vec<String> = Vec::new();
-    let mut rpn: Vec<String> = Vec::new();
+fn build_mode_switch(env: Rc<RefCell<Env>>) -> Box {
+    let float_btn = CheckButton::builder().label("float").active(true).build();
+    let exact_btn = CheckButton::builder().label("exact").group(&float_btn).build();
+    let decimal_btn = CheckButton::builder().label("decimal").group(&float_btn).build();
+    let digits_btn = SpinButton::with_range(1.0, 1000.0, 1.0);
+    digits_btn.set_value(30.0);
+    let programmer_btn = CheckButton::builder().label("programmer").group(&float_btn).build();
+    let bits_btn = DropDown::from_strings(&["8 bit", "16 bit", "32 bit", "64 bit"]);
+    bits_btn.set_selected(3);
+    let signed_btn = CheckButton::builder().label("signed").active(true).build();
 
-    match parse_expression_to_tokens(expr) {
-        Ok(res) => tokens = res,
-        Err(err) => return Err(format!("Parse Error: {err}")),
-    }
-    println!("Tokens: {}", tokens.join(" "));
+    let buttons = ModeButtons {
+        exact_btn: exact_btn.clone(),
+        decimal_btn: decimal_btn.clone(),
+        digits_btn: digits_btn.clone(),
+        programmer_btn: programmer_btn.clone(),
+        bits_btn: bits_btn.clone(),
+        signed_btn: signed_btn.clone(),
+    };
+    let update_mode = Rc::new(move || {
+        env.borrow_mut().set_mode(buttons.selected_mode());
+    });
 
-    match parse_tokens_to_rpn(tokens){
-        Ok(res) => rpn = res,
-        Err(err) => return Err(format!("RPN Error: {err}")),
+    for btn in [&float_btn, &exact_btn, &decimal_btn, &programmer_btn, &signed_btn] {
+        let update_mode = Rc::clone(&update_mode);
+        btn.connect_toggled(move |_| update_mode());
+    }
+    {
+        let update_mode = Rc::clone(&update_mode);
+        digits_btn.connect_value_changed(move |_| update_mode());
     }
+    bits_btn.connect_selected_notify(move |_| update_mode());
 
-    println!("RPN: {}", rpn.join(" "));
+    let hbox = Box::new(Orientation::Horizontal, 3);
+    hbox.append(&float_btn);
+    hbox.append(&exact_btn);
+    hbox.append(&decimal_btn);
+    hbox.append(&digits_btn);
+    hbox.append(&programmer_btn);
+    hbox.append(&bits_btn);
+    hbox.append(&signed_btn);
+
+    hbox
+}
 
-    match calculate_rpn(rpn) {
-        Ok(res) => return Ok(res),
-        Err(err) => return Err(format!("Calculate Error: {err}")),
+const NOTATIONS: [&str; 5] = ["auto", "fixed", "significant", "scientific", "engineering"];
+
+// `on_change` runs after the format changes, to show the variables anew
+fn build_format_switch(env: Rc<RefCell<Env>>, on_change: impl Fn() + 'static) -> Box {
+    let notation_btn = DropDown::from_strings(&NOTATIONS);
+    let digits_btn = SpinButton::with_range(0.0, 17.0, 1.0);
+    digits_btn.set_value(6.0);
+    digits_btn.set_sensitive(false);
+    let grouping_btn = CheckButton::builder().label("1,000 separators").build();
+    let comma_btn = CheckButton::builder().label("decimal comma").build();
+
+    let update_format = {
+        let notation_btn = notation_btn.clone();
+        let digits_btn = digits_btn.clone();
+        let grouping_btn = grouping_btn.clone();
+        let comma_btn = comma_btn.clone();
+        Rc::new(move || {
+            let digits = digits_btn.value_as_int() as u32;
+            let notation = match notation_btn.selected() {
+                1 => Notation::Fixed(digits),
+                2 => Notation::Significant(digits.max(1)),
+                3 => Notation::Scientific(digits.max(1)),
+                4 => Notation::Engineering(digits.max(1)),
+                _ => Notation::Auto,
+            };
+            // the digits mean nothing to `auto`
+            digits_btn.set_sensitive(notation != Notation::Auto);
+            env.borrow_mut().set_format(Format {
+                notation,
+                grouping: grouping_btn.is_active(),
+                decimal_comma: comma_btn.is_active(),
+            });
+            on_change();
+        })
+    };
+
+    {
+        let update_format = Rc::clone(&update_format);
+        notation_btn.connect_selected_notify(move |_| update_format());
+    }
+    {
+        let update_format = Rc::clone(&update_format);
+        digits_btn.connect_value_changed(move |_| update_format());
+    }
+    for btn in [&grouping_btn, &comma_btn] {
+        let update_format = Rc::clone(&update_format);
+        btn.connect_toggled(move |_| update_format());
     }
+
+    let hbox = Box::new(Orientation::Horizontal, 3);
+    hbox.append(&notation_btn);
+    hbox.append(&digits_btn);
+    hbox.append(&grouping_btn);
+    hbox.append(&comma_btn);
+
+    hbox
 }
 
 fn build_ui(app: &Application) {
+    let env = Rc::new(RefCell::new(Env::new()));
+    env.borrow_mut().set_trace(true);
     let res_box = ListBox::new();
+    let vars_box = ListBox::new();
     let err_text = Text::builder().text("").build();
+    // the steps of the last calculation, filled in from `Env::last_trace`
+    let explain_view = TextView::builder().editable(false).monospace(true).build();
     let field_input = Entry::builder().build();
     let btn = Button::builder().label("=").build();
+    let (plot_box, plot_area) = build_plot(Rc::clone(&env));
+    let data_box = {
+        let env_clone = Rc::clone(&env);
+        let vars_box = vars_box.clone();
+        build_data(Rc::clone(&env), move || show_variables(Rc::clone(&env_clone), &vars_box))
+    };
+    let search_input = SearchEntry::builder().placeholder_text("search history").build();
+    let csv_btn = Button::builder().label("export CSV").build();
+    let markdown_btn = Button::builder().label("export Markdown").build();
+    let clear_btn = Button::builder().label("clear").build();
+
+    // user units and constants, e.g. ~/.config/lab2/units.conf
+    for (file, define) in CONFIG_FILES {
+        let path = glib::user_config_dir().join("lab2").join(file);
+        if let Ok(text) = std::fs::read_to_string(&path) {
+            if let Err(err) = define(&mut env.borrow_mut(), &text) {
+                err_text.set_text(&format!("{}: {err}", path.display()));
+            }
+        }
+    }
+
+    // ~/.local/share/lab2/history
+    let history_path = glib::user_data_dir().join("lab2").join("history");
+    let history = match History::load(&history_path) {
+        Ok(history) => history,
+        Err(err) => {
+            err_text.set_text(&format!("{}: {err}", history_path.display()));
+            History::default()
+        }
+    };
+    let history = Rc::new(RefCell::new(history));
+    show_history(&history.borrow(), &res_box);
+
+    {
+        let history = Rc::clone(&history);
+        let search_input = search_input.clone();
+        res_box.set_filter_func(move |row| {
+            let query = search_input.text();
+            history.borrow().entries().get(row.index() as usize).is_none_or(|entry| entry.matches(&query))
+        });
+    }
+    {
+        let res_box = res_box.clone();
+        search_input.connect_search_changed(move |_| res_box.invalidate_filter());
+    }
+    {
+        let history = Rc::clone(&history);
+        let field_input = field_input.clone();
+        // clicking an entry brings its expression back for editing
+        res_box.connect_row_activated(move |_, row| {
+            if let Some(entry) = history.borrow().entries().get(row.index() as usize) {
+                field_input.set_text(&entry.expr);
+                field_input.grab_focus();
+                field_input.set_position(-1);
+            }
+        });
+    }
+    for (btn, markdown) in [(&csv_btn, false), (&markdown_btn, true)] {
+        let history = Rc::clone(&history);
+        let err_text = err_text.clone();
+        btn.connect_clicked(move |btn| export_history(btn, Rc::clone(&history), markdown, err_text.clone()));
+    }
+    {
+        let history = Rc::clone(&history);
+        let res_box = res_box.clone();
+        let err_text = err_text.clone();
+        clear_btn.connect_clicked(move |_| {
+            if let Err(err) = history.borrow_mut().clear() {
+                err_text.set_text(&format!("{err}"));
+            }
+            show_history(&history.borrow(), &res_box);
+        });
+    }
+
+    // Up and Down walk through the history, Escape clears the input; what
+    // was typed before browsing comes back after the newest entry
+    let browsed: Rc<Cell<Option<usize>>> = Rc::new(Cell::new(None));
+    let draft = Rc::new(RefCell::new(String::new()));
+    {
+        let history = Rc::clone(&history);
+        let browsed = Rc::clone(&browsed);
+        let field_input_clone = field_input.clone();
+        let err_text = err_text.clone();
+        let keys = EventControllerKey::builder().propagation_phase(PropagationPhase::Capture).build();
+        keys.connect_key_pressed(move |_, key, _, _| {
+            let len = history.borrow().entries().len();
+            let index = match (key, browsed.get()) {
+                (gdk::Key::Up, None) if len > 0 => {
+                    draft.replace(field_input_clone.text().to_string());
+                    Some(len - 1)
+                }
+                (gdk::Key::Up, Some(i)) => Some(i.saturating_sub(1)),
+                (gdk::Key::Down, Some(i)) if i + 1 < len => Some(i + 1),
+                (gdk::Key::Down, Some(_)) => None,
+                (gdk::Key::Escape, _) => {
+                    browsed.set(None);
+                    field_input_clone.set_text("");
+                    err_text.set_text("");
+                    return glib::Propagation::Stop;
+                }
+                _ => return glib::Propagation::Proceed,
+            };
+            browsed.set(index);
+            let text = match index {
+                Some(i) => history.borrow().entries().get(i).map(|entry| entry.expr.clone()).unwrap_or_default(),
+                None => draft.take(),
+            };
+            field_input_clone.set_text(&text);
+            field_input_clone.set_position(-1);
+            glib::Propagation::Stop
+        });
+        field_input.add_controller(keys);
+    }
+    {
+        let btn = btn.clone();
+        field_input.connect_activate(move |_| btn.emit_clicked());
+    }
+    let format_box = {
+        let env_clone = Rc::clone(&env);
+        let vars_box = vars_box.clone();
+        build_format_switch(Rc::clone(&env), move || show_variables(Rc::clone(&env_clone), &vars_box))
+    };
+    let keypad_box = build_keypad(Rc::clone(&env), &field_input, &btn, &err_text);
 
     let cloned_res_box = res_box.clone();
     let cloned_err_text = err_text.clone();
     let cloned_field_input = field_input.clone();
+    let cloned_vars_box = vars_box.clone();
+    let env_clone = Rc::clone(&env);
+    let cloned_history = Rc::clone(&history);
+    let cloned_explain_view = explain_view.clone();
 
     btn.connect_clicked(move |_| {
+        browsed.set(None);
         let expr = cloned_field_input.text();
-        let res = calc(expr.as_str());
+        let res = calc(expr.as_str(), &mut env_clone.borrow_mut());
         // let res = evalexpr::eval(&expr);
+        if let Some(trace) = env_clone.borrow().last_trace() {
+            cloned_explain_view.buffer().set_text(&trace.to_string());
+        }
 
         match res {
-            Ok(res_) => cloned_res_box.append(
-                &Text::builder().text(
-                    &format!("{expr} = {res_}")
-                ).build()
-            ),
-            Err(err) => cloned_err_text
-                .set_text(format!("{err}").as_str()),
+            Ok(res_) => {
+                cloned_err_text.set_text("");
+                let (results, format) = (env_clone.borrow().results().len(), env_clone.borrow().format());
+                let res = cloned_history.borrow_mut().push(history_entry(expr.as_str(), res_, results, &format));
+                if let Err(err) = res {
+                    cloned_err_text.set_text(&format!("{}: {err}", history_path.display()));
+                }
+                show_history(&cloned_history.borrow(), &cloned_res_box);
+                show_variables(Rc::clone(&env_clone), &cloned_vars_box);
+                // plotted curves may use the variables and functions
+                plot_area.queue_draw();
+            },
+            Err(err) => {
+                cloned_err_text.set_text(format!("{err}").as_str());
+                // point at the failing part of the expression
+                if let Some(span) = err.span {
+                    cloned_field_input.grab_focus();
+                    cloned_field_input.select_region(span.start as i32, span.end as i32);
+                }
+            },
         }
     });
 
     let res_list = ScrolledWindow::builder()
         .hscrollbar_policy(PolicyType::Never)
         .min_content_width(200)
+        .vexpand(true)
         .child(&res_box)
         .build();
 
+    let vars_list = ScrolledWindow::builder()
+        .hscrollbar_policy(PolicyType::Never)
+        .min_content_width(120)
+        .child(&vars_box)
+        .build();
+
+    let history_tools = Box::new(Orientation::Horizontal, 5);
+    history_tools.append(&search_input);
+    history_tools.append(&csv_btn);
+    history_tools.append(&markdown_btn);
+    history_tools.append(&clear_btn);
+
+    let history_box = Box::new(Orientation::Vertical, 5);
+    history_box.append(&history_tools);
+    history_box.append(&res_list);
+
+    let lists = Box::new(Orientation::Horizontal, 5);
+    lists.append(&history_box);
+    lists.append(&vars_list);
+
     let vbox = Box::new(Orientation::Vertical, 5);
-    vbox.append(&res_list);
+    vbox.append(&lists);
+    vbox.append(&build_mode_switch(Rc::clone(&env)));
+    vbox.append(&format_box);
     vbox.append(&field_input);
     vbox.append(&btn);
     vbox.append(&err_text);
+    vbox.append(&keypad_box);
+    vbox.append(
+        &Expander::builder()
+            .label("explain")
+            .child(&ScrolledWindow::builder().min_content_height(150).child(&explain_view).build())
+            .build(),
+    );
+
+    let notebook = Notebook::new();
+    notebook.append_page(&vbox, Some(&Label::new(Some("Calculator"))));
+    notebook.append_page(&plot_box, Some(&Label::new(Some("Plot"))));
+    notebook.append_page(&data_box, Some(&Label::new(Some("Data"))));
 
     let window = ApplicationWindow::builder()
         .application(app)
         .title("RPNCalc")
-        .child(&vbox)
+        .child(&notebook)
         .build();
 
     window.present();
@@ -213,4 +480,4 @@ fn main() -> glib::ExitCode {
 
     app.connect_activate(build_ui);
     app.run()
-}
\ No newline at end of file
+}
@@ -0,0 +1,424 @@
+use std::rc::Rc;
+use std::cell::RefCell;
+use gtk::{
+    cairo, prelude::*, Box, Button, DrawingArea, Entry, EventControllerMotion, EventControllerScroll,
+    EventControllerScrollFlags, GestureDrag, Label, Orientation
+};
+use gtk::glib;
+
+use lab2::engine::ast::Expr;
+use lab2::engine::compile::compile;
+use lab2::engine::env::Env;
+use lab2::engine::eval::calculate_at;
+use lab2::engine::parse;
+
+const COLORS: [(f64, f64, f64); 5] = [
+    (0.80, 0.15, 0.15),
+    (0.15, 0.35, 0.80),
+    (0.10, 0.60, 0.25),
+    (0.75, 0.45, 0.05),
+    (0.55, 0.20, 0.65),
+];
+
+// grid lines are at least this many pixels apart
+const TICK_SPACING: f64 = 60.0;
+const ZOOM_STEP: f64 = 1.2;
+// the smallest range zooming in goes to, well above the rounding of f64
+const MIN_RANGE: f64 = 1e-9;
+// grid lines per axis at most, in case the steps get lost in the rounding
+const MAX_TICKS: usize = 1000;
+
+// the curves of `sin(x); max(x; 1)`: only a `;` outside of brackets ends
+// one, the others separate arguments with a decimal comma
+fn split_curves(text: &str) -> Vec<&str> {
+    let mut curves = Vec::new();
+    let (mut depth, mut start) = (0usize, 0);
+    for (i, ch) in text.char_indices() {
+        match ch {
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth = depth.saturating_sub(1),
+            ';' if depth == 0 => {
+                curves.push(&text[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    curves.push(&text[start..]);
+    curves
+}
+
+#[derive(Clone, Copy)]
+struct View {
+    x_min: f64,
+    x_max: f64,
+    y_min: f64,
+    y_max: f64,
+}
+
+impl View {
+    const DEFAULT: View = View { x_min: -10.0, x_max: 10.0, y_min: -6.0, y_max: 6.0 };
+
+    fn screen_point(&self, x: f64, y: f64, width: f64, height: f64) -> (f64, f64) {
+        (
+            (x - self.x_min) / (self.x_max - self.x_min) * width,
+            (self.y_max - y) / (self.y_max - self.y_min) * height,
+        )
+    }
+
+    fn plot_point(&self, px: f64, py: f64, width: f64, height: f64) -> (f64, f64) {
+        (
+            self.x_min + px / width * (self.x_max - self.x_min),
+            self.y_max - py / height * (self.y_max - self.y_min),
+        )
+    }
+
+    // scales the view around the point `(x, y)`, which stays in place
+    fn zoom(&mut self, factor: f64, x: f64, y: f64) {
+        let range = (self.x_max - self.x_min).min(self.y_max - self.y_min);
+        if factor < 1.0 && range * factor < MIN_RANGE {
+            return;
+        }
+        self.x_min = x + (self.x_min - x) * factor;
+        self.x_max = x + (self.x_max - x) * factor;
+        self.y_min = y + (self.y_min - y) * factor;
+        self.y_max = y + (self.y_max - y) * factor;
+    }
+}
+
+struct Curve {
+    text: String,
+    tree: Expr,
+}
+
+struct PlotState {
+    curves: Vec<Curve>,
+    view: View,
+    // the view when the current drag started
+    drag_start: View,
+    // the pointer position in pixels
+    cursor: Option<(f64, f64)>,
+}
+
+// the curve compiled against the current variables; expressions the compiler
+// does not handle, such as quantities, go through the evaluator
+fn sampler<'a>(curve: &'a Curve, env: &'a Env) -> impl Fn(f64) -> Option<f64> + 'a {
+    let program = compile(&curve.tree, env, &["x"]).ok();
+    move |x| {
+        let y = match &program {
+            Some(program) => program.eval(&[x]),
+            None => calculate_at(&curve.tree, env, "x", x).ok()?,
+        };
+        Some(y).filter(|y| y.is_finite())
+    }
+}
+
+// 1, 2 or 5 times a power of ten, so that `range` holds about `count` steps
+fn tick_step(range: f64, count: f64) -> f64 {
+    let raw = range / count.max(1.0);
+    let power = 10f64.powf(raw.log10().floor());
+    match raw / power {
+        r if r <= 1.0 => power,
+        r if r <= 2.0 => 2.0 * power,
+        r if r <= 5.0 => 5.0 * power,
+        _ => 10.0 * power,
+    }
+}
+
+fn format_tick(value: f64, step: f64) -> String {
+    let decimals = (-step.log10().floor()).max(0.0) as usize;
+    let text = format!("{value:.decimals$}");
+    // no "-0" next to the axis
+    if text.trim_start_matches(['-', '0', '.']).is_empty() {
+        String::from("0")
+    } else {
+        text
+    }
+}
+
+fn format_coord(value: f64, range: f64) -> String {
+    format_tick(value, range / 1000.0)
+}
+
+fn draw_axes(cr: &cairo::Context, view: &View, width: f64, height: f64) -> Result<(), cairo::Error> {
+    cr.set_font_size(11.0);
+    let (origin_x, origin_y) = view.screen_point(0.0, 0.0, width, height);
+    // labels stay visible when an axis is scrolled out of sight
+    let label_x = origin_x.clamp(2.0, width - 40.0);
+    let label_y = origin_y.clamp(12.0, height - 4.0);
+
+    let x_step = tick_step(view.x_max - view.x_min, width / TICK_SPACING);
+    let x_start = (view.x_min / x_step).ceil() * x_step;
+    for x in (0..MAX_TICKS).map(|i| x_start + i as f64 * x_step).take_while(|x| *x <= view.x_max) {
+        let (px, _) = view.screen_point(x, 0.0, width, height);
+        cr.set_source_rgb(0.88, 0.88, 0.88);
+        cr.move_to(px, 0.0);
+        cr.line_to(px, height);
+        cr.stroke()?;
+        cr.set_source_rgb(0.3, 0.3, 0.3);
+        cr.move_to(px + 2.0, label_y - 2.0);
+        cr.show_text(&format_tick(x, x_step))?;
+    }
+
+    let y_step = tick_step(view.y_max - view.y_min, height / TICK_SPACING);
+    let y_start = (view.y_min / y_step).ceil() * y_step;
+    for y in (0..MAX_TICKS).map(|i| y_start + i as f64 * y_step).take_while(|y| *y <= view.y_max) {
+        let (_, py) = view.screen_point(0.0, y, width, height);
+        cr.set_source_rgb(0.88, 0.88, 0.88);
+        cr.move_to(0.0, py);
+        cr.line_to(width, py);
+        cr.stroke()?;
+        if y.abs() > y_step / 2.0 {
+            cr.set_source_rgb(0.3, 0.3, 0.3);
+            cr.move_to(label_x + 2.0, py - 2.0);
+            cr.show_text(&format_tick(y, y_step))?;
+        }
+    }
+
+    cr.set_source_rgb(0.0, 0.0, 0.0);
+    cr.move_to(origin_x, 0.0);
+    cr.line_to(origin_x, height);
+    cr.move_to(0.0, origin_y);
+    cr.line_to(width, origin_y);
+    cr.stroke()?;
+    cr.move_to(width - 10.0, label_y - 14.0);
+    cr.show_text("x")?;
+    cr.move_to(label_x + 14.0, 12.0);
+    cr.show_text("y")?;
+    Ok(())
+}
+
+fn draw_curve(
+    cr: &cairo::Context,
+    curve: &Curve,
+    env: &Env,
+    view: &View,
+    width: f64,
+    height: f64,
+) -> Result<(), cairo::Error> {
+    // one sample per pixel; the line breaks where the value is undefined or
+    // jumps by more than the height of the plot, as `tan(x)` does
+    let sample = sampler(curve, env);
+    let mut last: Option<f64> = None;
+    for px in 0..=width as i32 {
+        let (x, _) = view.plot_point(px as f64, 0.0, width, height);
+        let py = match sample(x) {
+            Some(y) => view.screen_point(x, y, width, height).1,
+            None => {
+                last = None;
+                continue;
+            }
+        };
+        match last {
+            Some(last_py) if (py - last_py).abs() < height => cr.line_to(px as f64, py),
+            _ => cr.move_to(px as f64, py),
+        }
+        last = Some(py);
+    }
+    cr.stroke()
+}
+
+fn draw(cr: &cairo::Context, state: &PlotState, env: &Env, width: f64, height: f64) -> Result<(), cairo::Error> {
+    cr.set_source_rgb(1.0, 1.0, 1.0);
+    cr.paint()?;
+    draw_axes(cr, &state.view, width, height)?;
+
+    cr.set_line_width(2.0);
+    for (i, curve) in state.curves.iter().enumerate() {
+        let (r, g, b) = COLORS[i % COLORS.len()];
+        cr.set_source_rgb(r, g, b);
+        draw_curve(cr, curve, env, &state.view, width, height)?;
+    }
+
+    if let Some((px, py)) = state.cursor {
+        cr.set_line_width(1.0);
+        cr.set_source_rgba(0.0, 0.0, 0.0, 0.5);
+        cr.set_dash(&[4.0, 4.0], 0.0);
+        cr.move_to(px, 0.0);
+        cr.line_to(px, height);
+        cr.move_to(0.0, py);
+        cr.line_to(width, py);
+        cr.stroke()?;
+        cr.set_dash(&[], 0.0);
+    }
+    Ok(())
+}
+
+// the pointer coordinates and the value of every curve at that `x`
+fn readout(state: &PlotState, env: &Env, width: f64, height: f64) -> String {
+    let (px, py) = match state.cursor {
+        Some(cursor) => cursor,
+        None => return String::new(),
+    };
+    let view = &state.view;
+    let (x, y) = view.plot_point(px, py, width, height);
+    let x_range = view.x_max - view.x_min;
+    let y_range = view.y_max - view.y_min;
+
+    let mut parts = vec![format!("x = {}, y = {}", format_coord(x, x_range), format_coord(y, y_range))];
+    for curve in &state.curves {
+        let value = match sampler(curve, env)(x) {
+            Some(value) => format_coord(value, y_range),
+            None => String::from("undefined"),
+        };
+        parts.push(format!("{} = {value}", curve.text));
+    }
+    parts.join("    ")
+}
+
+// a tab that graphs `;`-separated expressions in `x`; drag to pan, scroll to
+// zoom. The returned area has to be redrawn when variables or functions change
+pub fn build_plot(env: Rc<RefCell<Env>>) -> (Box, DrawingArea) {
+    let state = Rc::new(RefCell::new(PlotState {
+        curves: Vec::new(),
+        view: View::DEFAULT,
+        drag_start: View::DEFAULT,
+        cursor: None,
+    }));
+
+    let field_input = Entry::builder().placeholder_text("sin(x); x^2/4").hexpand(true).build();
+    let plot_btn = Button::builder().label("plot").build();
+    let reset_btn = Button::builder().label("reset view").build();
+    let area = DrawingArea::builder().content_width(500).content_height(350).vexpand(true).build();
+    let coords_text = Label::builder().label("").xalign(0.0).build();
+    let err_text = Label::builder().label("").xalign(0.0).build();
+
+    {
+        let state = Rc::clone(&state);
+        let env = Rc::clone(&env);
+        area.set_draw_func(move |_, cr, width, height| {
+            let res = draw(cr, &state.borrow(), &env.borrow(), width as f64, height as f64);
+            if let Err(err) = res {
+                eprintln!("plot: {err}");
+            }
+        });
+    }
+
+    let update_coords = {
+        let state = Rc::clone(&state);
+        let env = Rc::clone(&env);
+        let area = area.clone();
+        let coords_text = coords_text.clone();
+        Rc::new(move || {
+            let (width, height) = (area.width() as f64, area.height() as f64);
+            coords_text.set_text(&readout(&state.borrow(), &env.borrow(), width, height));
+            area.queue_draw();
+        })
+    };
+
+    let replot = {
+        let state = Rc::clone(&state);
+        let field_input = field_input.clone();
+        let err_text = err_text.clone();
+        let update_coords = Rc::clone(&update_coords);
+        Rc::new(move || {
+            let mut curves = Vec::new();
+            let mut errors = Vec::new();
+            for text in split_curves(&field_input.text()).into_iter().map(str::trim).filter(|text| !text.is_empty()) {
+                match parse(text, &env.borrow()) {
+                    Ok(tree) => curves.push(Curve { text: text.to_string(), tree }),
+                    Err(err) => errors.push(format!("{text}: {err}")),
+                }
+            }
+            state.borrow_mut().curves = curves;
+            err_text.set_text(&errors.join("\n"));
+            update_coords();
+        })
+    };
+    {
+        let replot = Rc::clone(&replot);
+        plot_btn.connect_clicked(move |_| replot());
+    }
+    field_input.connect_activate(move |_| replot());
+
+    {
+        let state = Rc::clone(&state);
+        let update_coords = Rc::clone(&update_coords);
+        reset_btn.connect_clicked(move |_| {
+            state.borrow_mut().view = View::DEFAULT;
+            update_coords();
+        });
+    }
+
+    let drag = GestureDrag::new();
+    {
+        let state = Rc::clone(&state);
+        drag.connect_drag_begin(move |_, _, _| {
+            let mut state = state.borrow_mut();
+            state.drag_start = state.view;
+        });
+    }
+    {
+        let state = Rc::clone(&state);
+        let area = area.clone();
+        let update_coords = Rc::clone(&update_coords);
+        drag.connect_drag_update(move |_, dx, dy| {
+            {
+                let mut state = state.borrow_mut();
+                let start = state.drag_start;
+                let shift_x = dx / area.width() as f64 * (start.x_max - start.x_min);
+                let shift_y = dy / area.height() as f64 * (start.y_max - start.y_min);
+                state.view = View {
+                    x_min: start.x_min - shift_x,
+                    x_max: start.x_max - shift_x,
+                    y_min: start.y_min + shift_y,
+                    y_max: start.y_max + shift_y,
+                };
+            }
+            update_coords();
+        });
+    }
+    area.add_controller(drag);
+
+    let scroll = EventControllerScroll::new(EventControllerScrollFlags::VERTICAL);
+    {
+        let state = Rc::clone(&state);
+        let area = area.clone();
+        let update_coords = Rc::clone(&update_coords);
+        scroll.connect_scroll(move |_, _, dy| {
+            {
+                let mut state = state.borrow_mut();
+                let (width, height) = (area.width() as f64, area.height() as f64);
+                // zoom around the pointer, or the middle when it is unknown
+                let (px, py) = state.cursor.unwrap_or((width / 2.0, height / 2.0));
+                let (x, y) = state.view.plot_point(px, py, width, height);
+                let factor = if dy > 0.0 { ZOOM_STEP } else { 1.0 / ZOOM_STEP };
+                state.view.zoom(factor, x, y);
+            }
+            update_coords();
+            glib::Propagation::Stop
+        });
+    }
+    area.add_controller(scroll);
+
+    let motion = EventControllerMotion::new();
+    {
+        let state = Rc::clone(&state);
+        let update_coords = Rc::clone(&update_coords);
+        motion.connect_motion(move |_, px, py| {
+            state.borrow_mut().cursor = Some((px, py));
+            update_coords();
+        });
+    }
+    {
+        let state = Rc::clone(&state);
+        motion.connect_leave(move |_| {
+            state.borrow_mut().cursor = None;
+            update_coords();
+        });
+    }
+    area.add_controller(motion);
+
+    let controls = Box::new(Orientation::Horizontal, 5);
+    controls.append(&field_input);
+    controls.append(&plot_btn);
+    controls.append(&reset_btn);
+
+    let vbox = Box::new(Orientation::Vertical, 5);
+    vbox.append(&controls);
+    vbox.append(&area);
+    vbox.append(&coords_text);
+    vbox.append(&err_text);
+
+    (vbox, area)
+}
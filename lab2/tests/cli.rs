@@ -0,0 +1,85 @@
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static RUNS: AtomicUsize = AtomicUsize::new(0);
+
+// runs the CLI with a config dir of its own, holding `files`, which is
+// removed afterwards so that tests running side by side do not see each
+// other's config
+fn run_with(files: &[(&str, &str)], args: &[&str]) -> (bool, String, String) {
+    let run = RUNS.fetch_add(1, Ordering::Relaxed);
+    let config = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(format!("config-{}-{run}", std::process::id()));
+    fs::create_dir_all(config.join("lab2")).unwrap();
+    for (name, text) in files {
+        fs::write(config.join("lab2").join(name), text).unwrap();
+    }
+    let output = Command::new(env!("CARGO_BIN_EXE_lab2-cli")).args(args).env("XDG_CONFIG_HOME", &config).output();
+    fs::remove_dir_all(&config).unwrap();
+    let output = output.unwrap();
+    (
+        output.status.success(),
+        String::from_utf8(output.stdout).unwrap(),
+        String::from_utf8(output.stderr).unwrap(),
+    )
+}
+
+fn run(args: &[&str]) -> (bool, String, String) {
+    run_with(&[], args)
+}
+
+#[test]
+fn one_shot_expressions_share_state() {
+    let (ok, stdout, _) = run(&["-e", "x = 2^10", "-e", "f(n) = n + 1", "-e", "f(x)"]);
+    assert!(ok);
+    assert_eq!(stdout, "1024\nf(n) = n + 1\n1025\n");
+}
+
+#[test]
+fn mode_flag_selects_the_number_mode() {
+    let (ok, stdout, _) = run(&["-m", "exact", "-e", "1/3 + 1/6"]);
+    assert!(ok);
+    assert_eq!(stdout, "1/2\n");
+}
+
+#[test]
+fn errors_fail_the_command() {
+    let (ok, stdout, stderr) = run(&["-e", "1 +"]);
+    assert!(!ok);
+    assert!(stdout.is_empty());
+    assert!(stderr.contains("RPN Error"), "{stderr}");
+
+    let (ok, _, stderr) = run(&["-m", "hex"]);
+    assert!(!ok);
+    assert!(stderr.contains("unknown mode"), "{stderr}");
+}
+
+#[test]
+fn constants_are_read_from_the_config() {
+    let (ok, stdout, _) = run_with(&[("constants.conf", "answer = 6 * 7\n")], &["-e", "answer + 1_000 + .5e1"]);
+    assert!(ok);
+    assert_eq!(stdout, "1047\n");
+}
+
+#[test]
+fn matrices_print_as_a_grid() {
+    let (ok, stdout, _) = run(&["-m", "exact", "-e", "inv([[4, 7], [2, 6]])", "-e", "[1, 2] * 3"]);
+    assert!(ok);
+    assert_eq!(stdout, "[  3/5  -7/10 ]\n[ -1/5    2/5 ]\n[3, 6]\n");
+}
+
+#[test]
+fn format_flag_sets_the_notation_and_locale() {
+    let (ok, stdout, _) = run(&["-f", "fixed:2,group", "-e", "1234.5", "-e", "0.1 + 0.2"]);
+    assert!(ok);
+    assert_eq!(stdout, "1,234.50\n0.30\n");
+
+    let (ok, stdout, _) = run(&["-f", "eng:3,comma", "-e", "max(1,5; 12345,6)", "-e", "[0,5; 1]"]);
+    assert!(ok);
+    assert_eq!(stdout, "12,3e3\n[500e-3; 1,00e0]\n");
+
+    let (ok, _, stderr) = run(&["-f", "fixed:99"]);
+    assert!(!ok);
+    assert!(stderr.contains("invalid number of digits"), "{stderr}");
+}
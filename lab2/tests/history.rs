@@ -0,0 +1,84 @@
+use std::fs;
+use std::path::PathBuf;
+
+use lab2::history::{Entry, History, MAX_ENTRIES};
+
+fn history_path(name: &str) -> PathBuf {
+    let path = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("history").join(name);
+    let _ = fs::remove_file(&path);
+    path
+}
+
+fn entry(expr: &str, result: &str) -> Entry {
+    Entry { expr: expr.to_string(), result: result.to_string(), index: None, grid: None }
+}
+
+#[test]
+fn history_survives_a_restart() {
+    let path = history_path("restart");
+    let mut history = History::load(&path).unwrap();
+    assert!(history.entries().is_empty());
+    history.push(entry("2 + 2", "4")).unwrap();
+    history.push(entry("f(x) = x\t+ 1", "")).unwrap();
+    history.push(entry("a \\ b", "line\nbreak")).unwrap();
+
+    history.push(Entry { index: Some(1), ..entry("ans", "4") }).unwrap();
+    assert_eq!(history.entries()[3].text(), "$1: ans = 4");
+
+    // result numbers belong to the session that computed them
+    let restored = History::load(&path).unwrap();
+    assert_eq!(restored.entries()[..3], history.entries()[..3]);
+    assert_eq!(restored.entries()[3], entry("ans", "4"));
+    assert_eq!(restored.entries()[0].text(), "2 + 2 = 4");
+    assert_eq!(restored.entries()[1].text(), "f(x) = x\t+ 1");
+
+    for i in 0..MAX_ENTRIES {
+        history.push(entry(&i.to_string(), "")).unwrap();
+    }
+    assert_eq!(History::load(&path).unwrap().entries().len(), MAX_ENTRIES);
+    assert_eq!(history.entries()[0].expr, "0");
+}
+
+#[test]
+fn history_is_searchable_and_exportable() {
+    let mut history = History::default();
+    history.push(entry("sin(pi)", "0")).unwrap();
+    history.push(entry("max(1, 2)", "2")).unwrap();
+    history.push(entry("say \"a|b\"", "")).unwrap();
+
+    let found: Vec<&str> =
+        history.entries().iter().filter(|e| e.matches("SIN")).map(|e| e.expr.as_str()).collect();
+    assert_eq!(found, ["sin(pi)"]);
+    assert!(history.entries()[1].matches("2"));
+
+    assert_eq!(
+        history.to_csv(),
+        "expression,result\nsin(pi),0\n\"max(1, 2)\",2\n\"say \"\"a|b\"\"\",\n"
+    );
+    assert_eq!(
+        history.to_markdown(),
+        "| Expression | Result |\n| --- | --- |\n| sin(pi) | 0 |\n| max(1, 2) | 2 |\n| say \"a\\|b\" |  |\n"
+    );
+}
+
+#[test]
+fn matrix_results_keep_their_grid() {
+    let path = history_path("matrix");
+    let cells = |rows: &[&[&str]]| rows.iter().map(|row| row.iter().map(|cell| cell.to_string()).collect()).collect();
+    let grid: Vec<Vec<String>> = cells(&[&["2", "1,000.5"], &["-6", "1/2"]]);
+    let matrix = Entry { index: Some(2), grid: Some(grid.clone()), ..entry("m * 2", "[[2, 1,000.5], [-6, 1/2]]") };
+    assert_eq!(matrix.heading(), "$2: m * 2");
+
+    let mut history = History::load(&path).unwrap();
+    history.push(matrix).unwrap();
+    history.push(entry("v", "[1, 2]")).unwrap();
+    let restored = History::load(&path).unwrap();
+    assert_eq!(restored.entries()[0].grid, Some(grid));
+    assert_eq!(restored.entries()[1].grid, None);
+
+    assert_eq!(history.to_csv(), "expression,result\nm * 2,2,\"1,000.5\"\n,-6,1/2\nv,\"[1, 2]\"\n");
+    assert_eq!(
+        history.to_markdown(),
+        "| Expression | Result |\n| --- | --- |\n| m * 2 | `2` `1,000.5`<br>`-6` `1/2` |\n| v | [1, 2] |\n"
+    );
+}